@@ -0,0 +1,586 @@
+//! Derive macro for the `DMO` trait of liberation's database module.
+//!
+//! ```ignore
+//! #[derive(DMO)]
+//! #[dmo(table = "books", id = "book_id")]
+//! pub struct Book {
+//!     pub id: Option<BookId>,
+//!     #[dmo(column = "title_by_id")]
+//!     pub title: TitleId,
+//!     pub owner_type: EntityType,
+//!     #[dmo(entity_type = "owner_type", member = "owner_member_by_id", guild = "owner_guild_by_id")]
+//!     pub owner: EntityId,
+//!     #[dmo(varchar)]
+//!     pub quality: String,
+//!     pub external_inventory_id: ExternalInventoryId,
+//! }
+//! ```
+//!
+//! Struct attributes:
+//! * `table`: name of the table
+//! * `id`: column of the primary key, stored in the field `id: Option<_>`
+//!
+//! Field attributes:
+//! * `column`: column of the field, defaults to the field name
+//! * `varchar`: refuses strings longer than 255 characters on insert and update
+//! * `date`: refuses dates outside of the years 1000 to 9999 on insert and update
+//! * `entity_type`, `member`, `guild`: the field holds the id of a member or guild,
+//!   stored in one of two columns depending on the `EntityType` field named by `entity_type`.
+//!   That field is read from its generated column and never written.
+//!
+//! The generated code accesses the private backend of the `Database`,
+//! so the derive may only be used within the database module.
+//! Rows are read as tuples, which limits an entity to 12 columns.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Fields, GenericArgument, Ident, Lit, Meta,
+    NestedMeta, PathArguments, Type,
+};
+
+#[proc_macro_derive(DMO, attributes(dmo))]
+pub fn derive_dmo(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match Entity::parse(&input) {
+        Ok(entity) => entity.expand().into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// How a field is stored
+enum Kind {
+    /// Primary key
+    Id,
+    /// Single column
+    Plain(String),
+    /// Id of a member or guild in one of two columns
+    Entity {
+        type_field: Ident,
+        member: String,
+        guild: String,
+    },
+    /// Type of a polymorphic id, read from a generated column
+    EntityType(String),
+}
+
+struct Field {
+    ident: Ident,
+    ty: Type,
+    kind: Kind,
+    varchar: bool,
+    date: bool,
+}
+
+struct Entity {
+    ident: Ident,
+    table: String,
+    id_column: String,
+    id_type: Type,
+    fields: Vec<Field>,
+}
+
+/// Items of all `#[dmo(...)]` attributes
+fn dmo_items(attrs: &[Attribute]) -> syn::Result<Vec<NestedMeta>> {
+    let mut items = vec![];
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("dmo")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => items.extend(list.nested),
+            meta => return Err(syn::Error::new_spanned(meta, "expected #[dmo(...)]")),
+        }
+    }
+    Ok(items)
+}
+
+/// Name and value of `name = "value"`, or only the name of a flag
+fn name_and_value(item: &NestedMeta) -> syn::Result<(String, Option<String>)> {
+    match item {
+        NestedMeta::Meta(Meta::Path(path)) if path.get_ident().is_some() => {
+            Ok((path.get_ident().unwrap().to_string(), None))
+        }
+        NestedMeta::Meta(Meta::NameValue(pair)) if pair.path.get_ident().is_some() => {
+            match pair.lit {
+                Lit::Str(ref value) => Ok((
+                    pair.path.get_ident().unwrap().to_string(),
+                    Some(value.value()),
+                )),
+                ref lit => Err(syn::Error::new_spanned(lit, "expected a string")),
+            }
+        }
+        item => Err(syn::Error::new_spanned(
+            item,
+            "expected `name` or `name = \"value\"`",
+        )),
+    }
+}
+
+/// Type wrapped by `Option`, None for other types
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let segment = match ty {
+        Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != "Option" {
+        return None;
+    }
+    match segment.arguments {
+        PathArguments::AngleBracketed(ref args) => match args.args.first()? {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl Entity {
+    fn parse(input: &DeriveInput) -> syn::Result<Entity> {
+        let mut table = None;
+        let mut id_column = None;
+        for item in dmo_items(&input.attrs)? {
+            match name_and_value(&item)? {
+                (ref name, Some(value)) if name == "table" => table = Some(value),
+                (ref name, Some(value)) if name == "id" => id_column = Some(value),
+                _ => return Err(syn::Error::new_spanned(item, "expected `table` or `id`")),
+            }
+        }
+        let span = Span::call_site();
+        let table =
+            table.ok_or_else(|| syn::Error::new(span, "missing #[dmo(table = \"...\")]"))?;
+        let id_column =
+            id_column.ok_or_else(|| syn::Error::new(span, "missing #[dmo(id = \"...\")]"))?;
+
+        let named = match input.data {
+            Data::Struct(ref data) => match data.fields {
+                Fields::Named(ref fields) => &fields.named,
+                _ => {
+                    return Err(syn::Error::new(
+                        span,
+                        "DMO needs a struct with named fields",
+                    ))
+                }
+            },
+            _ => return Err(syn::Error::new(span, "DMO can only be derived for structs")),
+        };
+
+        let mut fields = vec![];
+        let mut id_type = None;
+        for field in named.iter() {
+            let ident = field.ident.clone().expect("named fields have an ident");
+            let mut column = None;
+            let mut type_field = None;
+            let mut member = None;
+            let mut guild = None;
+            let mut varchar = false;
+            let mut date = false;
+            for item in dmo_items(&field.attrs)? {
+                match name_and_value(&item)? {
+                    (ref name, Some(value)) if name == "column" => column = Some(value),
+                    (ref name, Some(value)) if name == "entity_type" => {
+                        type_field = Some(Ident::new(&value, Span::call_site()))
+                    }
+                    (ref name, Some(value)) if name == "member" => member = Some(value),
+                    (ref name, Some(value)) if name == "guild" => guild = Some(value),
+                    (ref name, None) if name == "varchar" => varchar = true,
+                    (ref name, None) if name == "date" => date = true,
+                    _ => return Err(syn::Error::new_spanned(item, "unknown dmo attribute")),
+                }
+            }
+
+            let kind = match (type_field, member, guild) {
+                (Some(type_field), Some(member), Some(guild)) => Kind::Entity {
+                    type_field,
+                    member,
+                    guild,
+                },
+                (None, None, None) if ident == "id" => {
+                    id_type = Some(
+                        option_inner(&field.ty)
+                            .ok_or_else(|| syn::Error::new_spanned(&field.ty, "expected Option"))?
+                            .clone(),
+                    );
+                    Kind::Id
+                }
+                (None, None, None) => Kind::Plain(column.unwrap_or_else(|| ident.to_string())),
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        "`entity_type`, `member` and `guild` must be given together",
+                    ))
+                }
+            };
+            fields.push(Field {
+                ident,
+                ty: field.ty.clone(),
+                kind,
+                varchar,
+                date,
+            });
+        }
+
+        // Types of polymorphic ids are read from their generated column only
+        let type_fields: Vec<Ident> = fields
+            .iter()
+            .filter_map(|field| match field.kind {
+                Kind::Entity { ref type_field, .. } => Some(type_field.clone()),
+                _ => None,
+            })
+            .collect();
+        for type_field in type_fields {
+            match fields.iter_mut().find(|field| field.ident == type_field) {
+                Some(field) => {
+                    let column = match field.kind {
+                        Kind::Plain(ref column) => column.clone(),
+                        _ => {
+                            return Err(syn::Error::new_spanned(&field.ident, "expected a column"))
+                        }
+                    };
+                    field.kind = Kind::EntityType(column);
+                }
+                None => return Err(syn::Error::new_spanned(type_field, "no such field")),
+            }
+        }
+
+        Ok(Entity {
+            ident: input.ident.clone(),
+            table,
+            id_column,
+            id_type: id_type.ok_or_else(|| syn::Error::new(span, "missing field `id`"))?,
+            fields,
+        })
+    }
+
+    /// Columns in the order they are selected
+    fn select_columns(&self) -> Vec<&str> {
+        let mut columns = vec![];
+        for field in &self.fields {
+            match field.kind {
+                Kind::Id => columns.push(self.id_column.as_str()),
+                Kind::Plain(ref column) | Kind::EntityType(ref column) => columns.push(column),
+                Kind::Entity {
+                    ref member,
+                    ref guild,
+                    ..
+                } => {
+                    columns.push(member);
+                    columns.push(guild);
+                }
+            }
+        }
+        columns
+    }
+
+    /// Columns written on insert and update
+    fn write_columns(&self) -> Vec<&str> {
+        let mut columns = vec![];
+        for field in &self.fields {
+            match field.kind {
+                Kind::Id | Kind::EntityType(_) => (),
+                Kind::Plain(ref column) => columns.push(column.as_str()),
+                Kind::Entity {
+                    ref member,
+                    ref guild,
+                    ..
+                } => {
+                    columns.push(member);
+                    columns.push(guild);
+                }
+            }
+        }
+        columns
+    }
+
+    fn select_sql(&self) -> String {
+        format!(
+            "select {} from {}",
+            self.select_columns().join(", "),
+            self.table
+        )
+    }
+
+    fn insert_sql(&self) -> String {
+        let columns = self.write_columns();
+        let values: Vec<String> = columns
+            .iter()
+            .map(|column| format!(":{}", column))
+            .collect();
+        format!(
+            "insert into {} ({}) values ({})",
+            self.table,
+            columns.join(", "),
+            values.join(", ")
+        )
+    }
+
+    fn update_sql(&self) -> String {
+        let assignments: Vec<String> = self
+            .write_columns()
+            .iter()
+            .map(|column| format!("{}=:{}", column, column))
+            .collect();
+        format!(
+            "update {} set {} where {}=:id;",
+            self.table,
+            assignments.join(", "),
+            self.id_column
+        )
+    }
+
+    fn delete_sql(&self) -> String {
+        format!("delete from {} where {}=:id", self.table, self.id_column)
+    }
+
+    /// Closure converting a row into the entity
+    fn convert(&self) -> TokenStream2 {
+        let ident = &self.ident;
+        let mut bindings = vec![];
+        let mut types = vec![];
+        let mut resolved = vec![];
+        let mut names = vec![];
+        for field in &self.fields {
+            let name = &field.ident;
+            let ty = &field.ty;
+            names.push(name);
+            match field.kind {
+                Kind::Id | Kind::Plain(_) | Kind::EntityType(_) => {
+                    bindings.push(quote!(#name));
+                    types.push(quote!(#ty));
+                }
+                Kind::Entity {
+                    ref type_field,
+                    ref member,
+                    ref guild,
+                } => {
+                    let member_id = Ident::new(&format!("{}_member", name), Span::call_site());
+                    let guild_id = Ident::new(&format!("{}_guild", name), Span::call_site());
+                    let message = format!(
+                        "Field '{}' or '{}' is not set according to '{}'.",
+                        member, guild, type_field
+                    );
+                    bindings.push(quote!(#member_id));
+                    bindings.push(quote!(#guild_id));
+                    types.push(quote!(Option<#ty>));
+                    types.push(quote!(Option<#ty>));
+                    resolved.push(quote! {
+                        let #name = match #type_field {
+                            crate::database::EntityType::Member => #member_id,
+                            crate::database::EntityType::Guild => #guild_id,
+                        }
+                        .unwrap_or_else(|| panic!(#message));
+                    });
+                }
+            }
+        }
+        quote! {
+            |row: crate::database::backend::Row| -> #ident {
+                let (#(#bindings,)*): (#(#types,)*) = crate::database::backend::from_row(row);
+                #(#resolved)*
+                #ident {
+                    #(#names,)*
+                }
+            }
+        }
+    }
+
+    /// Checks of varchar lengths and dates of `dmo`
+    fn checks(&self) -> TokenStream2 {
+        let entity = self.ident.to_string().to_lowercase();
+        let mut checks = vec![];
+        for field in &self.fields {
+            let name = &field.ident;
+            let label = format!("{}.{}", entity, name);
+            let optional = option_inner(&field.ty).is_some();
+            if field.varchar {
+                let check = quote! {
+                    if #name.chars().count() > 255 {
+                        return Err(crate::error::Error::DataTooLong(String::from(#label)));
+                    }
+                };
+                checks.push(match optional {
+                    true => quote!(if let Some(ref #name) = dmo.#name { #check }),
+                    false => quote!({ let #name = &dmo.#name; #check }),
+                });
+            }
+            if field.date {
+                let check = quote! {
+                    let year = ::chrono::Datelike::year(&#name);
+                    if !(1000..=9999).contains(&year) {
+                        return Err(crate::error::Error::IllegalValueForType(String::from(#label)));
+                    }
+                };
+                checks.push(match optional {
+                    true => quote!(if let Some(#name) = dmo.#name { #check }),
+                    false => quote!({ let #name = dmo.#name; #check }),
+                });
+            }
+        }
+        quote!(#(#checks)*)
+    }
+
+    /// Parameters of insert and update, named like their columns
+    fn params(&self) -> TokenStream2 {
+        let mut params = vec![];
+        for field in &self.fields {
+            let name = &field.ident;
+            match field.kind {
+                Kind::Id | Kind::EntityType(_) => (),
+                Kind::Plain(ref column) => params.push(quote! {
+                    (#column, crate::database::backend::Value::from(Clone::clone(&dmo.#name)))
+                }),
+                Kind::Entity {
+                    ref type_field,
+                    ref member,
+                    ref guild,
+                } => {
+                    params.push(quote! {
+                        (#member, crate::database::backend::Value::from(match dmo.#type_field {
+                            crate::database::EntityType::Member => Some(Clone::clone(&dmo.#name)),
+                            crate::database::EntityType::Guild => None,
+                        }))
+                    });
+                    params.push(quote! {
+                        (#guild, crate::database::backend::Value::from(match dmo.#type_field {
+                            crate::database::EntityType::Member => None,
+                            crate::database::EntityType::Guild => Some(Clone::clone(&dmo.#name)),
+                        }))
+                    });
+                }
+            }
+        }
+        quote!(#(#params,)*)
+    }
+
+    fn expand(&self) -> TokenStream2 {
+        let ident = &self.ident;
+        let id_type = &self.id_type;
+        let select_all = format!("{};", self.select_sql());
+        let select_one = format!("{} where {}=:id;", self.select_sql(), self.id_column);
+        let insert = self.insert_sql();
+        let update = self.update_sql();
+        let delete = self.delete_sql();
+        let convert = self.convert();
+        let checks = self.checks();
+        let params = self.params();
+
+        quote! {
+            impl crate::database::DMO for #ident {
+                type Id = #id_type;
+
+                fn get_all(
+                    db: &crate::database::Database,
+                ) -> Result<Vec<#ident>, crate::error::Error> {
+                    Ok(db.backend.query(#select_all, vec![])?.into_iter().map(#convert).collect())
+                }
+
+                fn get(
+                    db: &crate::database::Database,
+                    id: #id_type,
+                ) -> Result<Option<#ident>, crate::error::Error> {
+                    Ok(db
+                        .backend
+                        .query(#select_one, vec![("id", crate::database::backend::Value::from(id))])?
+                        .into_iter()
+                        .map(#convert)
+                        .next())
+                }
+
+                fn insert(
+                    db: &crate::database::Database,
+                    dmo: &#ident,
+                ) -> Result<crate::database::Id, crate::error::Error> {
+                    #checks
+                    db.backend
+                        .execute(#insert, vec![#params])
+                        .map(|changes| changes.last_insert_id)
+                }
+
+                fn update(
+                    db: &crate::database::Database,
+                    dmo: &#ident,
+                ) -> Result<(), crate::error::Error> {
+                    #checks
+                    db.backend
+                        .execute(
+                            #update,
+                            vec![
+                                #params
+                                ("id", crate::database::backend::Value::from(dmo.id)),
+                            ],
+                        )
+                        .map(|_| ())
+                }
+
+                fn delete(
+                    db: &crate::database::Database,
+                    id: #id_type,
+                ) -> Result<bool, crate::error::Error> {
+                    db.backend
+                        .execute(#delete, vec![("id", crate::database::backend::Value::from(id))])
+                        .and_then(|changes| match changes.affected_rows {
+                            1 => Ok(true),
+                            0 => Ok(false),
+                            _ => Err(crate::error::Error::IllegalState),
+                        })
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn book() -> Entity {
+        let input: DeriveInput = parse_quote! {
+            #[dmo(table = "books", id = "book_id")]
+            pub struct Book {
+                pub id: Option<BookId>,
+                #[dmo(column = "title_by_id")]
+                pub title: TitleId,
+                pub owner_type: EntityType,
+                #[dmo(entity_type = "owner_type", member = "owner_member_by_id", guild = "owner_guild_by_id")]
+                pub owner: EntityId,
+                #[dmo(varchar)]
+                pub quality: String,
+                pub external_inventory_id: ExternalInventoryId,
+            }
+        };
+        Entity::parse(&input).unwrap()
+    }
+
+    #[test]
+    fn select_polymorphic_owner_and_its_type() {
+        assert_eq!(
+            book().select_sql(),
+            "select book_id, title_by_id, owner_type, owner_member_by_id, owner_guild_by_id, quality, external_inventory_id from books"
+        );
+    }
+
+    #[test]
+    fn write_neither_id_nor_generated_type() {
+        let book = book();
+        assert_eq!(
+            book.insert_sql(),
+            "insert into books (title_by_id, owner_member_by_id, owner_guild_by_id, quality, external_inventory_id) values (:title_by_id, :owner_member_by_id, :owner_guild_by_id, :quality, :external_inventory_id)"
+        );
+        assert_eq!(
+            book.update_sql(),
+            "update books set title_by_id=:title_by_id, owner_member_by_id=:owner_member_by_id, owner_guild_by_id=:owner_guild_by_id, quality=:quality, external_inventory_id=:external_inventory_id where book_id=:id;"
+        );
+    }
+
+    #[test]
+    fn refuse_entity_without_table() {
+        let input: DeriveInput = parse_quote! {
+            #[dmo(id = "book_id")]
+            pub struct Book {
+                pub id: Option<BookId>,
+            }
+        };
+        assert!(Entity::parse(&input).is_err());
+    }
+}
@@ -1,4 +1,6 @@
-insert into members (member_id, external_id) values (999, 'REPLACE HERE');
+-- Members are created on their first login. This placeholder is only the initial
+-- contact of the guild, an admin can hand the guild over to a real member afterwards.
+insert into members (member_id, external_id) values (999, 'tiki-migration');
 
 insert into guilds (guild_id, name, address, contact_by_member_id) values (1, 'RPG Librarium Aachen', 'Schurzelter Str. 469, 52074 Aachen', 999);
 
@@ -1,6 +1,5 @@
--- Cleanup for testing:
--- drop table if exists rentals, books, titles, rpg_systems, guilds, members;
--- DO NOT USE IN PRODUCTION!
+-- Tables of the first release. Installations created before versioned migrations
+-- already have them, hence "if not exists".
 
 -- Entity (aka members and guilds) tables
 -- Have to be created created before books+rentals due to foreign keys
@@ -0,0 +1,4 @@
+-- Return date and extensions of rentals
+alter table rentals
+  add column if not exists return_date     date null,
+  add column if not exists extensions      int not null default 0;
@@ -0,0 +1,13 @@
+-- Reservations of titles, holding returned books for the next member
+create table if not exists reservations (
+  reservation_id        int auto_increment primary key,
+  title_by_id           int not null,
+  member_by_id          int not null,
+  reservation_date      date not null,
+  held_book_by_id       int null,
+  held_until            date null,
+  unique (title_by_id, member_by_id),
+  foreign key (title_by_id) references titles (title_id),
+  foreign key (member_by_id) references members (member_id),
+  foreign key (held_book_by_id) references books (book_id)
+) character set utf8mb4 collate utf8mb4_general_ci;
@@ -0,0 +1,10 @@
+-- Personal api tokens of members, only the hash of the secret is stored
+create table if not exists api_tokens (
+  token_id              int auto_increment primary key,
+  member_by_id          int not null,
+  name                  varchar(255) not null,
+  token_hash            char(64) not null unique,
+  roles                 varchar(255) not null,
+  created_date          date not null,
+  foreign key (member_by_id) references members (member_id)
+) character set utf8mb4 collate utf8mb4_general_ci;
@@ -0,0 +1,4 @@
+-- Day after which an api token is rejected, existing tokens expire a year after their creation
+alter table api_tokens add column if not exists expires_date date null;
+update api_tokens set expires_date = date_add(created_date, interval 1 year) where expires_date is null;
+alter table api_tokens modify expires_date date not null;
@@ -0,0 +1,66 @@
+-- Tables of the first release, see the MySQL migration of the same version.
+-- Generated columns need SQLite 3.31 or newer.
+
+-- Entity (aka members and guilds) tables
+-- Have to be created before books+rentals due to foreign keys
+create table if not exists members (
+  member_id                   integer primary key autoincrement,
+  external_id                 varchar(255) not null unique
+);
+
+create table if not exists guilds (
+  guild_id                    integer primary key autoincrement,
+  name                        varchar(255) not null unique,
+  address                     text not null,
+  contact_by_member_id        integer not null,
+  foreign key (contact_by_member_id) references members (member_id)
+);
+
+-- "Book-related" tables
+
+create table if not exists rpg_systems (
+  rpg_system_id   integer primary key autoincrement,
+  name            varchar(255) unique not null,
+  shortname       varchar(255) unique
+);
+
+create table if not exists titles (
+  title_id          integer primary key autoincrement,
+  name              varchar(255) not null unique,
+  rpg_system_by_id  integer not null,
+  language          varchar(255) not null,
+  publisher         varchar(255) not null,
+  year              smallint not null,
+  coverimage        text null,
+  foreign key (rpg_system_by_id) references rpg_systems (rpg_system_id)
+);
+
+create table if not exists books (
+  book_id               integer primary key autoincrement,
+  title_by_id           integer not null,
+  owner_member_by_id    integer null,
+  owner_guild_by_id     integer null,
+  owner_type            text
+      as (case when owner_guild_by_id is not null then 'guild' else 'member' end) stored,
+  quality               text not null,
+  external_inventory_id integer not null unique,
+  foreign key (title_by_id) references titles (title_id),
+  foreign key (owner_member_by_id) references members (member_id),
+  foreign key (owner_guild_by_id) references guilds (guild_id),
+  check ((owner_guild_by_id is not null) <> (owner_member_by_id is not null))
+);
+
+create table if not exists rentals (
+  rental_id             integer primary key autoincrement,
+  from_date             date not null,
+  to_date               date not null,
+  book_by_id            integer not null,
+  rentee_member_by_id   integer null,
+  rentee_guild_by_id    integer null,
+  rentee_type           text
+      as (case when rentee_guild_by_id is not null then 'guild' else 'member' end) stored,
+  foreign key (book_by_id) references books (book_id),
+  foreign key (rentee_member_by_id) references members (member_id),
+  foreign key (rentee_guild_by_id) references guilds (guild_id),
+  check ((rentee_guild_by_id is not null) <> (rentee_member_by_id is not null))
+);
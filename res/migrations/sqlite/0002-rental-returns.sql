@@ -0,0 +1,3 @@
+-- Return date and extensions of rentals
+alter table rentals add column return_date date null;
+alter table rentals add column extensions integer not null default 0;
@@ -0,0 +1,10 @@
+-- Personal api tokens of members, only the hash of the secret is stored
+create table if not exists api_tokens (
+  token_id              integer primary key autoincrement,
+  member_by_id          integer not null,
+  name                  varchar(255) not null,
+  token_hash            char(64) not null unique,
+  roles                 varchar(255) not null,
+  created_date          date not null,
+  foreign key (member_by_id) references members (member_id)
+);
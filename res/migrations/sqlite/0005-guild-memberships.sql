@@ -0,0 +1,11 @@
+-- Members of guilds with their role within the guild,
+-- role is one of 'member', 'librarian' and 'contact'
+create table if not exists guild_memberships (
+  membership_id         integer primary key autoincrement,
+  guild_by_id           integer not null,
+  member_by_id          integer not null,
+  role                  varchar(255) not null,
+  unique (guild_by_id, member_by_id),
+  foreign key (guild_by_id) references guilds (guild_id) on delete cascade,
+  foreign key (member_by_id) references members (member_id)
+);
@@ -0,0 +1,3 @@
+-- Day after which an api token is rejected, existing tokens expire a year after their creation
+alter table api_tokens add column expires_date date not null default '1970-01-01';
+update api_tokens set expires_date = date(created_date, '+1 year');
@@ -135,6 +135,66 @@ pub struct GetMember {
     pub member: MemberWithRoles,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub struct GetMe {
+    pub id: db::MemberId,
+    pub roles: Vec<String>,
+    pub inventory: InventorySummary,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub struct GetHealth {
+    pub keycloak: KeycloakHealth,
+}
+
+/// State of the Keycloak synchronisation, times are formatted as RFC 3339.
+/// Without Keycloak configured nothing is ever synchronised.
+#[derive(Serialize)]
+pub struct KeycloakHealth {
+    pub lastsuccess: Option<String>,
+    pub lasterror: Option<String>,
+    /// Only shown to admins
+    pub lasterrormessage: Option<String>,
+    pub failures: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub struct GetFeatures {
+    pub features: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub struct GetApiTokens {
+    pub tokens: Vec<db::ApiToken>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub struct PostApiToken {
+    pub token: PartialApiToken,
+}
+
+#[derive(Deserialize)]
+pub struct PartialApiToken {
+    pub name: String,
+    /// Defaults to all roles of the logged in user
+    pub roles: Option<Vec<String>>,
+    /// Last day the token is accepted, defaults to a year from today
+    #[serde(default, with = "serde_formats::optional_naive_date")]
+    pub expires: Option<db::Date>,
+}
+
+/// Newly created api token, the secret is only shown once
+#[derive(Serialize)]
+pub struct CreatedApiToken {
+    pub id: db::ApiTokenId,
+    pub secret: String,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "lowercase")]
 pub struct GetMemberInventory {
@@ -167,12 +227,61 @@ pub struct GetGuildInventory {
     pub inventory: Inventory,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub struct PutGuildInventory {
+    pub book: PartialBook,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub struct GetGuildMembers {
+    pub members: Vec<GuildMember>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub struct PutGuildMember {
+    pub membership: PartialGuildMembership,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub struct PutPostGuild {
     pub guild: db::Guild,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub struct GetRental {
+    pub rental: db::Rental,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub struct GetOverdueRentals {
+    pub rentals: Vec<OverdueRental>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub struct PostRentalExtension {
+    #[serde(with = "serde_formats::naive_date")]
+    pub to: db::Date,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub struct GetReservations {
+    pub reservations: Vec<db::Reservation>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub struct PostRental {
+    pub rental: PartialRental,
+}
+
 // ############### Inner DTOS ############################
 #[derive(Serialize)]
 pub struct RpgSystemWithTitles {
@@ -281,6 +390,14 @@ pub struct RentalWithRentee {
     pub rentee: Entity,
 }
 
+#[derive(Deserialize)]
+pub struct PartialRental {
+    pub book: db::BookId,
+    pub rentee: UnresolvedEntity,
+    #[serde(with = "serde_formats::naive_date")]
+    pub to: db::Date,
+}
+
 #[derive(Deserialize)]
 pub struct PartialTitle {
     pub name: String,
@@ -302,25 +419,62 @@ pub struct BookWithTitleWithOwnerWithRental {
     pub rental: Option<RentalWithRentee>,
 }
 
+#[derive(Serialize)]
+pub struct BookWithTitleWithOwner {
+    pub id: db::BookId,
+    pub title: TitleWithSystem,
+    pub owner: Entity,
+    pub quality: String,
+    pub external_inventory_id: db::ExternalInventoryId,
+}
+
+#[derive(Serialize)]
+pub struct OverdueRental {
+    pub id: db::RentalId,
+    #[serde(with = "serde_formats::naive_date")]
+    pub from: db::Date,
+    #[serde(with = "serde_formats::naive_date")]
+    pub to: db::Date,
+    pub daysoverdue: i64,
+    pub book: BookWithTitleWithOwner,
+    pub rentee: Entity,
+}
+
 #[derive(Deserialize)]
 pub struct PartialBook {
     pub title: db::TitleId,
     pub owner: UnresolvedEntity,
     pub quality: String,
+    /// Assigned automatically when left out
+    pub external_inventory_id: Option<db::ExternalInventoryId>,
 }
 
 #[derive(Serialize)]
 pub struct Inventory {
     pub ownedbookds: Vec<BookWithTitleWithOwnerWithRental>,
-    pub rentedbooks: Vec<BookWithOwnerWithRental>,
+    pub rentedbooks: Vec<BookWithTitleWithOwnerWithRental>,
+}
+
+/// Number of books owned and rented by a member
+#[derive(Serialize)]
+pub struct InventorySummary {
+    pub ownedbooks: usize,
+    pub rentedbooks: usize,
 }
 
+/// Member profile, contact details are only filled for librarians and admins
 #[derive(Serialize)]
 pub struct MemberWithRoles {
     pub id: db::MemberId,
     pub name: String,
-    pub email: String,
-    pub roles: Vec<db::Role>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<String>>,
 }
 
 #[derive(Serialize)]
@@ -338,6 +492,18 @@ pub struct GuildWithContact {
     pub contact: Member,
 }
 
+/// Member of a guild with their role within it
+#[derive(Serialize)]
+pub struct GuildMember {
+    pub member: Member,
+    pub role: db::GuildRole,
+}
+
+#[derive(Deserialize)]
+pub struct PartialGuildMembership {
+    pub role: db::GuildRole,
+}
+
 #[derive(Deserialize)]
 pub struct PartialGuild {
     pub name: String,
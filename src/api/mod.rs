@@ -1,6 +1,8 @@
 mod dto;
 
 pub use self::dto::*;
+// The Member DTO, not the Member of the database module
+pub use self::dto::Member;
 
 use actix_files as fs;
 
@@ -10,9 +12,11 @@ use actix_files as fs;
 
 use actix_web::{http, web, App, HttpMessage, HttpRequest, HttpResponse, Responder, Scope};
 use crate::auth::roles::*;
-use crate::auth::{assert_roles, Claims, KeycloakCache};
+use crate::auth::{assert_roles, Claims, JwtVerifier, KeycloakCache};
 use crate::business as bus;
 use crate::database::*;
+use crate::features::{Features, FEATURE_RESERVATIONS};
+use crate::settings::Lending;
 //use futures::future::Future;
 
 /// Handling of external modules
@@ -22,6 +26,12 @@ pub struct AppState {
     pub db: Database,
     /// Keycloak for authentification
     pub kc: KeycloakCache,
+    /// Verifies the access tokens of requests
+    pub verifier: JwtVerifier,
+    /// Rules for rentals and reservations
+    pub lending: Lending,
+    /// Feature flags
+    pub features: Features,
 }
 
 /// Getter for web folder
@@ -53,10 +63,22 @@ pub fn get_v1() -> Scope {
                         .route(web::post().to(post_rpg_system)),
                 )
                 .service(
-                    web::resource("/{titleid}")
-                        .route(web::get().to(get_title))
-                        .route(web::put().to(put_title))
-                        .route(web::delete().to(delete_title)),
+                    web::scope("/{titleid}")
+                        .service(
+                            web::resource("")
+                                .route(web::get().to(get_title))
+                                .route(web::put().to(put_title))
+                                .route(web::delete().to(delete_title)),
+                        )
+                        .service(
+                            web::resource("/reservations")
+                                .route(web::get().to(get_reservations))
+                                .route(web::post().to(post_reservation)),
+                        )
+                        .service(
+                            web::resource("/reservations/{reservationid}")
+                                .route(web::delete().to(delete_reservation)),
+                        ),
                 ),
         )
         .service(
@@ -85,21 +107,45 @@ pub fn get_v1() -> Scope {
                         .service(
                             web::resource("")
                                 .route(web::get().to(get_guild))
-                                .route(web::put().to(put_guild)),
+                                .route(web::put().to(put_guild))
+                                .route(web::delete().to(delete_guild)),
                         )
                         .service(
                             web::resource("/inventory")
                                 .route(web::get().to(get_guild_inventory))
                                 .route(web::post().to(post_guild_inventory)),
+                        )
+                        .service(
+                            web::resource("/members").route(web::get().to(get_guild_members)),
+                        )
+                        .service(
+                            web::resource("/members/{memberid}")
+                                .route(web::put().to(put_guild_member))
+                                .route(web::delete().to(delete_guild_member)),
                         ),
                 ),
         )
         .service(
-            web::scope("members")
+            web::scope("/rentals")
+                .service(web::resource("").route(web::post().to(post_rental)))
+                .service(web::resource("/overdue").route(web::get().to(get_overdue_rentals)))
+                .service(
+                    web::scope("/{rentalid}")
+                        .service(web::resource("").route(web::get().to(get_rental)))
+                        .service(
+                            web::resource("/return").route(web::post().to(post_rental_return)),
+                        )
+                        .service(
+                            web::resource("/extend").route(web::post().to(post_rental_extension)),
+                        ),
+                ),
+        )
+        .service(
+            web::scope("/members")
                 .service(web::resource("").route(web::get().to(get_members)))
                 .service(
                     web::scope("/{memberid}")
-                        .service(web::resource("/").route(web::get().to(get_member)))
+                        .service(web::resource("").route(web::get().to(get_member)))
                         .service(
                             web::resource("/inventory")
                                 .route(web::get().to(get_member_inventory))
@@ -107,6 +153,22 @@ pub fn get_v1() -> Scope {
                         ),
                 ),
         )
+        .service(
+            web::resource("/features").route(web::get().to(get_features)),
+        )
+        .service(web::resource("/health").route(web::get().to(get_health)))
+        .service(
+            web::scope("/me")
+                .service(web::resource("").route(web::get().to(get_me)))
+                .service(
+                    web::resource("/tokens")
+                        .route(web::get().to(get_api_tokens))
+                        .route(web::post().to(post_api_token)),
+                )
+                .service(
+                    web::resource("/tokens/{tokenid}").route(web::delete().to(delete_api_token)),
+                ),
+        )
 }
 
 // Responder<Item = Into<AsyncResult<HttpResponse>>, Error = Into<Error>>
@@ -116,9 +178,9 @@ pub fn get_v1() -> Scope {
 // https://actix.rs/actix-web/actix_web/trait.Responder.html
 
 fn get_rpg_systems(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
-    assert_roles(&_req, vec![])?;
+    let claims = assert_roles(&_req, vec![])?;
 
-    return bus::get_rpgsystems(&state.db).and_then(|systems| Ok(HttpResponse::Ok().json(systems)));
+    return bus::get_rpgsystems(&state.db, claims).and_then(|systems| Ok(HttpResponse::Ok().json(systems)));
     // This works because of reasons:
     // Response<Json<T>, Into<Error>> = impl Response
 }
@@ -173,9 +235,9 @@ fn delete_rpg_system(state: web::Data<AppState>, _req: HttpRequest) -> Result<Ht
 
 /// Get all Titles (if authentification is successful)
 fn get_titles(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
-    assert_roles(&_req, vec![])?;
+    let claims = assert_roles(&_req, vec![])?;
 
-    bus::get_titles(&state.db).and_then(|titles| Ok(HttpResponse::Ok().json(titles)))
+    bus::get_titles(&state.db, claims).and_then(|titles| Ok(HttpResponse::Ok().json(titles)))
 }
 
 /// Get a requested Title (if authentification is successful)
@@ -229,11 +291,68 @@ fn delete_title(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpRes
     bus::delete_title(&state.db, claims, id).and_then(|_| Ok(HttpResponse::NoContent().finish()))
 }
 
+/// Extend a Rental (if authentification is successful)
+fn post_rental_extension(
+    state: web::Data<AppState>,
+    json: web::Json<PostRentalExtension>,
+    _req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_LIBRARIAN, ROLE_MEMBER])?;
+
+    let id: RentalId = _req.match_info().query("rentalid").parse::<RentalId>()?;
+
+    bus::extend_rental(&state.db, &state.lending, claims, id, json.into_inner())
+        .and_then(|()| Ok(HttpResponse::Ok().finish()))
+}
+
+/// Get the reservation queue of a Title (if authentification is successful)
+fn get_reservations(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_LIBRARIAN, ROLE_MEMBER])?;
+    state.features.assert_enabled(FEATURE_RESERVATIONS, &claims)?;
+
+    let id: TitleId = _req.match_info().query("titleid").parse::<TitleId>()?;
+
+    bus::get_reservations(&state.db, &state.lending, claims, id)
+        .and_then(|reservations| Ok(HttpResponse::Ok().json(reservations)))
+}
+
+/// Reserve a Title for the logged in Member (if authentification is successful)
+fn post_reservation(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_LIBRARIAN, ROLE_MEMBER])?;
+    state.features.assert_enabled(FEATURE_RESERVATIONS, &claims)?;
+
+    let id: TitleId = _req.match_info().query("titleid").parse::<TitleId>()?;
+
+    bus::post_reservation(&state.db, &state.lending, claims, id).and_then(|reservation_id| {
+        Ok(HttpResponse::Created()
+            .header(
+                "Location",
+                format!("v1/titles/{}/reservations/{}", id, reservation_id),
+            )
+            .finish())
+    })
+}
+
+/// Cancel a reservation of a Title (if authentification is successful)
+fn delete_reservation(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_LIBRARIAN, ROLE_MEMBER])?;
+    state.features.assert_enabled(FEATURE_RESERVATIONS, &claims)?;
+
+    let title_id: TitleId = _req.match_info().query("titleid").parse::<TitleId>()?;
+    let id: ReservationId = _req
+        .match_info()
+        .query("reservationid")
+        .parse::<ReservationId>()?;
+
+    bus::delete_reservation(&state.db, &state.lending, claims, title_id, id)
+        .and_then(|_| Ok(HttpResponse::NoContent().finish()))
+}
+
 /// Get all Books (if authentification is successful)
 fn get_books(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
     let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_LIBRARIAN, ROLE_MEMBER])?;
 
-    bus::get_books(&state.db, claims).and_then(|books| Ok(HttpResponse::Ok().json(books)))
+    bus::get_books(&state.db, &state.kc, claims).and_then(|books| Ok(HttpResponse::Ok().json(books)))
 }
 
 /// Get a requested Book (if authentification is successful)
@@ -242,7 +361,7 @@ fn get_book(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpRespons
 
     let id: BookId = _req.match_info().query("bookid").parse::<BookId>()?;
 
-    bus::get_book(&state.db, claims, id).and_then(|book| Ok(HttpResponse::Ok().json(book)))
+    bus::get_book(&state.db, &state.kc, claims, id).and_then(|book| Ok(HttpResponse::Ok().json(book)))
 }
 
 /// Insert a new Book (if authentification is successful)
@@ -287,6 +406,50 @@ fn delete_book(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResp
     bus::delete_book(&state.db, claims, id).and_then(|_| Ok(HttpResponse::NoContent().finish()))
 }
 
+/// Get all overdue Rentals (if authentification is successful)
+fn get_overdue_rentals(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_LIBRARIAN])?;
+
+    bus::get_overdue_rentals(&state.db, &state.kc, claims)
+        .and_then(|rentals| Ok(HttpResponse::Ok().json(rentals)))
+}
+
+/// Get a requested Rental (if authentification is successful)
+fn get_rental(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_LIBRARIAN, ROLE_MEMBER])?;
+
+    let id: RentalId = _req.match_info().query("rentalid").parse::<RentalId>()?;
+
+    bus::get_rental(&state.db, claims, id).and_then(|rental| Ok(HttpResponse::Ok().json(rental)))
+}
+
+/// Check out a Book (if authentification is successful)
+fn post_rental(
+    state: web::Data<AppState>,
+    json: web::Json<PostRental>,
+    _req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_LIBRARIAN, ROLE_MEMBER])?;
+
+    let localdb = state.db.clone();
+
+    bus::checkout_book(&localdb, &state.lending, claims, json.into_inner()).and_then(|rental_id| {
+        Ok(HttpResponse::Created()
+            .header("Location", format!("v1/rentals/{}", rental_id))
+            .finish())
+    })
+}
+
+/// Return a rented Book (if authentification is successful)
+fn post_rental_return(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_LIBRARIAN, ROLE_MEMBER])?;
+
+    let id: RentalId = _req.match_info().query("rentalid").parse::<RentalId>()?;
+
+    bus::return_rental(&state.db, &state.lending, claims, id)
+        .and_then(|()| Ok(HttpResponse::Ok().finish()))
+}
+
 /// Get all Members (if authentification is successful)
 fn get_members(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
     let claims = assert_roles(
@@ -294,7 +457,7 @@ fn get_members(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResp
         vec![ROLE_ADMIN, ROLE_ARISTOCRAT, ROLE_LIBRARIAN, ROLE_MEMBER],
     )?;
 
-    bus::get_members(&state.db, claims).and_then(|members| Ok(HttpResponse::Ok().json(members)))
+    bus::get_members(&state.db, &state.kc, claims).and_then(|members| Ok(HttpResponse::Ok().json(members)))
 }
 
 /// Get a requested Member (if authentification is successful)
@@ -306,24 +469,99 @@ fn get_member(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpRespo
 
     let id: MemberId = _req.match_info().query("memberid").parse::<MemberId>()?;
 
-    bus::get_member(&state.db, claims, id).and_then(|member| Ok(HttpResponse::Ok().json(member)))
+    bus::get_member(&state.db, &state.kc, claims, id).and_then(|member| Ok(HttpResponse::Ok().json(member)))
+}
+
+/// Get the logged in member (if authentification is successful)
+fn get_me(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(
+        &_req,
+        vec![ROLE_ADMIN, ROLE_ARISTOCRAT, ROLE_LIBRARIAN, ROLE_MEMBER],
+    )?;
+
+    bus::get_me(&state.db, claims).and_then(|me| Ok(HttpResponse::Ok().json(me)))
+}
+
+/// Get the state of the Keycloak synchronisation, for health checks
+fn get_health(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![])?;
+
+    Ok(HttpResponse::Ok().json(bus::get_health(&state.kc, &claims)))
+}
+
+/// Get the features available to the logged in user
+fn get_features(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![])?;
+
+    Ok(HttpResponse::Ok().json(GetFeatures {
+        features: state.features.enabled_for(&claims),
+    }))
+}
+
+/// Get the api tokens of the logged in member (if authentification is successful)
+fn get_api_tokens(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![])?;
+
+    bus::get_api_tokens(&state.db, claims).and_then(|tokens| Ok(HttpResponse::Ok().json(tokens)))
+}
+
+/// Create an api token for the logged in member (if authentification is successful)
+fn post_api_token(
+    state: web::Data<AppState>,
+    json: web::Json<PostApiToken>,
+    _req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![])?;
+
+    bus::post_api_token(&state.db, claims, json.into_inner()).and_then(|token| {
+        Ok(HttpResponse::Created()
+            .header("Location", format!("v1/me/tokens/{}", token.id))
+            .json(token))
+    })
+}
+
+/// Revoke an api token of the logged in member (if authentification is successful)
+fn delete_api_token(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![])?;
+
+    let id: ApiTokenId = _req.match_info().query("tokenid").parse::<ApiTokenId>()?;
+
+    bus::delete_api_token(&state.db, claims, id).and_then(|_| Ok(HttpResponse::NoContent().finish()))
 }
 
 /// Get the inventory of a Member (if authentification is successful)
-fn get_member_inventory(_state: web::Data<AppState>, _req: HttpRequest) -> HttpResponse {
-    HttpResponse::NotImplemented().finish()
+fn get_member_inventory(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_LIBRARIAN, ROLE_MEMBER])?;
+
+    let id: MemberId = _req.match_info().query("memberid").parse::<MemberId>()?;
+
+    bus::get_member_inventory(&state.db, &state.kc, claims, id)
+        .and_then(|inventory| Ok(HttpResponse::Ok().json(inventory)))
 }
 
 /// Insert into a member's inventory (if authentification is successful)
-fn post_member_inventory(_state: web::Data<AppState>, _req: HttpRequest) -> HttpResponse {
-    HttpResponse::NotImplemented().finish()
+fn post_member_inventory(
+    state: web::Data<AppState>,
+    json: web::Json<PutMemberInvetory>,
+    _req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_MEMBER])?;
+
+    let localdb = state.db.clone();
+    let id: MemberId = _req.match_info().query("memberid").parse::<MemberId>()?;
+
+    bus::post_member_inventory(&localdb, claims, id, json.into_inner()).and_then(|book_id| {
+        Ok(HttpResponse::Created()
+            .header("Location", format!("v1/books/{}", book_id))
+            .finish())
+    })
 }
 
 /// Get all Guilds (if authentification is successful)
 fn get_guilds(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
     let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_ARISTOCRAT, ROLE_MEMBER])?;
 
-    bus::get_guilds(&state.db, claims).and_then(|guilds| Ok(HttpResponse::Ok().json(guilds)))
+    bus::get_guilds(&state.db, &state.kc, claims).and_then(|guilds| Ok(HttpResponse::Ok().json(guilds)))
 }
 
 /// Get a requested Guild (if authentification is successful)
@@ -332,7 +570,7 @@ fn get_guild(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpRespon
 
     let id: GuildId = _req.match_info().query("guildid").parse::<GuildId>()?;
 
-    bus::get_guild(&state.db, claims, id).and_then(|guild| Ok(HttpResponse::Ok().json(guild)))
+    bus::get_guild(&state.db, &state.kc, claims, id).and_then(|guild| Ok(HttpResponse::Ok().json(guild)))
 }
 
 /// Insert a new Guild (if authentification is successful)
@@ -358,7 +596,7 @@ fn put_guild(
     json: web::Json<PutPostGuild>,
     _req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
-    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_ARISTOCRAT])?;
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_ARISTOCRAT, ROLE_MEMBER])?;
 
     let localdb = state.db.clone();
     let id: GuildId = _req.match_info().query("guildid").parse::<GuildId>()?;
@@ -369,12 +607,213 @@ fn put_guild(
     bus::put_guild(&localdb, claims, guild).and_then(|()| Ok(HttpResponse::Ok().finish()))
 }
 
+/// Delete a Guild (if authentification is successful)
+fn delete_guild(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_ARISTOCRAT, ROLE_MEMBER])?;
+
+    let id: GuildId = _req.match_info().query("guildid").parse::<GuildId>()?;
+
+    bus::delete_guild(&state.db, claims, id).and_then(|_| Ok(HttpResponse::NoContent().finish()))
+}
+
 /// Get the inventory of a Guild (if authentification is successful)
-fn get_guild_inventory(_state: web::Data<AppState>, _req: HttpRequest) -> HttpResponse {
-    HttpResponse::NotImplemented().finish()
+fn get_guild_inventory(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_LIBRARIAN, ROLE_MEMBER])?;
+
+    let id: GuildId = _req.match_info().query("guildid").parse::<GuildId>()?;
+
+    bus::get_guild_inventory(&state.db, &state.kc, claims, id)
+        .and_then(|inventory| Ok(HttpResponse::Ok().json(inventory)))
 }
 
 /// Insert into a guild's inventory (if authentification is successful)
-fn post_guild_inventory(_state: web::Data<AppState>, _req: HttpRequest) -> HttpResponse {
-    HttpResponse::NotImplemented().finish()
+fn post_guild_inventory(
+    state: web::Data<AppState>,
+    json: web::Json<PutGuildInventory>,
+    _req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_LIBRARIAN, ROLE_MEMBER])?;
+
+    let localdb = state.db.clone();
+    let id: GuildId = _req.match_info().query("guildid").parse::<GuildId>()?;
+
+    bus::post_guild_inventory(&localdb, claims, id, json.into_inner()).and_then(|book_id| {
+        Ok(HttpResponse::Created()
+            .header("Location", format!("v1/books/{}", book_id))
+            .finish())
+    })
+}
+
+/// Get the members of a Guild with their guild roles (if authentification is successful)
+fn get_guild_members(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(
+        &_req,
+        vec![ROLE_ADMIN, ROLE_LIBRARIAN, ROLE_ARISTOCRAT, ROLE_MEMBER],
+    )?;
+
+    let id: GuildId = _req.match_info().query("guildid").parse::<GuildId>()?;
+
+    bus::get_guild_members(&state.db, &state.kc, claims, id)
+        .and_then(|members| Ok(HttpResponse::Ok().json(members)))
+}
+
+/// Add a member to a Guild or change their guild role (if authentification is successful)
+fn put_guild_member(
+    state: web::Data<AppState>,
+    json: web::Json<PutGuildMember>,
+    _req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_ARISTOCRAT, ROLE_MEMBER])?;
+
+    let guild_id: GuildId = _req.match_info().query("guildid").parse::<GuildId>()?;
+    let member_id: MemberId = _req.match_info().query("memberid").parse::<MemberId>()?;
+
+    bus::put_guild_member(&state.db, claims, guild_id, member_id, json.into_inner())
+        .and_then(|()| Ok(HttpResponse::Ok().finish()))
+}
+
+/// Remove a member from a Guild (if authentification is successful)
+fn delete_guild_member(state: web::Data<AppState>, _req: HttpRequest) -> Result<HttpResponse, Error> {
+    let claims = assert_roles(&_req, vec![ROLE_ADMIN, ROLE_ARISTOCRAT, ROLE_MEMBER])?;
+
+    let guild_id: GuildId = _req.match_info().query("guildid").parse::<GuildId>()?;
+    let member_id: MemberId = _req.match_info().query("memberid").parse::<MemberId>()?;
+
+    bus::delete_guild_member(&state.db, claims, guild_id, member_id)
+        .and_then(|()| Ok(HttpResponse::NoContent().finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::mock_idp::*;
+    use crate::auth::{IntrospectTokens, Introspector};
+    use crate::database::test_util::*;
+    use crate::settings::LendingPolicy;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use std::collections::HashMap;
+
+    /// Verifier of the tokens of the mock identity provider, introspecting opaque ones
+    fn verifier(idp: &MockIdp) -> JwtVerifier {
+        let verifier = idp.verifier().with_introspection(Introspector::new(
+            String::from(CLIENT_ID),
+            String::from(CLIENT_SECRET),
+        ));
+        idp.fetch_keys(&verifier);
+        verifier
+    }
+
+    /// Status of a GET request through the middleware and handlers of the api
+    fn get(db: &Database, verifier: &JwtVerifier, uri: &str, token: &str) -> StatusCode {
+        let policy = LendingPolicy {
+            max_loan_days: 28,
+            max_extensions: 2,
+        };
+        let state = AppState {
+            db: db.clone(),
+            kc: KeycloakCache::new(),
+            verifier: verifier.clone(),
+            lending: Lending {
+                hold_days: 7,
+                member: policy.clone(),
+                guild: policy,
+            },
+            features: Features::new(HashMap::new()),
+        };
+        let mut app = test::init_service(
+            App::new()
+                .wrap(IntrospectTokens::new(verifier.clone()))
+                .register_data(web::Data::new(state))
+                .service(get_v1()),
+        );
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .header("Authorization", format!("Bearer {}", token))
+            .to_request();
+        test::call_service(&mut app, req).status()
+    }
+
+    fn is_provisioned(db: &Database, uid: &str) -> bool {
+        db.get_member_by_external_id(&String::from(uid))
+            .unwrap()
+            .is_some()
+    }
+
+    #[test]
+    fn valid_jwt_provisions_the_member() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let idp = MockIdp::start();
+        let token = idp.mint(&claims("external_id", vec![ROLE_MEMBER]));
+
+        let status = get(&db, &verifier(&idp), "/v1/me", &token);
+        let provisioned = is_provisioned(&db, "external_id");
+        teardown(settings);
+        assert_eq!(status, StatusCode::OK);
+        assert!(provisioned);
+    }
+
+    #[test]
+    fn expired_jwt_is_unauthorized() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let idp = MockIdp::start();
+        let token = idp.mint_for(&claims("external_id", vec![ROLE_MEMBER]), AUDIENCE, -120);
+
+        let status = get(&db, &verifier(&idp), "/v1/me", &token);
+        let provisioned = is_provisioned(&db, "external_id");
+        teardown(settings);
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(!provisioned);
+    }
+
+    #[test]
+    fn jwt_for_other_audience_is_unauthorized() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let idp = MockIdp::start();
+        let token = idp.mint_for(
+            &claims("external_id", vec![ROLE_MEMBER]),
+            "other-client",
+            60,
+        );
+
+        let status = get(&db, &verifier(&idp), "/v1/me", &token);
+        let provisioned = is_provisioned(&db, "external_id");
+        teardown(settings);
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(!provisioned);
+    }
+
+    #[test]
+    fn jwt_without_required_role_is_forbidden() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let idp = MockIdp::start();
+        let token = idp.mint(&claims("external_id", vec![ROLE_MEMBER]));
+
+        let status = get(&db, &verifier(&idp), "/v1/rentals/overdue", &token);
+        teardown(settings);
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn opaque_token_is_introspected() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let idp = MockIdp::start();
+        let verifier = verifier(&idp);
+        let token = idp.issue_opaque(&claims("external_id", vec![ROLE_MEMBER]));
+        let revoked = idp.issue_opaque(&claims("revoked_id", vec![ROLE_MEMBER]));
+        idp.revoke(&revoked);
+
+        let status = get(&db, &verifier, "/v1/me", &token);
+        let revoked_status = get(&db, &verifier, "/v1/me", &revoked);
+        let provisioned = is_provisioned(&db, "external_id");
+        teardown(settings);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(revoked_status, StatusCode::UNAUTHORIZED);
+        assert!(provisioned);
+    }
 }
@@ -0,0 +1,125 @@
+use super::{Claims, KeycloakCache};
+use chrono::Local;
+use crate::database::{ApiToken, Member, Repository};
+use crate::error::Error;
+use openssl::rand::rand_bytes;
+use openssl::sha::sha256;
+
+/// Prefix of api token secrets, distinguishes them from JWTs in the Authorization header
+pub const API_TOKEN_PREFIX: &str = "liberation_";
+
+/// Days an api token is accepted unless another expiry is requested
+pub const API_TOKEN_DAYS: i64 = 365;
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Creates a new random secret for an api token
+pub fn generate_secret() -> Result<String, Error> {
+    let mut bytes = [0u8; 32];
+    rand_bytes(&mut bytes).map_err(|err| {
+        error!("Generating api token secret failed: {:?}", err);
+        Error::IllegalState
+    })?;
+    Ok(format!("{}{}", API_TOKEN_PREFIX, to_hex(&bytes)))
+}
+
+/// Hashes a secret the way it is stored in the database
+pub fn hash_secret(secret: &str) -> String {
+    to_hex(&sha256(secret.as_bytes()))
+}
+
+/// Get the claims of the member an api token belongs to.
+/// The roles are limited to those the member still has in the realm, expired tokens are rejected.
+pub fn get_claims_for_api_token<R: Repository>(
+    db: &R,
+    kc: &KeycloakCache,
+    secret: &str,
+) -> Result<Claims, Error> {
+    let token: ApiToken = match db.get_api_token_by_hash(&hash_secret(secret))? {
+        Some(token) => token,
+        None => {
+            debug!("Unknown api token");
+            return Err(Error::InvalidAuthenticationError);
+        }
+    };
+    if token.expires < Local::today().naive_local() {
+        debug!("Api token {:?} expired on {}", token.id, token.expires);
+        return Err(Error::InvalidAuthenticationError);
+    }
+    let member = match db.get::<Member>(token.member)? {
+        Some(member) => member,
+        None => return Err(Error::IllegalState),
+    };
+    let member_roles = kc.get_roles(&member.external_id);
+
+    Ok(Claims {
+        uid: member.external_id,
+        roles: token
+            .roles
+            .into_iter()
+            .filter(|role| member_roles.contains(role))
+            .collect(),
+        name: String::new(),
+        email: String::new(),
+        member_id: Some(token.member),
+        api_token: token.id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use crate::database::memory::MemoryRepository;
+    use crate::database::{Date, EntityRepository};
+    use std::collections::HashSet;
+
+    /// Inserts a token of a member with the realm role "member" and returns its secret
+    fn token(db: &MemoryRepository, kc: &KeycloakCache, roles: Vec<&str>, expires: Date) -> String {
+        let member = db
+            .insert(&Member::new(None, String::from("external_id")))
+            .unwrap();
+        let mut users = HashSet::new();
+        users.insert(String::from("external_id"));
+        kc.set_role_users("member", users);
+        let secret = generate_secret().unwrap();
+        db.insert(&ApiToken::new(
+            None,
+            member,
+            String::from("kiosk"),
+            hash_secret(&secret),
+            roles.into_iter().map(String::from).collect(),
+            Local::today().naive_local(),
+            expires,
+        ))
+        .unwrap();
+        secret
+    }
+
+    #[test]
+    fn api_token_roles_are_limited_to_realm_roles() {
+        let (db, kc) = (MemoryRepository::new(), KeycloakCache::new());
+        let today = Local::today().naive_local();
+        let secret = token(&db, &kc, vec!["member", "librarian"], today);
+
+        let claims = get_claims_for_api_token(&db, &kc, &secret).unwrap();
+        assert_eq!(claims.uid, "external_id");
+        assert_eq!(claims.roles, vec![String::from("member")]);
+    }
+
+    #[test]
+    fn expired_or_unknown_api_token_fails() {
+        let (db, kc) = (MemoryRepository::new(), KeycloakCache::new());
+        let yesterday = Local::today().naive_local() - Duration::days(1);
+        let secret = token(&db, &kc, vec!["member"], yesterday);
+
+        for secret in &[secret, generate_secret().unwrap()] {
+            match get_claims_for_api_token(&db, &kc, secret) {
+                Err(Error::InvalidAuthenticationError) => (),
+                _ => panic!("Expected Error::InvalidAuthenticationError"),
+            }
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use super::api_token::hash_secret;
+use super::oidc::has_audience;
+use super::{Claims, JwtVerifier};
+use actix_web::client::Client;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, StatusCode};
+use actix_web::HttpMessage;
+use chrono::Local;
+use crate::error::Error;
+use crate::settings::Keycloak as KeycloakSettings;
+use futures::{future, Future, Poll};
+use serde::Deserialize;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Answer of an RFC 7662 introspection endpoint.
+/// Keycloak includes the claims of the token, they are read like those of a JWT.
+#[derive(Deserialize, Debug)]
+struct IntrospectionResponse {
+    active: bool,
+    exp: Option<i64>,
+    iss: Option<String>,
+    /// A single audience or a list of them
+    aud: Option<Value>,
+    /// The whole answer, holding the claims of the token
+    #[serde(skip)]
+    payload: Value,
+}
+
+impl IntrospectionResponse {
+    fn parse(payload: Value) -> Result<IntrospectionResponse, Error> {
+        let response: IntrospectionResponse = serde_json::from_value(payload.clone())
+            .map_err(|err| Error::IntrospectionError(format!("{}", err)))?;
+        Ok(IntrospectionResponse {
+            payload,
+            ..response
+        })
+    }
+
+    /// Claims of an active token for the given issuer and audience
+    fn into_claims(self, check: &TokenCheck) -> Result<Claims, Error> {
+        if !self.active {
+            debug!("introspected token is not active");
+            return Err(Error::InvalidAuthenticationError);
+        }
+        let audience = match self.aud {
+            None => check.optional_audience,
+            Some(ref aud) => has_audience(Some(aud), &check.audience),
+        };
+        if self.iss.as_ref() != Some(&check.issuer) || !audience {
+            debug!("introspected token has wrong issuer or audience: {:?}", self);
+            return Err(Error::InvalidAuthenticationError);
+        }
+        Claims::from_token(&self.payload, &check.roles_claim)
+            .ok_or(Error::InvalidAuthenticationError)
+    }
+}
+
+/// What an introspected token is checked against
+#[derive(Clone, Debug)]
+struct TokenCheck {
+    issuer: String,
+    audience: String,
+    /// Accept tokens the provider reports without audience
+    optional_audience: bool,
+    /// Dot separated path of the claim holding the realm roles
+    roles_claim: String,
+}
+
+#[derive(Clone, Debug)]
+struct CachedClaims {
+    claims: Claims,
+    /// Seconds since the epoch
+    expires: i64,
+}
+
+/// Validates opaque access tokens at the introspection endpoint of the provider.
+/// Results are cached by the hash of the token until the token expires.
+#[derive(Clone)]
+pub struct Introspector {
+    client_id: String,
+    client_secret: String,
+    /// Accept tokens the provider reports without audience
+    optional_audience: bool,
+    cache: Arc<Mutex<HashMap<String, CachedClaims>>>,
+}
+
+impl fmt::Debug for Introspector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Introspector {{ client_id: {:?} }}", self.client_id)
+    }
+}
+
+impl Introspector {
+    pub fn new(client_id: String, client_secret: String) -> Introspector {
+        Introspector {
+            client_id,
+            client_secret,
+            optional_audience: false,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Accept tokens without audience, for providers which do not report it
+    pub fn with_optional_audience(self, optional_audience: bool) -> Introspector {
+        Introspector {
+            optional_audience,
+            ..self
+        }
+    }
+
+    pub fn from_settings(settings: &KeycloakSettings) -> Introspector {
+        Introspector::new(settings.clientid.clone(), settings.clientsecret.clone())
+    }
+
+    fn get_cached(&self, key: &str) -> Option<Claims> {
+        let now = Local::now().timestamp();
+        self.cache
+            .lock()
+            .expect("Can not lock introspection cache mutex.")
+            .get(key)
+            .filter(|cached| cached.expires > now)
+            .map(|cached| cached.claims.clone())
+    }
+
+    fn cache(&self, key: String, claims: Claims, expires: i64) {
+        let now = Local::now().timestamp();
+        let mut cache = self
+            .cache
+            .lock()
+            .expect("Can not lock introspection cache mutex.");
+        cache.retain(|_, cached| cached.expires > now);
+        cache.insert(key, CachedClaims { claims, expires });
+    }
+
+    /// Claims of an active token, the provider is only asked if the token is not cached
+    pub fn introspect(
+        &self,
+        endpoint: &str,
+        issuer: &str,
+        audience: &str,
+        roles_claim: &str,
+        token: &str,
+    ) -> Box<dyn Future<Item = Claims, Error = Error>> {
+        let key = hash_secret(token);
+        if let Some(claims) = self.get_cached(&key) {
+            return Box::new(future::ok(claims));
+        }
+        let check = TokenCheck {
+            issuer: String::from(issuer),
+            audience: String::from(audience),
+            optional_audience: self.optional_audience,
+            roles_claim: String::from(roles_claim),
+        };
+
+        let introspector = self.clone();
+        Box::new(self.request(endpoint, token).and_then(move |response| {
+            let expires = response.exp;
+            let claims = response.into_claims(&check)?;
+            // Tokens without expiry are introspected on every request
+            if let Some(expires) = expires {
+                introspector.cache(key, claims.clone(), expires);
+            }
+            Ok(claims)
+        }))
+    }
+
+    /// Posts the token to the introspection endpoint, authenticated as the backend client
+    fn request(
+        &self,
+        endpoint: &str,
+        token: &str,
+    ) -> impl Future<Item = IntrospectionResponse, Error = Error> {
+        Client::build()
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .post(endpoint)
+            .send_form(&[("token", token), ("token_type_hint", "access_token")])
+            .map_err(|err| Error::IntrospectionError(format!("{}", err)))
+            .and_then(
+                |mut response| -> Box<dyn Future<Item = IntrospectionResponse, Error = Error>> {
+                    match response.status() {
+                        StatusCode::OK => Box::new(
+                            response
+                                .json::<Value>()
+                                .map_err(|err| Error::IntrospectionError(format!("{}", err)))
+                                .and_then(IntrospectionResponse::parse),
+                        ),
+                        status => Box::new(future::err(Error::IntrospectionError(format!(
+                            "introspection endpoint answered with status {}",
+                            status.as_u16()
+                        )))),
+                    }
+                },
+            )
+    }
+}
+
+/// Claims of the opaque access token of a request, introspected before the handler runs
+#[derive(Clone, Debug)]
+pub struct IntrospectedClaims(pub Claims);
+
+/// Middleware introspecting opaque access tokens without blocking the worker.
+/// The handlers pick up the result as `IntrospectedClaims` in the request extensions.
+pub struct IntrospectTokens {
+    verifier: JwtVerifier,
+}
+
+impl IntrospectTokens {
+    pub fn new(verifier: JwtVerifier) -> IntrospectTokens {
+        IntrospectTokens { verifier }
+    }
+}
+
+impl<S, B> Transform<S> for IntrospectTokens
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+        + 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = IntrospectTokensMiddleware<S>;
+    type Future = future::FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        future::ok(IntrospectTokensMiddleware {
+            verifier: self.verifier.clone(),
+            service: Rc::new(RefCell::new(service)),
+        })
+    }
+}
+
+pub struct IntrospectTokensMiddleware<S> {
+    verifier: JwtVerifier,
+    service: Rc<RefCell<S>>,
+}
+
+impl<S, B> Service for IntrospectTokensMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+        + 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.borrow_mut().poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let introspection = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| value.starts_with("Bearer "))
+            .and_then(|value| self.verifier.introspect(&value.replacen("Bearer ", "", 1)));
+
+        match introspection {
+            None => Box::new(self.service.borrow_mut().call(req)),
+            Some(introspection) => {
+                let service = self.service.clone();
+                Box::new(introspection.then(move |result| {
+                    match result {
+                        Ok(claims) => {
+                            req.extensions_mut().insert(IntrospectedClaims(claims));
+                        }
+                        Err(e) => debug!("Introspecting the token failed: {:?}", e),
+                    }
+                    service.borrow_mut().call(req)
+                }))
+            }
+        }
+    }
+}
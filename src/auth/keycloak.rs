@@ -0,0 +1,693 @@
+use super::roles;
+use actix::prelude::*;
+use actix_web::client::Client;
+use actix_web::error::BlockingError;
+use actix_web::http::StatusCode;
+use actix_web::web;
+use chrono::{DateTime, Local, TimeZone};
+use crate::database::type_aliases::*;
+use crate::error::Error;
+use futures::future::{self, Loop};
+use futures::Future;
+use oauth2::basic::BasicClient;
+use oauth2::prelude::*;
+use oauth2::{AuthUrl, ClientId, ClientSecret, TokenResponse, TokenUrl};
+use crate::settings::Keycloak as KeycloakSettings;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use url::Url;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::cmp::min;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct KeycloakUser {
+    id: String,
+    #[serde(rename = "createdTimestamp")]
+    created_timestamp: u64,
+    username: String,
+    #[serde(rename = "firstName")]
+    first_name: Option<String>,
+    #[serde(rename = "lastName")]
+    last_name: Option<String>,
+    email: Option<String>,
+    enabled: bool,
+    totp: bool,
+    #[serde(rename = "emailVerified")]
+    email_verified: bool,
+    #[serde(rename = "disableableCredentialTypes")]
+    disableable_credential_types: Vec<String>,
+    #[serde(rename = "requiredActions")]
+    required_actions: Vec<String>,
+    #[serde(rename = "notBefore")]
+    not_before: u64,
+    access: Access,
+}
+
+impl KeycloakUser {
+    /// Full name of the user, or the username if no name is set
+    pub fn display_name(&self) -> String {
+        let name = vec![&self.first_name, &self.last_name]
+            .into_iter()
+            .filter_map(|part| part.as_ref())
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ");
+        match name.is_empty() {
+            true => self.username.clone(),
+            false => name,
+        }
+    }
+
+    pub fn username(&self) -> &String {
+        &self.username
+    }
+
+    pub fn email(&self) -> Option<&String> {
+        self.email.as_ref()
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// Change done through the admin api or console, only recorded if saving admin events is enabled
+#[derive(Deserialize, Debug)]
+struct AdminEvent {
+    /// Milliseconds since the epoch
+    time: i64,
+    #[serde(rename = "operationType")]
+    operation_type: String,
+    #[serde(rename = "resourceType")]
+    resource_type: String,
+    #[serde(rename = "resourcePath")]
+    resource_path: String,
+}
+
+impl AdminEvent {
+    /// Id of the user the event is about, if any
+    fn user_id(&self) -> Option<ExternalId> {
+        let mut path = self.resource_path.split('/');
+        match (path.next(), path.next()) {
+            (Some("users"), Some(id)) if !id.is_empty() => Some(String::from(id)),
+            _ => None,
+        }
+    }
+}
+
+/// Reference to a user, as listed for a role
+#[derive(Deserialize, Debug)]
+struct KeycloakUserRef {
+    id: ExternalId,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Access {
+    #[serde(rename = "manageGroupMembership")]
+    manage_group_membership: bool,
+    view: bool,
+    #[serde(rename = "mapRoles")]
+    map_roles: bool,
+    impersonate: bool,
+    manage: bool,
+}
+
+/// State of the synchronisation with Keycloak, for health checks
+#[derive(Clone, Debug, Default)]
+pub struct SyncHealth {
+    pub last_success: Option<DateTime<Local>>,
+    pub last_error: Option<DateTime<Local>>,
+    pub last_error_message: Option<String>,
+    /// Failed synchronisations since the last successful one
+    pub failures: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct KeycloakCache {
+    cache: Arc<Mutex<HashMap<ExternalId, KeycloakUser>>>,
+    role_users: Arc<Mutex<HashMap<String, HashSet<ExternalId>>>>,
+    health: Arc<Mutex<SyncHealth>>,
+}
+
+/// Admin api of the realm
+#[derive(Clone, Debug)]
+struct AdminApi {
+    url: Url,
+    page_size: u32,
+}
+
+impl AdminApi {
+    /// Url of a resource of the realm
+    fn url(&self, path: &str) -> Url {
+        self.url.join(path).unwrap()
+    }
+}
+
+pub struct Keycloak {
+    admin: AdminApi,
+    oauth_client: BasicClient,
+    cache: KeycloakCache,
+    interval: Duration,
+    max_backoff: Duration,
+    /// Synchronise incrementally using admin events between full synchronisations
+    admin_events: bool,
+    full_sync_interval: Duration,
+    last_full_sync: Option<Instant>,
+    /// Time of the newest admin event already applied, milliseconds since the epoch
+    events_since: i64,
+}
+
+/// Largest response accepted from the admin api, a page of users easily exceeds the default of 64KB
+const MAX_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Admin events this much older than the start of a full synchronisation are applied again,
+/// in case the clocks of Keycloak and Liberation differ
+const EVENT_OVERLAP_MILLIS: i64 = 60 * 1000;
+
+impl KeycloakCache {
+    pub fn new() -> KeycloakCache {
+        KeycloakCache {
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            role_users: Arc::new(Mutex::new(HashMap::new())),
+            health: Arc::new(Mutex::new(SyncHealth::default())),
+        }
+    }
+
+    pub fn insert_user(&self, user: KeycloakUser) {
+        self.cache.lock().unwrap().insert(user.id.clone(), user);
+    }
+
+    pub fn remove_user(&self, user_id: &ExternalId) {
+        self.cache
+            .lock()
+            .expect("Can not lock user cache mutex.")
+            .remove(user_id);
+    }
+
+    pub fn get_user(&self, user_id: &ExternalId) -> Result<Option<KeycloakUser>, Error> {
+        Ok(self
+            .cache
+            .lock()
+            .expect("Can not lock user cache mutex.")
+            .get(user_id)
+            .map(|user| (*user).clone()))
+    }
+
+    /// Removes all users from the cache
+    pub fn reset_users(&self) {
+        self.cache
+            .lock()
+            .expect("Can not lock user cache mutex.")
+            .clear();
+    }
+
+    /// Replaces all cached users, users missing in the list are removed
+    pub fn set_users(&self, users: Vec<KeycloakUser>) {
+        let users: HashMap<ExternalId, KeycloakUser> = users
+            .into_iter()
+            .map(|user| (user.id.clone(), user))
+            .collect();
+        *self.cache.lock().expect("Can not lock user cache mutex.") = users;
+    }
+
+    /// Replaces the users having the given realm role
+    pub fn set_role_users(&self, role: &str, users: HashSet<ExternalId>) {
+        self.role_users
+            .lock()
+            .expect("Can not lock role cache mutex.")
+            .insert(String::from(role), users);
+    }
+
+    /// Gets the realm roles of a user
+    pub fn get_roles(&self, user_id: &ExternalId) -> Vec<String> {
+        self.role_users
+            .lock()
+            .expect("Can not lock role cache mutex.")
+            .iter()
+            .filter(|(_, users)| users.contains(user_id))
+            .map(|(role, _)| role.clone())
+            .collect()
+    }
+
+    pub fn health(&self) -> SyncHealth {
+        self.health
+            .lock()
+            .expect("Can not lock sync health mutex.")
+            .clone()
+    }
+
+    fn record_sync_success(&self) {
+        let mut health = self.health.lock().expect("Can not lock sync health mutex.");
+        health.last_success = Some(Local::now());
+        health.failures = 0;
+    }
+
+    fn record_sync_error(&self, err: &Error) {
+        let mut health = self.health.lock().expect("Can not lock sync health mutex.");
+        health.last_error = Some(Local::now());
+        health.last_error_message = Some(format!("{:?}", err));
+        health.failures += 1;
+    }
+}
+
+impl Actor for Keycloak {
+    type Context = Context<Keycloak>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        Keycloak::sync(self, ctx);
+    }
+}
+
+impl Keycloak {
+    pub fn new(
+        keycloak_url: Url,
+        realm: String,
+        client_id: String,
+        client_secret: String,
+        cache: KeycloakCache,
+        interval: Duration,
+        max_backoff: Duration,
+    ) -> Self {
+        let token_url = TokenUrl::new(
+            keycloak_url
+                .join("realms/")
+                .unwrap()
+                .join(format!("{}/", realm.clone()).as_str())
+                .unwrap()
+                .join("protocol/openid-connect/token")
+                .unwrap(),
+        );
+
+        let auth_url = AuthUrl::new(
+            keycloak_url
+                .join("realms/")
+                .unwrap()
+                .join(format!("{}/", realm.clone()).as_str())
+                .unwrap()
+                .join("protocol/openid-connect/auth")
+                .unwrap(),
+        );
+
+        let kc = Keycloak {
+            admin: AdminApi {
+                url: keycloak_url
+                    .join("admin/realms/")
+                    .unwrap()
+                    .join(format!("{}/", realm).as_str())
+                    .unwrap(),
+                page_size: 100,
+            },
+            oauth_client: BasicClient::new(
+                ClientId::new(client_id.clone()),
+                Some(ClientSecret::new(client_secret.clone())),
+                auth_url,
+                Some(token_url),
+            ),
+            cache,
+            interval,
+            max_backoff,
+            admin_events: false,
+            full_sync_interval: interval,
+            last_full_sync: None,
+            events_since: 0,
+        };
+
+        return kc;
+    }
+
+    pub fn from_settings(settings: &KeycloakSettings, cache: KeycloakCache) -> Self {
+        let mut kc = Keycloak::new(
+            settings.url.clone(),
+            settings.realm.clone(),
+            settings.clientid.clone(),
+            settings.clientsecret.clone(),
+            cache,
+            Duration::from_secs(settings.syncinterval),
+            Duration::from_secs(settings.maxbackoff),
+        );
+        kc.admin.page_size = settings.pagesize;
+        kc.admin_events = settings.adminevents;
+        kc.full_sync_interval = Duration::from_secs(settings.fullsyncinterval);
+        kc
+    }
+
+    /// Wait until the next synchronisation, growing exponentially after failures
+    fn next_delay(&self) -> Duration {
+        match self.cache.health().failures {
+            0 => self.interval,
+            failures => min(self.interval * 2u32.pow(min(failures, 16)), self.max_backoff),
+        }
+    }
+
+    /// Exchanges the client credentials for an access token.
+    /// The exchange blocks until Keycloak answers, so it runs on the thread pool.
+    fn access_token(&self) -> impl Future<Item = String, Error = Error> {
+        debug!("authenticating with keycloak...");
+        let client = self.oauth_client.clone();
+        web::block(move || {
+            client
+                .exchange_client_credentials()
+                .map(|token| token.access_token().secret().clone())
+                .map_err(|err| format!("{:?}", err))
+        })
+        .map_err(|err| match err {
+            BlockingError::Error(message) => Error::KeycloakAuthenticationError(message),
+            BlockingError::Canceled => {
+                Error::KeycloakAuthenticationError(String::from("token exchange was canceled"))
+            }
+        })
+    }
+
+    /// Whether the next synchronisation has to fetch all users
+    fn needs_full_sync(&self) -> bool {
+        !self.admin_events
+            || self
+                .last_full_sync
+                .map_or(true, |last| last.elapsed() >= self.full_sync_interval)
+    }
+
+    /// Synchronises users and roles, then schedules the next synchronisation
+    fn sync(kc: &mut Self, ctx: &mut Context<Keycloak>) {
+        let full = kc.needs_full_sync();
+        let started = Local::now().timestamp_millis() - EVENT_OVERLAP_MILLIS;
+        let sync = kc
+            .access_token()
+            .into_actor(kc)
+            .and_then(move |token, kc, _| {
+                let fetch: Box<dyn Future<Item = i64, Error = Error>> = match full {
+                    true => Box::new(kc.fetch(token).map(move |()| started)),
+                    false => Box::new(kc.fetch_changes(token, kc.events_since)),
+                };
+                fetch.into_actor(kc)
+            });
+
+        ctx.spawn(sync.then(move |result, kc, ctx| {
+            match result {
+                Ok(events_since) => {
+                    if full {
+                        kc.last_full_sync = Some(Instant::now());
+                    }
+                    kc.events_since = events_since;
+                    kc.cache.record_sync_success();
+                }
+                Err(err) => {
+                    error!("Synchronising with keycloak failed: {:?}", err);
+                    kc.cache.record_sync_error(&err);
+                }
+            }
+            let delay = kc.next_delay();
+            debug!("next keycloak synchronisation in {:?}", delay);
+            ctx.run_later(delay, Keycloak::sync);
+            actix::fut::ok(())
+        }));
+    }
+
+    /// Fetches all users and the users of each realm role
+    fn fetch(&self, token: String) -> impl Future<Item = (), Error = Error> {
+        debug!("updating user cache from keycloak...");
+        let users = get_all::<KeycloakUser>(token.clone(), &self.admin, "users");
+        let role_users = fetch_role_users(token, &self.admin, self.cache.clone());
+
+        let cache = self.cache.clone();
+        users
+            .join(role_users)
+            .map(move |(users, ())| cache.set_users(users))
+    }
+
+    /// Fetches the users changed according to the admin events newer than `since`.
+    /// Returns the time of the newest event.
+    fn fetch_changes(&self, token: String, since: i64) -> impl Future<Item = i64, Error = Error> {
+        debug!("fetching keycloak admin events since {}...", since);
+        // dateFrom only takes whole days in the timezone of Keycloak, so start a day earlier
+        let date_from = (Local.timestamp_millis(since) - chrono::Duration::days(1))
+            .format("%Y-%m-%d")
+            .to_string();
+        let path = format!(
+            "admin-events?resourceTypes=USER&resourceTypes=REALM_ROLE_MAPPING&dateFrom={}",
+            date_from
+        );
+
+        let admin = self.admin.clone();
+        let cache = self.cache.clone();
+        get_all::<AdminEvent>(token.clone(), &self.admin, &path).and_then(move |events| {
+            let events: Vec<AdminEvent> =
+                events.into_iter().filter(|event| event.time > since).collect();
+            let newest = events.iter().map(|event| event.time).max().unwrap_or(since);
+            // Deleted users have to disappear from the roles as well
+            let roles_changed = events.iter().any(|event| {
+                event.resource_type == "REALM_ROLE_MAPPING" || event.operation_type == "DELETE"
+            });
+            let user_ids: HashSet<ExternalId> = events
+                .iter()
+                .filter(|event| event.resource_type == "USER")
+                .filter_map(|event| event.user_id())
+                .collect();
+            debug!(
+                "{} keycloak admin events, {} changed users",
+                events.len(),
+                user_ids.len()
+            );
+
+            let users = future::join_all(
+                user_ids
+                    .into_iter()
+                    .map(|id| {
+                        get_json::<KeycloakUser>(&token, admin.url(format!("users/{}", id).as_str()))
+                            .map(move |user| (id, user))
+                    })
+                    .collect::<Vec<_>>(),
+            );
+            let role_users: Box<dyn Future<Item = (), Error = Error>> = match roles_changed {
+                true => Box::new(fetch_role_users(token, &admin, cache.clone())),
+                false => Box::new(future::ok(())),
+            };
+
+            users.join(role_users).map(move |(users, ())| {
+                for (id, user) in users {
+                    match user {
+                        Some(user) => cache.insert_user(user),
+                        None => cache.remove_user(&id),
+                    }
+                }
+                newest
+            })
+        })
+    }
+}
+
+/// Fetches and caches the users of each realm role
+fn fetch_role_users(
+    token: String,
+    admin: &AdminApi,
+    cache: KeycloakCache,
+) -> impl Future<Item = (), Error = Error> {
+    debug!("updating realm roles from keycloak...");
+    let role_users = future::join_all(
+        roles::ROLES
+            .iter()
+            .map(|role| {
+                get_all::<KeycloakUserRef>(
+                    token.clone(),
+                    admin,
+                    format!("roles/{}/users", role).as_str(),
+                )
+                .map(move |users| (*role, users))
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    role_users.map(move |role_users| {
+        for (role, users) in role_users {
+            cache.set_role_users(role, users.into_iter().map(|user| user.id).collect());
+        }
+    })
+}
+
+/// Gets all pages of a list from the admin api, which returns at most 100 entries by default
+fn get_all<T: DeserializeOwned + 'static>(
+    token: String,
+    admin: &AdminApi,
+    path: &str,
+) -> impl Future<Item = Vec<T>, Error = Error> {
+    let url = admin.url(path);
+    let page_size = admin.page_size;
+    future::loop_fn(Vec::new(), move |mut results: Vec<T>| {
+        let mut page_url = url.clone();
+        page_url
+            .query_pairs_mut()
+            .append_pair("first", &results.len().to_string())
+            .append_pair("max", &page_size.to_string());
+        get_list::<T>(&token, page_url).map(move |page| {
+            let last_page = page.len() < page_size as usize;
+            results.extend(page);
+            match last_page {
+                true => Loop::Break(results),
+                false => Loop::Continue(results),
+            }
+        })
+    })
+}
+
+/// Gets a list from the admin api. Missing resources, like roles not defined in the realm, are empty.
+fn get_list<T: DeserializeOwned + 'static>(
+    token: &str,
+    url: Url,
+) -> impl Future<Item = Vec<T>, Error = Error> {
+    get_json::<Vec<T>>(token, url).map(|list| list.unwrap_or_default())
+}
+
+/// Gets a resource from the admin api, None if it does not exist
+fn get_json<T: DeserializeOwned + 'static>(
+    token: &str,
+    url: Url,
+) -> impl Future<Item = Option<T>, Error = Error> {
+    Client::build()
+        .bearer_auth(token)
+        .finish()
+        .get(url.as_str())
+        .send()
+        .map_err(|err| Error::KeycloakConnectionError(err))
+        .and_then(|mut response| -> Box<dyn Future<Item = Option<T>, Error = Error>> {
+            match response.status() {
+                StatusCode::NOT_FOUND => Box::new(future::ok(None)),
+                status if !status.is_success() => {
+                    Box::new(future::err(Error::KeycloakStatusError(status.as_u16())))
+                }
+                _ => Box::new(
+                    response
+                        .json()
+                        .limit(MAX_RESPONSE_SIZE)
+                        .map(Some)
+                        .map_err(|err| Error::KeycloakJsonError(err)),
+                ),
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::mock_idp::*;
+
+    #[test]
+    fn fetch_all_pages_of_users() {
+        let idp = MockIdp::start();
+        for i in 0..250 {
+            let roles = match i % 2 {
+                0 => vec![roles::ROLE_MEMBER],
+                _ => vec![],
+            };
+            idp.add_user(MockUser::new(&format!("user{}", i), roles));
+        }
+        let cache = KeycloakCache::new();
+        let kc = Keycloak::new(
+            idp.url(),
+            String::from(REALM),
+            String::from(CLIENT_ID),
+            String::from(CLIENT_SECRET),
+            cache.clone(),
+            Duration::from_secs(5),
+            Duration::from_secs(300),
+        );
+
+        System::new("test")
+            .block_on(kc.access_token().and_then(|token| kc.fetch(token)))
+            .unwrap();
+
+        let user = cache.get_user(&String::from("user249")).unwrap().unwrap();
+        assert_eq!(user.username(), "user-user249");
+        assert_eq!(
+            cache.get_roles(&String::from("user248")),
+            vec![String::from(roles::ROLE_MEMBER)]
+        );
+        assert!(cache.get_roles(&String::from("user249")).is_empty());
+    }
+
+    #[test]
+    fn fetch_changes_from_admin_events() {
+        let idp = MockIdp::start();
+        idp.add_user(MockUser::new("unchanged", vec![roles::ROLE_MEMBER]));
+        idp.add_user(MockUser::new("deleted", vec![roles::ROLE_MEMBER]));
+        let cache = KeycloakCache::new();
+        let kc = Keycloak::new(
+            idp.url(),
+            String::from(REALM),
+            String::from(CLIENT_ID),
+            String::from(CLIENT_SECRET),
+            cache.clone(),
+            Duration::from_secs(5),
+            Duration::from_secs(300),
+        );
+        let mut sys = System::new("test");
+        let token = sys.block_on(kc.access_token()).unwrap();
+        sys.block_on(kc.fetch(token.clone())).unwrap();
+        let since = Local::now().timestamp_millis();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        idp.take_user_requests();
+
+        idp.add_user(MockUser::new("added", vec![roles::ROLE_MEMBER]));
+        idp.remove_user("deleted");
+        let newest = sys.block_on(kc.fetch_changes(token, since)).unwrap();
+
+        assert!(newest > since);
+        let user = cache.get_user(&String::from("added")).unwrap().unwrap();
+        assert_eq!(user.username(), "user-added");
+        assert_eq!(
+            cache.get_roles(&String::from("added")),
+            vec![String::from(roles::ROLE_MEMBER)]
+        );
+        assert!(cache.get_user(&String::from("deleted")).unwrap().is_none());
+        assert!(cache.get_roles(&String::from("deleted")).is_empty());
+        assert!(cache
+            .get_user(&String::from("unchanged"))
+            .unwrap()
+            .is_some());
+        let mut requests = idp.take_user_requests();
+        requests.sort();
+        assert_eq!(requests, vec!["users/added", "users/deleted"]);
+    }
+
+    #[test]
+    fn slow_token_exchange_does_not_block_the_sync_actor() {
+        use std::net::TcpListener;
+        use std::thread;
+
+        // Takes connections, but only closes them after two seconds without an answer
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_secs(2));
+                    drop(stream);
+                });
+            }
+        });
+
+        let sys = System::new("test");
+        Keycloak::new(
+            url,
+            String::from(REALM),
+            String::from(CLIENT_ID),
+            String::from(CLIENT_SECRET),
+            KeycloakCache::new(),
+            Duration::from_secs(5),
+            Duration::from_secs(300),
+        )
+        .start();
+        let system = System::current();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            system.stop();
+        });
+
+        // The system only stops once its thread is not stuck in the exchange
+        let started = Instant::now();
+        sys.run().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
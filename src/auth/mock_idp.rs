@@ -0,0 +1,442 @@
+use super::{Claims, JwtVerifier, KeycloakUser};
+use actix::System;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use chrono::{Local, NaiveDate, TimeZone};
+use crate::database::type_aliases::*;
+use jsonwebtoken as jwt;
+use openssl::rsa::Rsa;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use url::Url;
+
+pub const REALM: &str = "liberation";
+pub const AUDIENCE: &str = "liberation-backend";
+pub const CLIENT_ID: &str = "liberation-backend";
+pub const CLIENT_SECRET: &str = "secret";
+const KID: &str = "mock-key";
+
+/// User known to the mock identity provider
+#[derive(Clone, Debug)]
+pub struct MockUser {
+    pub id: ExternalId,
+    pub username: String,
+    pub email: Option<String>,
+    /// Realm roles of the user
+    pub roles: Vec<String>,
+}
+
+impl MockUser {
+    pub fn new(id: &str, roles: Vec<&str>) -> MockUser {
+        MockUser {
+            id: String::from(id),
+            username: format!("user-{}", id),
+            email: Some(format!("{}@example.com", id)),
+            roles: roles.into_iter().map(String::from).collect(),
+        }
+    }
+
+    /// The user as the synchronisation with Keycloak caches it
+    pub fn to_keycloak_user(&self) -> KeycloakUser {
+        serde_json::from_value(self.to_json()).expect("Can not convert mock user")
+    }
+
+    /// Representation of the user in the Keycloak admin api
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "createdTimestamp": 1552348800000u64,
+            "username": self.username,
+            "firstName": null,
+            "lastName": null,
+            "email": self.email,
+            "enabled": true,
+            "totp": false,
+            "emailVerified": true,
+            "disableableCredentialTypes": [],
+            "requiredActions": [],
+            "notBefore": 0,
+            "access": {
+                "manageGroupMembership": true,
+                "view": true,
+                "mapRoles": true,
+                "impersonate": false,
+                "manage": true
+            }
+        })
+    }
+}
+
+/// Claims of a user with the given realm roles
+pub fn claims(uid: &str, roles: Vec<&str>) -> Claims {
+    Claims {
+        uid: String::from(uid),
+        roles: roles.into_iter().map(String::from).collect(),
+        name: format!("user-{}", uid),
+        email: format!("{}@example.com", uid),
+        member_id: None,
+        api_token: None,
+    }
+}
+
+#[derive(Deserialize)]
+struct IntrospectionRequest {
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct EventFilter {
+    /// First day of the events, in the timezone of the provider
+    #[serde(rename = "dateFrom")]
+    date_from: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Page {
+    first: Option<usize>,
+    max: Option<usize>,
+}
+
+impl Page {
+    /// Pages like Keycloak does, returning at most 100 entries by default
+    fn of(&self, list: Vec<Value>) -> Vec<Value> {
+        list.into_iter()
+            .skip(self.first.unwrap_or(0))
+            .take(self.max.unwrap_or(100))
+            .collect()
+    }
+}
+
+/// Claims of an opaque token with the issuer and audience the introspection endpoint reports
+#[derive(Clone)]
+struct OpaqueToken {
+    claims: Claims,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+#[derive(Clone)]
+struct MockState {
+    issuer: String,
+    public_key: (String, String),
+    users: Arc<Mutex<Vec<MockUser>>>,
+    /// Opaque tokens known to the introspection endpoint
+    opaque_tokens: Arc<Mutex<HashMap<String, OpaqueToken>>>,
+    /// Admin events of the changes to the users, oldest first
+    events: Arc<Mutex<Vec<Value>>>,
+    /// Paths of the user resources requested from the admin api
+    user_requests: Arc<Mutex<Vec<String>>>,
+    private_key: Arc<Vec<u8>>,
+}
+
+impl MockState {
+    /// Records an admin event like Keycloak does with saving admin events enabled
+    fn record_event(&self, operation_type: &str, resource_type: &str, resource_path: String) {
+        self.events.lock().unwrap().push(json!({
+            "time": Local::now().timestamp_millis(),
+            "realmId": REALM,
+            "operationType": operation_type,
+            "resourceType": resource_type,
+            "resourcePath": resource_path,
+        }));
+    }
+
+    fn record_user_request(&self, path: String) {
+        self.user_requests.lock().unwrap().push(path);
+    }
+
+    fn mint(&self, claims: &Claims, audience: &str, valid_for: i64) -> String {
+        let payload = serde_json::to_value(claims).expect("Can not serialize claims");
+        self.sign(payload, audience, valid_for)
+    }
+
+    fn issue_opaque(&self, claims: &Claims, issuer: bool, audience: bool) -> String {
+        let token = format!("opaque-{:x}", rand::random::<u64>());
+        let opaque = OpaqueToken {
+            claims: claims.clone(),
+            issuer: Some(self.issuer.clone()).filter(|_| issuer),
+            audience: Some(String::from(AUDIENCE)).filter(|_| audience),
+        };
+        self.opaque_tokens
+            .lock()
+            .unwrap()
+            .insert(token.clone(), opaque);
+        token
+    }
+
+    /// Signs the payload as a token issued by the provider
+    fn sign(&self, mut payload: Value, audience: &str, valid_for: i64) -> String {
+        let mut header = jwt::Header::new(jwt::Algorithm::RS256);
+        header.kid = Some(String::from(KID));
+        payload["iss"] = json!(self.issuer);
+        payload["aud"] = json!(audience);
+        payload["exp"] = json!(Local::now().timestamp() + valid_for);
+        jwt::encode(&header, &payload, self.private_key.as_slice()).expect("Can not sign token")
+    }
+}
+
+/// Local OpenID provider with the parts of the Keycloak admin api used by Liberation,
+/// listening on an ephemeral port until dropped
+pub struct MockIdp {
+    url: Url,
+    state: MockState,
+    system: System,
+}
+
+impl MockIdp {
+    pub fn start() -> MockIdp {
+        let rsa = Rsa::generate(2048).expect("Can not generate RSA key");
+        let encode = |bytes: Vec<u8>| base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD);
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Can not bind mock identity provider");
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let state = MockState {
+            issuer: format!("{}realms/{}", url, REALM),
+            public_key: (encode(rsa.n().to_vec()), encode(rsa.e().to_vec())),
+            users: Arc::new(Mutex::new(Vec::new())),
+            opaque_tokens: Arc::new(Mutex::new(HashMap::new())),
+            events: Arc::new(Mutex::new(Vec::new())),
+            user_requests: Arc::new(Mutex::new(Vec::new())),
+            private_key: Arc::new(rsa.private_key_to_der().expect("Can not encode RSA key")),
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let server_state = state.clone();
+        thread::spawn(move || {
+            let sys = System::new("mock-idp");
+            HttpServer::new(move || {
+                App::new()
+                    .data(server_state.clone())
+                    .service(
+                        web::scope(&format!("/realms/{}", REALM))
+                            .route("/.well-known/openid-configuration", web::get().to(discovery))
+                            .route("/protocol/openid-connect/certs", web::get().to(certs))
+                            .route("/protocol/openid-connect/token", web::post().to(token))
+                            .route(
+                                "/protocol/openid-connect/token/introspect",
+                                web::post().to(introspect),
+                            ),
+                    )
+                    .service(
+                        web::scope(&format!("/admin/realms/{}", REALM))
+                            .route("/users", web::get().to(users))
+                            .route("/users/{id}", web::get().to(user))
+                            .route("/roles/{role}/users", web::get().to(role_users))
+                            .route("/admin-events", web::get().to(admin_events)),
+                    )
+            })
+            .workers(1)
+            .listen(listener)
+            .expect("Can not start mock identity provider")
+            .start();
+            sender.send(System::current()).unwrap();
+            sys.run().unwrap();
+        });
+
+        let system = receiver.recv().expect("Mock identity provider did not start");
+        MockIdp { url, state, system }
+    }
+
+    /// Base url, like the Keycloak url in the settings
+    pub fn url(&self) -> Url {
+        self.url.clone()
+    }
+
+    pub fn issuer(&self) -> String {
+        self.state.issuer.clone()
+    }
+
+    /// Verifier accepting the tokens minted for `AUDIENCE`
+    pub fn verifier(&self) -> JwtVerifier {
+        JwtVerifier::new(self.issuer(), String::from(AUDIENCE))
+    }
+
+    /// Runs the discovery and fetches the signing keys, like the OidcProvider actor does
+    pub fn fetch_keys(&self, verifier: &JwtVerifier) {
+        actix_web::test::block_on(super::oidc::fetch_keys(verifier.clone()))
+            .expect("Can not fetch the keys of the mock identity provider");
+    }
+
+    /// Adds a user and records the admin events of creating it and mapping its roles
+    pub fn add_user(&self, user: MockUser) {
+        let path = format!("users/{}", user.id);
+        self.state.record_event("CREATE", "USER", path.clone());
+        if !user.roles.is_empty() {
+            self.state.record_event(
+                "CREATE",
+                "REALM_ROLE_MAPPING",
+                format!("{}/role-mappings/realm", path),
+            );
+        }
+        self.state.users.lock().unwrap().push(user);
+    }
+
+    /// Deletes a user and records the admin event of it
+    pub fn remove_user(&self, id: &str) {
+        self.state
+            .users
+            .lock()
+            .unwrap()
+            .retain(|user| user.id != id);
+        self.state
+            .record_event("DELETE", "USER", format!("users/{}", id));
+    }
+
+    /// Paths of the user resources requested from the admin api since the last call,
+    /// `users` for the list of all users
+    pub fn take_user_requests(&self) -> Vec<String> {
+        self.state.user_requests.lock().unwrap().drain(..).collect()
+    }
+
+    /// Signed access token with the given claims, valid for a minute
+    pub fn mint(&self, claims: &Claims) -> String {
+        self.state.mint(claims, AUDIENCE, 60)
+    }
+
+    /// Signed access token for another audience or lifetime in seconds, negative for expired tokens
+    pub fn mint_for(&self, claims: &Claims, audience: &str, valid_for: i64) -> String {
+        self.state.mint(claims, audience, valid_for)
+    }
+
+    /// Signed access token with the claims of the given payload, valid for a minute
+    pub fn mint_payload(&self, payload: Value) -> String {
+        self.state.sign(payload, AUDIENCE, 60)
+    }
+
+    /// Opaque access token with the given claims, only known to the introspection endpoint
+    pub fn issue_opaque(&self, claims: &Claims) -> String {
+        self.state.issue_opaque(claims, true, true)
+    }
+
+    /// Opaque access token the introspection endpoint reports without audience
+    pub fn issue_opaque_without_audience(&self, claims: &Claims) -> String {
+        self.state.issue_opaque(claims, true, false)
+    }
+
+    /// Opaque access token the introspection endpoint reports without issuer
+    pub fn issue_opaque_without_issuer(&self, claims: &Claims) -> String {
+        self.state.issue_opaque(claims, false, true)
+    }
+
+    /// Forgets an opaque token, introspecting it then reports it as inactive
+    pub fn revoke(&self, token: &str) {
+        self.state.opaque_tokens.lock().unwrap().remove(token);
+    }
+}
+
+impl Drop for MockIdp {
+    fn drop(&mut self) {
+        self.system.stop();
+    }
+}
+
+fn discovery(state: web::Data<MockState>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "issuer": state.issuer,
+        "jwks_uri": format!("{}/protocol/openid-connect/certs", state.issuer),
+        "token_endpoint": format!("{}/protocol/openid-connect/token", state.issuer),
+        "introspection_endpoint": format!("{}/protocol/openid-connect/token/introspect", state.issuer),
+    }))
+}
+
+fn certs(state: web::Data<MockState>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "keys": [{
+            "kid": KID,
+            "kty": "RSA",
+            "alg": "RS256",
+            "use": "sig",
+            "n": state.public_key.0,
+            "e": state.public_key.1,
+        }]
+    }))
+}
+
+/// Client credentials grant, the credentials are not checked
+fn token(state: web::Data<MockState>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "access_token": state.mint(&claims("service-account", vec![]), AUDIENCE, 300),
+        "token_type": "bearer",
+        "expires_in": 300,
+    }))
+}
+
+/// RFC 7662 introspection of opaque tokens, the client credentials are not checked
+fn introspect(state: web::Data<MockState>, form: web::Form<IntrospectionRequest>) -> HttpResponse {
+    match state.opaque_tokens.lock().unwrap().get(&form.token) {
+        Some(OpaqueToken {
+            claims,
+            issuer,
+            audience,
+        }) => HttpResponse::Ok().json(json!({
+            "active": true,
+            "uid": claims.uid,
+            "roles": claims.roles,
+            "name": claims.name,
+            "email": claims.email,
+            "iss": issuer,
+            "aud": audience,
+            "exp": Local::now().timestamp() + 60,
+        })),
+        None => HttpResponse::Ok().json(json!({ "active": false })),
+    }
+}
+
+fn users(state: web::Data<MockState>, page: web::Query<Page>) -> HttpResponse {
+    state.record_user_request(String::from("users"));
+    let users = state.users.lock().unwrap();
+    HttpResponse::Ok().json(page.of(users.iter().map(MockUser::to_json).collect()))
+}
+
+fn user(state: web::Data<MockState>, req: HttpRequest) -> HttpResponse {
+    let id = req.match_info().get("id").unwrap_or_default();
+    state.record_user_request(format!("users/{}", id));
+    let users = state.users.lock().unwrap();
+    match users.iter().find(|user| user.id == id) {
+        Some(user) => HttpResponse::Ok().json(user.to_json()),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+fn role_users(
+    state: web::Data<MockState>,
+    req: HttpRequest,
+    page: web::Query<Page>,
+) -> HttpResponse {
+    let role = req.match_info().get("role").unwrap_or_default();
+    let users = state.users.lock().unwrap();
+    HttpResponse::Ok().json(
+        page.of(users
+            .iter()
+            .filter(|user| user.roles.iter().any(|r| r == role))
+            .map(MockUser::to_json)
+            .collect()),
+    )
+}
+
+/// Admin events from the start of `dateFrom` on, newest first like Keycloak lists them.
+/// The resource types are not filtered, the mock only records events of users.
+fn admin_events(
+    state: web::Data<MockState>,
+    filter: web::Query<EventFilter>,
+    page: web::Query<Page>,
+) -> HttpResponse {
+    let from = filter
+        .date_from
+        .as_ref()
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        .and_then(|date| Local.from_local_date(&date).single())
+        .map_or(0, |date| date.and_hms(0, 0, 0).timestamp_millis());
+    let events = state.events.lock().unwrap();
+    HttpResponse::Ok().json(
+        page.of(events
+            .iter()
+            .rev()
+            .filter(|event| event["time"].as_i64().unwrap_or(0) >= from)
+            .cloned()
+            .collect()),
+    )
+}
@@ -0,0 +1,153 @@
+use actix_web::{http, HttpMessage, HttpRequest};
+use crate::api::AppState;
+use crate::database::type_aliases::*;
+use crate::error::Error;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+pub mod api_token;
+mod introspection;
+mod keycloak;
+#[cfg(test)]
+pub mod mock_idp;
+mod oidc;
+pub mod policy;
+
+pub use self::introspection::{IntrospectTokens, IntrospectedClaims, Introspector};
+pub use self::keycloak::{Keycloak, KeycloakCache, KeycloakUser, SyncHealth};
+pub use self::oidc::{JwtVerifier, OidcProvider, ProviderMetadata};
+
+pub mod roles {
+    pub const ROLE_ADMIN: &str = "admin";
+    pub const ROLE_LIBRARIAN: &str = "librarian";
+    pub const ROLE_MEMBER: &str = "member";
+    pub const ROLE_ARISTOCRAT: &str = "aristocrat";
+    pub const ROLE_DEVELOPER: &str = "developer";
+
+    /// Realm roles known to Liberation
+    pub const ROLES: [&str; 5] = [
+        ROLE_ADMIN,
+        ROLE_LIBRARIAN,
+        ROLE_MEMBER,
+        ROLE_ARISTOCRAT,
+        ROLE_DEVELOPER,
+    ];
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Claims {
+    pub uid: String,
+    pub roles: Vec<String>,
+    pub name: String,
+    pub email: String,
+    // ... whatever!
+    /// Id of the member provisioned for the token's subject, not part of the token
+    #[serde(skip)]
+    pub member_id: Option<MemberId>,
+    /// Id of the api token the request was authenticated with, None for JWTs
+    #[serde(skip)]
+    pub api_token: Option<ApiTokenId>,
+}
+
+/// Claim holding the realm roles unless configured otherwise
+pub const DEFAULT_ROLES_CLAIM: &str = "roles";
+
+impl Claims {
+    /// Claims from the payload of a token. The `uid` claim falls back to the standard `sub`,
+    /// the roles are read from the claim at the dot separated path `roles_claim`.
+    pub fn from_token(payload: &Value, roles_claim: &str) -> Option<Claims> {
+        let string = |claim: &str| payload.get(claim).and_then(Value::as_str).map(String::from);
+        let roles = roles_claim
+            .split('.')
+            .try_fold(payload, |value, claim| value.get(claim))
+            .and_then(Value::as_array)
+            .map(|roles| {
+                roles
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(Claims {
+            uid: string("uid").or_else(|| string("sub"))?,
+            roles,
+            name: string("name")
+                .or_else(|| string("preferred_username"))
+                .unwrap_or_default(),
+            email: string("email").unwrap_or_default(),
+            member_id: None,
+            api_token: None,
+        })
+    }
+}
+
+pub fn get_claims_for_req(req: &HttpRequest) -> Result<Option<Claims>, Error> {
+    match req.headers().get(http::header::AUTHORIZATION) {
+        None => {
+            debug!("No Authorization Header provided");
+            Ok(None)
+        }
+        Some(header_val) => match header_val.to_str() {
+            Err(_) => {
+                debug!("Authorization header could not be converted to string");
+                Err(Error::InvalidAuthenticationError)
+            }
+            Ok(auth_str) => {
+                if auth_str.starts_with("Bearer ") {
+                    let token = auth_str.replacen("Bearer ", "", 1);
+                    let state = req
+                        .app_data::<AppState>()
+                        .expect("Expected app state is missing!");
+                    if token.starts_with(api_token::API_TOKEN_PREFIX) {
+                        api_token::get_claims_for_api_token(&state.db, &state.kc, &token)
+                            .map(|claims| Some(claims))
+                    } else if let Some(IntrospectedClaims(claims)) = req.extensions().get() {
+                        Ok(Some(claims.clone()))
+                    } else {
+                        state.verifier.verify(&token).map(|claims| Some(claims))
+                    }
+                } else {
+                    Err(Error::InvalidAuthenticationError)
+                }
+            }
+        },
+    }
+}
+
+/// Creates the member for the token's subject on first login and remembers its id in the claims
+fn provision_member(req: &HttpRequest, claims: &mut Claims) -> Result<(), Error> {
+    let db = &req
+        .app_data::<AppState>()
+        .expect("Expected app state is missing!")
+        .db;
+    claims.member_id = Some(db.provision_member(&claims.uid)?);
+    Ok(())
+}
+
+pub fn assert_roles(req: &HttpRequest, roles: Vec<&str>) -> Result<Option<Claims>, Error> {
+    let claims = get_claims_for_req(req)?;
+
+    let claims = match roles.is_empty() {
+        true => claims,
+        false => match claims {
+            Some(cl) => {
+                if !roles.iter().any(|role| cl.roles.contains(&String::from(*role))) {
+                    return Err(Error::YouShallNotPassError);
+                }
+                Some(cl)
+            }
+            None => return Err(Error::SpeakFriendAndEnterError),
+        },
+    };
+
+    match claims {
+        Some(mut cl) => {
+            if cl.member_id.is_none() {
+                provision_member(req, &mut cl)?;
+            }
+            Ok(Some(cl))
+        }
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,462 @@
+use super::{Claims, Introspector, DEFAULT_ROLES_CLAIM};
+use actix::prelude::*;
+use actix_web::client::Client;
+use crate::error::Error;
+use crate::settings::Oidc as OidcSettings;
+use futures::{future, Future, future::lazy};
+use jsonwebtoken as jwt;
+use openssl::bn::BigNum;
+use openssl::rsa::Rsa;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Subset of the OpenID provider metadata used by Liberation
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub jwks_uri: String,
+    pub token_endpoint: Option<String>,
+    pub introspection_endpoint: Option<String>,
+}
+
+/// Signing keys published by the provider
+#[derive(Deserialize, Debug)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+/// A single JSON Web Key, only RSA signing keys are used
+#[derive(Deserialize, Debug)]
+struct Jwk {
+    kid: String,
+    kty: String,
+    #[serde(rename = "use")]
+    usage: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+impl Jwk {
+    /// Converts an RSA signing key into the DER encoded PKCS#1 format expected by jsonwebtoken
+    fn to_der(&self) -> Option<Vec<u8>> {
+        if self.kty != "RSA" || self.usage.as_ref().map_or(false, |usage| usage != "sig") {
+            return None;
+        }
+        let n = base64::decode_config(self.n.as_ref()?, base64::URL_SAFE_NO_PAD).ok()?;
+        let e = base64::decode_config(self.e.as_ref()?, base64::URL_SAFE_NO_PAD).ok()?;
+        Rsa::from_public_components(BigNum::from_slice(&n).ok()?, BigNum::from_slice(&e).ok()?)
+            .and_then(|rsa| rsa.public_key_to_der_pkcs1())
+            .ok()
+    }
+}
+
+/// Whether the audience claim of a token names the given audience
+pub(super) fn has_audience(aud: Option<&Value>, audience: &str) -> bool {
+    match aud {
+        Some(Value::String(aud)) => aud == audience,
+        Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+        _ => false,
+    }
+}
+
+/// Verifies access tokens against the cached signing keys of the provider.
+/// Unknown key ids request a refresh of the keys, which the `OidcProvider` actor picks up.
+#[derive(Clone, Debug)]
+pub struct JwtVerifier {
+    issuer: String,
+    audience: String,
+    /// Dot separated path of the claim holding the realm roles
+    roles_claim: String,
+    metadata: Arc<Mutex<Option<ProviderMetadata>>>,
+    keys: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    refresh_requested: Arc<AtomicBool>,
+    /// Validates tokens that are no JWTs, if configured
+    introspector: Option<Introspector>,
+}
+
+impl JwtVerifier {
+    pub fn new(issuer: String, audience: String) -> JwtVerifier {
+        JwtVerifier {
+            issuer,
+            audience,
+            roles_claim: String::from(DEFAULT_ROLES_CLAIM),
+            metadata: Arc::new(Mutex::new(None)),
+            keys: Arc::new(Mutex::new(HashMap::new())),
+            refresh_requested: Arc::new(AtomicBool::new(true)),
+            introspector: None,
+        }
+    }
+
+    /// Accept opaque tokens as well, validating them at the introspection endpoint
+    pub fn with_introspection(self, introspector: Introspector) -> JwtVerifier {
+        JwtVerifier {
+            introspector: Some(introspector),
+            ..self
+        }
+    }
+
+    /// Read the realm roles from another claim, nested claims are separated by dots
+    pub fn with_roles_claim(self, roles_claim: String) -> JwtVerifier {
+        JwtVerifier {
+            roles_claim,
+            ..self
+        }
+    }
+
+    pub fn from_settings(settings: &OidcSettings) -> JwtVerifier {
+        JwtVerifier::new(settings.issuer.clone(), settings.audience.clone())
+            .with_roles_claim(settings.rolesclaim.clone())
+    }
+
+    /// Provider metadata, None until the discovery succeeded
+    pub fn metadata(&self) -> Option<ProviderMetadata> {
+        self.metadata
+            .lock()
+            .expect("Can not lock provider metadata mutex.")
+            .clone()
+    }
+
+    fn set_metadata(&self, metadata: ProviderMetadata) {
+        *self
+            .metadata
+            .lock()
+            .expect("Can not lock provider metadata mutex.") = Some(metadata);
+    }
+
+    /// Replaces the cached keys with the RSA signing keys of the given set
+    fn set_keys(&self, jwks: JwkSet) {
+        let keys: HashMap<String, Vec<u8>> = jwks
+            .keys
+            .iter()
+            .filter_map(|jwk| jwk.to_der().map(|der| (jwk.kid.clone(), der)))
+            .collect();
+        debug!("cached {} signing keys", keys.len());
+        *self.keys.lock().expect("Can not lock signing keys mutex.") = keys;
+    }
+
+    /// Asks the `OidcProvider` actor to fetch the signing keys again
+    pub fn request_refresh(&self) {
+        self.refresh_requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether a refresh of the keys has been requested since the last call
+    fn take_refresh_request(&self) -> bool {
+        self.refresh_requested.swap(false, Ordering::SeqCst)
+    }
+
+    /// Gets the key with the given id. Tokens without key id are accepted if there is only one key.
+    fn get_key(&self, kid: Option<&String>) -> Option<Vec<u8>> {
+        let keys = self.keys.lock().expect("Can not lock signing keys mutex.");
+        match kid {
+            Some(kid) => keys.get(kid).cloned(),
+            None if keys.len() == 1 => keys.values().next().cloned(),
+            None => None,
+        }
+    }
+
+    /// Checks signature, issuer, audience and expiry of the token and returns its claims
+    pub fn verify(&self, token: &str) -> Result<Claims, Error> {
+        let header = match jwt::decode_header(token) {
+            Ok(header) => header,
+            Err(e) => {
+                debug!("JWT header is invalid: {:?}", e);
+                return Err(Error::InvalidAuthenticationError);
+            }
+        };
+
+        let key = match self.get_key(header.kid.as_ref()) {
+            Some(key) => key,
+            None => {
+                warn!("No signing key for kid {:?}, requesting refresh", header.kid);
+                self.request_refresh();
+                return Err(Error::InvalidAuthenticationError);
+            }
+        };
+
+        // jsonwebtoken only compares the audience as a whole, which fails for lists
+        let mut validation = jwt::Validation::new(jwt::Algorithm::RS256);
+        validation.iss = Some(self.issuer.clone());
+
+        let payload = match jwt::decode::<Value>(token, key.as_slice(), &validation) {
+            Ok(token_data) => token_data.claims,
+            Err(e) => {
+                error!("JWT validation failed: {:?}", e);
+                return Err(Error::InvalidAuthenticationError);
+            }
+        };
+        if !has_audience(payload.get("aud"), &self.audience) {
+            error!("JWT validation failed: audience {:?}", payload.get("aud"));
+            return Err(Error::InvalidAuthenticationError);
+        }
+        match Claims::from_token(&payload, &self.roles_claim) {
+            Some(claims) => {
+                debug!("Successfully verified JWT: {:?}", claims);
+                Ok(claims)
+            }
+            None => {
+                error!("JWT validation failed: neither uid nor sub claim");
+                Err(Error::InvalidAuthenticationError)
+            }
+        }
+    }
+
+    /// Validates an opaque token at the introspection endpoint of the provider.
+    /// None for JWTs and if introspection is not configured.
+    pub fn introspect(&self, token: &str) -> Option<Box<dyn Future<Item = Claims, Error = Error>>> {
+        let introspector = self.introspector.as_ref()?;
+        if jwt::decode_header(token).is_ok() {
+            return None;
+        }
+        match self
+            .metadata()
+            .and_then(|metadata| metadata.introspection_endpoint)
+        {
+            Some(endpoint) => Some(introspector.introspect(
+                &endpoint,
+                &self.issuer,
+                &self.audience,
+                &self.roles_claim,
+                token,
+            )),
+            None => {
+                warn!("No introspection endpoint known for opaque token");
+                Some(Box::new(future::err(Error::InvalidAuthenticationError)))
+            }
+        }
+    }
+}
+
+/// Keeps the provider metadata and signing keys of a `JwtVerifier` up to date
+pub struct OidcProvider {
+    verifier: JwtVerifier,
+}
+
+impl Actor for OidcProvider {
+    type Context = Context<OidcProvider>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(Duration::from_millis(500), OidcProvider::refresh_if_requested);
+        ctx.run_interval(Duration::new(60, 0), |provider, _ctx| {
+            provider.verifier.request_refresh()
+        });
+    }
+}
+
+impl OidcProvider {
+    pub fn new(verifier: JwtVerifier) -> Self {
+        OidcProvider { verifier }
+    }
+
+    fn refresh_if_requested(provider: &mut Self, _ctx: &mut Context<OidcProvider>) {
+        if provider.verifier.take_refresh_request() {
+            provider.refresh();
+        }
+    }
+
+    fn refresh(&self) {
+        let verifier = self.verifier.clone();
+        Arbiter::spawn(lazy(move || {
+            fetch_keys(verifier).map_err(|err| error!("Fetching signing keys failed: {:?}", err))
+        }));
+    }
+}
+
+/// Fetches the signing keys, running the discovery first if it did not succeed yet
+pub(super) fn fetch_keys(verifier: JwtVerifier) -> impl Future<Item = (), Error = Error> {
+    let metadata: Box<dyn Future<Item = ProviderMetadata, Error = Error>> =
+        match verifier.metadata() {
+            Some(metadata) => Box::new(future::ok(metadata)),
+            None => Box::new(discover(verifier.clone())),
+        };
+
+    metadata
+        .and_then(|metadata| get_json::<JwkSet>(&metadata.jwks_uri))
+        .map(move |jwks| verifier.set_keys(jwks))
+}
+
+/// Fetches the provider metadata from the discovery endpoint of the issuer
+fn discover(verifier: JwtVerifier) -> impl Future<Item = ProviderMetadata, Error = Error> {
+    let issuer = verifier.issuer.trim_end_matches('/').to_string();
+    debug!("discovering openid provider {}...", issuer);
+
+    get_json::<ProviderMetadata>(&format!("{}/.well-known/openid-configuration", issuer))
+        .and_then(move |metadata| {
+            if metadata.issuer.trim_end_matches('/') != issuer {
+                return Err(Error::OidcIssuerMismatch(metadata.issuer));
+            }
+            trace!("openid provider metadata: {:?}", metadata);
+            verifier.set_metadata(metadata.clone());
+            Ok(metadata)
+        })
+}
+
+fn get_json<T: DeserializeOwned + 'static>(url: &str) -> impl Future<Item = T, Error = Error> {
+    Client::default()
+        .get(url)
+        .send()
+        .map_err(|err| Error::OidcConnectionError(err))
+        .and_then(|mut response| response.json().map_err(|err| Error::OidcJsonError(err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::mock_idp::*;
+
+    #[test]
+    fn verify_token_of_mock_provider() {
+        let idp = MockIdp::start();
+        let verifier = idp.verifier();
+        System::new("test")
+            .block_on(fetch_keys(verifier.clone()))
+            .unwrap();
+
+        let token = idp.mint(&claims("external_id", vec!["member"]));
+        let verified = verifier.verify(&token).unwrap();
+        assert_eq!(verified.uid, "external_id");
+        assert_eq!(verified.roles, vec![String::from("member")]);
+    }
+
+    #[test]
+    fn verify_token_with_standard_claims() {
+        let idp = MockIdp::start();
+        let verifier = idp
+            .verifier()
+            .with_roles_claim(String::from("realm_access.roles"));
+        System::new("test")
+            .block_on(fetch_keys(verifier.clone()))
+            .unwrap();
+
+        let token = idp.mint_payload(serde_json::json!({
+            "sub": "external_id",
+            "preferred_username": "user",
+            "realm_access": { "roles": ["member", "librarian"] },
+        }));
+        let verified = verifier.verify(&token).unwrap();
+        assert_eq!(verified.uid, "external_id");
+        assert_eq!(verified.name, "user");
+        assert_eq!(verified.roles, vec!["member", "librarian"]);
+
+        // Neither uid nor sub
+        let token = idp.mint_payload(serde_json::json!({ "name": "user" }));
+        match verifier.verify(&token) {
+            Err(Error::InvalidAuthenticationError) => (),
+            _ => panic!("Expected Error::InvalidAuthenticationError"),
+        }
+    }
+
+    #[test]
+    fn verify_opaque_token_by_introspection() {
+        let idp = MockIdp::start();
+        let verifier = idp.verifier().with_introspection(Introspector::new(
+            String::from(CLIENT_ID),
+            String::from(CLIENT_SECRET),
+        ));
+        let mut sys = System::new("test");
+        sys.block_on(fetch_keys(verifier.clone())).unwrap();
+
+        let token = idp.issue_opaque(&claims("external_id", vec!["member"]));
+        let mut introspect = |token: &str| sys.block_on(verifier.introspect(token).unwrap());
+        assert_eq!(introspect(&token).unwrap().uid, "external_id");
+        // The claims are cached until the token expires
+        idp.revoke(&token);
+        assert_eq!(introspect(&token).unwrap().uid, "external_id");
+
+        match introspect("opaque-unknown") {
+            Err(Error::InvalidAuthenticationError) => (),
+            _ => panic!("Expected Error::InvalidAuthenticationError"),
+        }
+        // JWTs are verified by their signature
+        assert!(verifier
+            .introspect(&idp.mint(&claims("external_id", vec![])))
+            .is_none());
+    }
+
+    #[test]
+    fn introspection_requires_the_audience_unless_optional() {
+        let idp = MockIdp::start();
+        let introspector = Introspector::new(String::from(CLIENT_ID), String::from(CLIENT_SECRET));
+        let mut sys = System::new("test");
+        for (optional, valid) in &[(false, false), (true, true)] {
+            let verifier = idp
+                .verifier()
+                .with_introspection(introspector.clone().with_optional_audience(*optional));
+            sys.block_on(fetch_keys(verifier.clone())).unwrap();
+
+            let token = idp.issue_opaque_without_audience(&claims("external_id", vec![]));
+            let result = sys.block_on(verifier.introspect(&token).unwrap());
+            assert_eq!(result.is_ok(), *valid, "optional audience {}", optional);
+        }
+    }
+
+    #[test]
+    fn introspection_requires_the_issuer() {
+        let idp = MockIdp::start();
+        let introspector = Introspector::new(String::from(CLIENT_ID), String::from(CLIENT_SECRET))
+            .with_optional_audience(true);
+        let verifier = idp.verifier().with_introspection(introspector);
+        let mut sys = System::new("test");
+        sys.block_on(fetch_keys(verifier.clone())).unwrap();
+
+        let token = idp.issue_opaque_without_issuer(&claims("external_id", vec![]));
+        match sys.block_on(verifier.introspect(&token).unwrap()) {
+            Err(Error::InvalidAuthenticationError) => (),
+            _ => panic!("Expected Error::InvalidAuthenticationError"),
+        }
+    }
+
+    #[test]
+    fn middleware_passes_introspected_claims_to_the_handler() {
+        use crate::auth::{IntrospectTokens, IntrospectedClaims};
+        use actix_web::{test, web, App, HttpRequest};
+
+        let idp = MockIdp::start();
+        let verifier = idp.verifier().with_introspection(Introspector::new(
+            String::from(CLIENT_ID),
+            String::from(CLIENT_SECRET),
+        ));
+        test::block_on(fetch_keys(verifier.clone())).unwrap();
+        let mut app = test::init_service(App::new().wrap(IntrospectTokens::new(verifier)).route(
+            "/",
+            web::get().to(|req: HttpRequest| match req.extensions().get() {
+                Some(IntrospectedClaims(claims)) => claims.uid.clone(),
+                None => String::new(),
+            }),
+        ));
+
+        let token = idp.issue_opaque(&claims("external_id", vec!["member"]));
+        for (token, uid) in &[(token.as_str(), "external_id"), ("opaque-unknown", "")] {
+            let req = test::TestRequest::get()
+                .uri("/")
+                .header("Authorization", format!("Bearer {}", token))
+                .to_request();
+            assert_eq!(test::read_response(&mut app, req), uid.as_bytes());
+        }
+    }
+
+    #[test]
+    fn verify_rejects_other_audience_and_expired_tokens() {
+        let idp = MockIdp::start();
+        let verifier = idp.verifier();
+        System::new("test")
+            .block_on(fetch_keys(verifier.clone()))
+            .unwrap();
+
+        let claims = claims("external_id", vec!["member"]);
+        for token in vec![
+            idp.mint_for(&claims, "other-client", 60),
+            idp.mint_for(&claims, AUDIENCE, -120),
+        ] {
+            match verifier.verify(&token) {
+                Err(Error::InvalidAuthenticationError) => (),
+                _ => panic!("Expected Error::InvalidAuthenticationError"),
+            }
+        }
+    }
+}
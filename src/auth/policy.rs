@@ -0,0 +1,248 @@
+use super::roles::*;
+use super::Claims;
+use crate::error::Error;
+
+/// Things a user can do in Liberation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// See RPG systems and titles, also without login
+    ReadCatalogue,
+    /// Add RPG systems and titles
+    CreateCatalogue,
+    /// Edit and delete RPG systems and titles
+    ManageCatalogue,
+    ReadBooks,
+    CreateBook,
+    UpdateBook,
+    DeleteBook,
+    ReadRental,
+    CheckoutBook,
+    ReturnRental,
+    ExtendRental,
+    ReadOverdueRentals,
+    ReadReservations,
+    Reserve,
+    CancelReservation,
+    ReadMembers,
+    /// See username, email, enabled state and roles of a member
+    ReadMemberDetails,
+    ReadInventory,
+    AddToMemberInventory,
+    AddToGuildInventory,
+    ReadGuilds,
+    CreateGuild,
+    UpdateGuild,
+    DeleteGuild,
+    ReadGuildMembers,
+    /// Add and remove members of a guild and change their guild roles
+    ManageGuildMembers,
+    /// See the error messages of the Keycloak synchronisation
+    ReadHealthDetails,
+}
+
+/// Relationship of the logged in member to the resource of an action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    /// The resource belongs to the member: an owned book, the own inventory, profile or reservation
+    Owner,
+    /// The member is the contact person of the guild
+    Contact,
+    /// The member runs the inventory of the guild the resource belongs to
+    GuildLibrarian,
+    /// The member currently rents the book
+    Rentee,
+}
+
+/// Decides whether a user with the given roles may perform the action
+pub fn is_allowed(roles: &[String], action: Action, relations: &[Relation]) -> bool {
+    let has = |role: &str| roles.iter().any(|r| r == role);
+    let is = |relation: Relation| relations.contains(&relation);
+
+    if has(ROLE_ADMIN) {
+        return true;
+    }
+    let librarian = has(ROLE_LIBRARIAN);
+    let member = has(ROLE_MEMBER);
+    let aristocrat = has(ROLE_ARISTOCRAT);
+
+    match action {
+        Action::ReadCatalogue => true,
+        Action::CreateCatalogue => librarian || member,
+        Action::ManageCatalogue => librarian,
+        Action::ReadBooks => librarian || member,
+        Action::CreateBook | Action::UpdateBook => {
+            librarian || (member && (is(Relation::Owner) || is(Relation::GuildLibrarian)))
+        }
+        Action::DeleteBook => librarian,
+        Action::ReadRental => {
+            librarian
+                || (member
+                    && (is(Relation::Owner)
+                        || is(Relation::Rentee)
+                        || is(Relation::GuildLibrarian)))
+        }
+        Action::CheckoutBook | Action::ReturnRental => {
+            librarian || (member && is(Relation::GuildLibrarian))
+        }
+        Action::ReadOverdueRentals => librarian,
+        Action::ExtendRental => librarian || (member && is(Relation::Rentee)),
+        Action::ReadReservations => librarian || member,
+        Action::Reserve => member,
+        Action::CancelReservation => librarian || (member && is(Relation::Owner)),
+        Action::ReadMembers => librarian || member || aristocrat,
+        Action::ReadMemberDetails => librarian || is(Relation::Owner),
+        Action::ReadInventory => librarian || member,
+        Action::AddToMemberInventory => member && is(Relation::Owner),
+        Action::AddToGuildInventory => librarian || (member && is(Relation::GuildLibrarian)),
+        Action::ReadGuilds => librarian || member || aristocrat,
+        Action::CreateGuild => aristocrat,
+        Action::UpdateGuild | Action::DeleteGuild | Action::ManageGuildMembers => {
+            aristocrat || (member && is(Relation::Contact))
+        }
+        Action::ReadGuildMembers => librarian || member || aristocrat,
+        Action::ReadHealthDetails => false,
+    }
+}
+
+/// Whether users who are not logged in may perform the action
+fn is_public(action: Action) -> bool {
+    action == Action::ReadCatalogue
+}
+
+/// Whether the logged in user may perform the action
+pub fn permits(claims: &Option<Claims>, action: Action, relations: &[Relation]) -> bool {
+    match claims {
+        None => is_public(action),
+        Some(claims) => is_allowed(&claims.roles, action, relations),
+    }
+}
+
+/// Fails unless the logged in user may perform the action
+pub fn authorize(claims: &Option<Claims>, action: Action, relations: &[Relation]) -> Result<(), Error> {
+    match claims {
+        None if is_public(action) => Ok(()),
+        None => Err(Error::SpeakFriendAndEnterError),
+        Some(_) if permits(claims, action, relations) => Ok(()),
+        Some(_) => Err(Error::YouShallNotPassError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONE: &[Relation] = &[];
+    const OWNER: &[Relation] = &[Relation::Owner];
+    const CONTACT: &[Relation] = &[Relation::Contact];
+    const RENTEE: &[Relation] = &[Relation::Rentee];
+    const GUILD_LIBRARIAN: &[Relation] = &[Relation::GuildLibrarian];
+
+    /// Expected decisions for admin, librarian, member, aristocrat and a user without roles
+    const MATRIX: &[(Action, &[Relation], [bool; 5])] = &[
+        (Action::ReadCatalogue, NONE, [true, true, true, true, true]),
+        (Action::CreateCatalogue, NONE, [true, true, true, false, false]),
+        (Action::ManageCatalogue, NONE, [true, true, false, false, false]),
+        (Action::ReadBooks, NONE, [true, true, true, false, false]),
+        (Action::CreateBook, NONE, [true, true, false, false, false]),
+        (Action::CreateBook, OWNER, [true, true, true, false, false]),
+        (Action::UpdateBook, NONE, [true, true, false, false, false]),
+        (Action::UpdateBook, OWNER, [true, true, true, false, false]),
+        (Action::UpdateBook, RENTEE, [true, true, false, false, false]),
+        (Action::UpdateBook, GUILD_LIBRARIAN, [true, true, true, false, false]),
+        (Action::DeleteBook, OWNER, [true, true, false, false, false]),
+        (Action::ReadRental, NONE, [true, true, false, false, false]),
+        (Action::ReadRental, OWNER, [true, true, true, false, false]),
+        (Action::ReadRental, RENTEE, [true, true, true, false, false]),
+        (Action::ReadRental, GUILD_LIBRARIAN, [true, true, true, false, false]),
+        (Action::CheckoutBook, OWNER, [true, true, false, false, false]),
+        (Action::CheckoutBook, GUILD_LIBRARIAN, [true, true, true, false, false]),
+        (Action::ReturnRental, RENTEE, [true, true, false, false, false]),
+        (Action::ReturnRental, GUILD_LIBRARIAN, [true, true, true, false, false]),
+        (Action::ExtendRental, NONE, [true, true, false, false, false]),
+        (Action::ExtendRental, OWNER, [true, true, false, false, false]),
+        (Action::ExtendRental, RENTEE, [true, true, true, false, false]),
+        (Action::ReadOverdueRentals, NONE, [true, true, false, false, false]),
+        (Action::ReadReservations, NONE, [true, true, true, false, false]),
+        (Action::Reserve, NONE, [true, false, true, false, false]),
+        (Action::CancelReservation, NONE, [true, true, false, false, false]),
+        (Action::CancelReservation, OWNER, [true, true, true, false, false]),
+        (Action::ReadMembers, NONE, [true, true, true, true, false]),
+        (Action::ReadMemberDetails, NONE, [true, true, false, false, false]),
+        (Action::ReadMemberDetails, OWNER, [true, true, true, true, true]),
+        (Action::ReadInventory, NONE, [true, true, true, false, false]),
+        (Action::AddToMemberInventory, NONE, [true, false, false, false, false]),
+        (Action::AddToMemberInventory, OWNER, [true, false, true, false, false]),
+        (Action::AddToGuildInventory, CONTACT, [true, true, false, false, false]),
+        (Action::AddToGuildInventory, GUILD_LIBRARIAN, [true, true, true, false, false]),
+        (Action::ReadGuilds, NONE, [true, true, true, true, false]),
+        (Action::CreateGuild, NONE, [true, false, false, true, false]),
+        (Action::UpdateGuild, NONE, [true, false, false, true, false]),
+        (Action::UpdateGuild, CONTACT, [true, false, true, true, false]),
+        (Action::DeleteGuild, NONE, [true, false, false, true, false]),
+        (Action::DeleteGuild, CONTACT, [true, false, true, true, false]),
+        (Action::ReadGuildMembers, NONE, [true, true, true, true, false]),
+        (Action::ManageGuildMembers, NONE, [true, false, false, true, false]),
+        (Action::ManageGuildMembers, GUILD_LIBRARIAN, [true, false, false, true, false]),
+        (Action::ManageGuildMembers, CONTACT, [true, false, true, true, false]),
+        (Action::ReadHealthDetails, NONE, [true, false, false, false, false]),
+    ];
+
+    fn roles(role: Option<&str>) -> Vec<String> {
+        role.into_iter().map(String::from).collect()
+    }
+
+    #[test]
+    fn policy_matrix() {
+        let users = [
+            Some(ROLE_ADMIN),
+            Some(ROLE_LIBRARIAN),
+            Some(ROLE_MEMBER),
+            Some(ROLE_ARISTOCRAT),
+            None,
+        ];
+        for (action, relations, expected) in MATRIX {
+            for (user, allowed) in users.iter().zip(expected.iter()) {
+                assert_eq!(
+                    is_allowed(&roles(*user), *action, relations),
+                    *allowed,
+                    "{:?} with role {:?} and relations {:?}",
+                    action,
+                    user,
+                    relations
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn authorize_requires_login() {
+        match authorize(&None, Action::ReadBooks, NONE) {
+            Err(Error::SpeakFriendAndEnterError) => (),
+            _ => panic!("Expected Error::SpeakFriendAndEnterError"),
+        }
+    }
+
+    #[test]
+    fn authorize_allows_reading_the_catalogue_without_login() {
+        authorize(&None, Action::ReadCatalogue, NONE).unwrap();
+        assert!(permits(&None, Action::ReadCatalogue, NONE));
+        assert!(!permits(&None, Action::ReadBooks, NONE));
+    }
+
+    #[test]
+    fn authorize_denies_missing_relation() {
+        let claims = Some(Claims {
+            uid: String::from("external_id"),
+            roles: roles(Some(ROLE_MEMBER)),
+            name: String::from("name"),
+            email: String::from("mail@example.com"),
+            member_id: Some(1),
+            api_token: None,
+        });
+        match authorize(&claims, Action::UpdateBook, RENTEE) {
+            Err(Error::YouShallNotPassError) => (),
+            _ => panic!("Expected Error::YouShallNotPassError"),
+        }
+        authorize(&claims, Action::UpdateBook, OWNER).unwrap();
+    }
+}
@@ -1,11 +1,94 @@
 use crate::api::*;
-use crate::auth::{Claims};
+use crate::auth::api_token;
+use crate::auth::policy::{authorize, permits, Action, Relation};
+use crate::auth::{Claims, KeycloakCache};
 use crate::database::*;
 use crate::error::Error;
+use crate::settings::Lending;
+use chrono::{Duration, Local};
 use std::collections::HashMap;
 
+/// Get the member id of the logged in user
+fn get_member_id<R: Repository>(db: &R, claims: &Option<Claims>) -> Result<MemberId, Error> {
+    match claims {
+        None => Err(Error::SpeakFriendAndEnterError),
+        Some(Claims {
+            member_id: Some(member_id),
+            ..
+        }) => Ok(*member_id),
+        Some(claims) => match db.get_member_by_external_id(&claims.uid)? {
+            Some(member) => Ok(member.id.expect("member id shall not be empty")),
+            None => Err(Error::YouShallNotPassError),
+        },
+    }
+}
+
+/// Id of the logged in member, None for anonymous users and users without member
+fn logged_in_member_id<R: Repository>(
+    db: &R,
+    claims: &Option<Claims>,
+) -> Result<Option<MemberId>, Error> {
+    match get_member_id(db, claims) {
+        Ok(member_id) => Ok(Some(member_id)),
+        Err(Error::SpeakFriendAndEnterError) | Err(Error::YouShallNotPassError) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether the logged in user is the given member or guild
+fn is_logged_in_as<R: Repository>(
+    db: &R,
+    claims: &Option<Claims>,
+    entity_type: &EntityType,
+    id: EntityId,
+) -> Result<bool, Error> {
+    match entity_type {
+        EntityType::Member => Ok(logged_in_member_id(db, claims)? == Some(id)),
+        EntityType::Guild => Ok(false),
+    }
+}
+
+/// Relations of the logged in user to a resource belonging to the given member or guild
+fn owner_relations<R: Repository>(
+    db: &R,
+    claims: &Option<Claims>,
+    entity_type: &EntityType,
+    id: EntityId,
+) -> Result<Vec<Relation>, Error> {
+    match entity_type {
+        EntityType::Member => match is_logged_in_as(db, claims, entity_type, id)? {
+            true => Ok(vec![Relation::Owner]),
+            false => Ok(vec![]),
+        },
+        EntityType::Guild => match db.get::<Guild>(id)? {
+            Some(guild) => guild_relations(db, claims, &guild),
+            None => Ok(vec![]),
+        },
+    }
+}
+
+/// Get the state of the synchronisation with Keycloak.
+/// Only admins see the error message, it may contain internal details.
+pub fn get_health(kc: &KeycloakCache, claims: &Option<Claims>) -> GetHealth {
+    let health = kc.health();
+    GetHealth {
+        keycloak: KeycloakHealth {
+            lastsuccess: health.last_success.map(|time| time.to_rfc3339()),
+            lasterror: health.last_error.map(|time| time.to_rfc3339()),
+            lasterrormessage: health
+                .last_error_message
+                .filter(|_| permits(claims, Action::ReadHealthDetails, &[])),
+            failures: health.failures,
+        },
+    }
+}
+
 /// Get all RPG systems from database
-pub fn get_rpgsystems(db: &Database) -> Result<GetRpgSystems, Error> {
+pub fn get_rpgsystems<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+) -> Result<GetRpgSystems, Error> {
+    authorize(&claims, Action::ReadCatalogue, &[])?;
     match db.get_all::<RpgSystem>() {
         Ok(rpgsystems) => Ok(GetRpgSystems { rpgsystems }),
         Err(e) => Err(e),
@@ -14,11 +97,12 @@ pub fn get_rpgsystems(db: &Database) -> Result<GetRpgSystems, Error> {
 
 /// Get an RPG system with given id from database
 /// Fills the stock and availability infos when user is logged in.
-pub fn get_rpgsystem(
-    db: &Database,
+pub fn get_rpgsystem<R: Repository>(
+    db: &R,
     claims: Option<Claims>,
     system_id: RpgSystemId,
 ) -> Result<GetRpgSystem, Error> {
+    authorize(&claims, Action::ReadCatalogue, &[])?;
     let titles = db.get_titles_by_rpg_system(system_id)?;
 
     let include_stock = match claims {
@@ -33,32 +117,35 @@ pub fn get_rpgsystem(
 }
 
 /// Insert a RPG system into database
-pub fn post_rpgsystem(
-    db: &Database,
-    _claims: Option<Claims>,
+pub fn post_rpgsystem<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
     system: PutPostRpgSystem,
 ) -> Result<RpgSystemId, Error> {
+    authorize(&claims, Action::CreateCatalogue, &[])?;
     //TODO: Error handling
     //TODO: Assert Id is unset
     Ok(db.insert::<RpgSystem>(&system.rpgsystem)?)
 }
 
 /// Update a specific system in database
-pub fn put_rpgsystem(
-    db: &Database,
-    _claims: Option<Claims>,
+pub fn put_rpgsystem<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
     system: &PutPostRpgSystem,
 ) -> Result<(), Error> {
+    authorize(&claims, Action::ManageCatalogue, &[])?;
     //TODO: Error handling
     Ok(db.update::<RpgSystem>(&system.rpgsystem)?)
 }
 
 /// Delete the RPG system with given id from database
-pub fn delete_rpgsystem(
-    db: &Database,
-    _claims: Option<Claims>,
+pub fn delete_rpgsystem<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
     systemid: RpgSystemId,
 ) -> Result<(), Error> {
+    authorize(&claims, Action::ManageCatalogue, &[])?;
     match db.delete::<RpgSystem>(systemid) {
         Ok(true) => Ok(()),
         Ok(false) => Err(Error::ItemNotFound),
@@ -67,10 +154,9 @@ pub fn delete_rpgsystem(
 }
 
 /// Get all titles from database
-pub fn get_titles(db: &Database) -> Result<GetTitles, Error> {
-    //TODO: authentication
+pub fn get_titles<R: Repository>(db: &R, claims: Option<Claims>) -> Result<GetTitles, Error> {
+    authorize(&claims, Action::ReadCatalogue, &[])?;
 
-    //TODO Error mapping
     let tuples = db.get_titles_with_details()?;
 
     Ok(GetTitles {
@@ -84,46 +170,58 @@ pub fn get_titles(db: &Database) -> Result<GetTitles, Error> {
 }
 
 /// Get a title with given id from database
-pub fn get_title(
-    db: &Database,
+pub fn get_title<R: Repository>(
+    db: &R,
     claims: Option<Claims>,
     title_id: TitleId,
 ) -> Result<GetTitle, Error> {
-    let (title, system, stock, available) = db.get_title_with_details(title_id)?.unwrap();
+    authorize(&claims, Action::ReadCatalogue, &[])?;
+    let (title, system, stock, available) = match db.get_title_with_details(title_id)? {
+        Some(details) => details,
+        None => return Err(Error::ItemNotFound),
+    };
     let books = get_books_by_title_id(db, title_id, claims)?;
-    //TODO: Error handling
-    //Map Errors to API Errors
-    //404 Not found
 
     Ok(GetTitle::new(title, system, stock, available, books))
 }
 
 /// Insert a title into database
-pub fn post_title(
-    db: &Database,
-    _claims: Option<Claims>,
+pub fn post_title<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
     title: PutPostTitle,
 ) -> Result<TitleId, Error> {
+    authorize(&claims, Action::CreateCatalogue, &[])?;
     //TODO: Error handling
     Ok(db.insert::<Title>(&title.title)?)
 }
 
 /// Update a specific title in database
-pub fn put_title(db: &Database, _claims: Option<Claims>, title: PutPostTitle) -> Result<(), Error> {
+pub fn put_title<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    title: PutPostTitle,
+) -> Result<(), Error> {
+    authorize(&claims, Action::ManageCatalogue, &[])?;
     //TODO: Error handling
     Ok(db.update::<Title>(&title.title)?)
 }
 
 /// Delete the title with given id from database
-pub fn delete_title(db: &Database, _claims: Option<Claims>, id: TitleId) -> Result<(), Error> {
+pub fn delete_title<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    id: TitleId,
+) -> Result<(), Error> {
+    authorize(&claims, Action::ManageCatalogue, &[])?;
     //TODO: Errorhandling
     db.delete::<Title>(id)?;
     Ok(())
 }
 
 /// Get all books of a title including rental information
-fn get_books_by_title_id(
-    _db: &Database,
+fn get_books_by_title_id<R: Repository>(
+    _db: &R,
     _id: TitleId,
     _claims: Option<Claims>,
 ) -> Result<Vec<BookWithOwnerWithRental>, Error> {
@@ -131,173 +229,1585 @@ fn get_books_by_title_id(
     return Ok(vec![]);
 }
 
-/// Get all books from database
-pub fn get_books(db: &Database, _claims: Option<Claims>) -> Result<GetBooks, Error> {
-    //TODO: authentication
+/// Name shown for members which are not known to Keycloak (yet)
+const UNKNOWN_MEMBER_NAME: &str = "Unknown member";
 
-    //TODO Error mapping
-    let books = db.get_books_with_details()?;
-    let systems_vec = RpgSystem::get_all(db)?;
-    let titles_vec = Title::get_all(db)?;
-    let guilds_vec = Guild::get_all(db)?;
+/// Resolves members and guilds to Entity DTOs with their names.
+/// Member names are looked up in the Keycloak cache by their external id.
+/// Emails are only resolved for the members the logged in user may see the details of.
+struct EntityResolver {
+    guilds: HashMap<GuildId, Guild>,
+    members: HashMap<MemberId, ExternalId>,
+    kc: KeycloakCache,
+    claims: Option<Claims>,
+    /// Member id of the logged in user
+    member_id: Option<MemberId>,
+}
 
-    let mut systems_map: HashMap<RpgSystemId, RpgSystem> = HashMap::new();
-    for system in systems_vec {
-        match system.id {
-            Some(id) => {
-                systems_map.insert(id, system);
-                ()
+impl EntityResolver {
+    fn new<R: Repository>(
+        db: &R,
+        kc: &KeycloakCache,
+        claims: &Option<Claims>,
+    ) -> Result<EntityResolver, Error> {
+        let mut guilds: HashMap<GuildId, Guild> = HashMap::new();
+        for guild in db.get_all::<Guild>()? {
+            if let Some(id) = guild.id {
+                guilds.insert(id, guild);
             }
-            None => (),
         }
+        let mut members: HashMap<MemberId, ExternalId> = HashMap::new();
+        for member in db.get_all::<crate::database::Member>()? {
+            if let Some(id) = member.id {
+                members.insert(id, member.external_id);
+            }
+        }
+        Ok(EntityResolver {
+            guilds,
+            members,
+            kc: kc.clone(),
+            claims: claims.clone(),
+            member_id: logged_in_member_id(db, claims)?,
+        })
     }
-    let mut titles_map: HashMap<TitleId, TitleWithSystem> = HashMap::new();
-    for title in titles_vec {
-        match title.id {
-            Some(id) => {
-                match systems_map.get(&title.system).cloned() {
-                    Some(system) => {
-                        titles_map.insert(id, TitleWithSystem::new(title, system, 0, 0));
-                        ()
-                    }
-                    None => (),
+
+    /// Fails for guilds deleted after the resolver was created
+    fn resolve(&self, entity_type: &EntityType, id: EntityId) -> Result<Entity, Error> {
+        Ok(Entity {
+            entity_type: entity_type.clone(),
+            id,
+            name: match entity_type {
+                EntityType::Guild => match self.guilds.get(&id) {
+                    Some(guild) => guild.name.clone(),
+                    None => return Err(Error::IllegalState),
+                },
+                EntityType::Member => self.resolve_member(id).name,
+            },
+        })
+    }
+
+    /// Same as `may_see_member_details`
+    fn may_see_details(&self, id: MemberId) -> bool {
+        let relations = match self.member_id == Some(id) {
+            true => vec![Relation::Owner],
+            false => vec![],
+        };
+        permits(&self.claims, Action::ReadMemberDetails, &relations)
+    }
+
+    fn resolve_member(&self, id: MemberId) -> crate::api::Member {
+        let user = match self.members.get(&id) {
+            Some(external_id) => self.kc.get_user(external_id).unwrap_or(None),
+            None => None,
+        };
+        match user {
+            Some(user) => crate::api::Member {
+                id,
+                name: user.display_name(),
+                email: match self.may_see_details(id) {
+                    true => user.email().cloned().unwrap_or_default(),
+                    false => String::new(),
+                },
+            },
+            None => {
+                debug!("member {} is missing in the Keycloak cache", id);
+                crate::api::Member {
+                    id,
+                    name: String::from(UNKNOWN_MEMBER_NAME),
+                    email: String::new(),
                 }
-                ()
             }
-            None => (),
         }
     }
-    let mut guilds_map: HashMap<GuildId, Guild> = HashMap::new();
-    for guild in guilds_vec {
-        match guild.id {
-            Some(id) => {
-                guilds_map.insert(id, guild);
-                ()
-            }
-            None => (),
+}
+
+/// Get all titles with their RPG systems, without stock information
+fn get_titles_map<R: Repository>(db: &R) -> Result<HashMap<TitleId, TitleWithSystem>, Error> {
+    let mut systems_map: HashMap<RpgSystemId, RpgSystem> = HashMap::new();
+    for system in db.get_all::<RpgSystem>()? {
+        if let Some(id) = system.id {
+            systems_map.insert(id, system);
+        }
+    }
+
+    let mut titles_map: HashMap<TitleId, TitleWithSystem> = HashMap::new();
+    for title in db.get_all::<Title>()? {
+        if let (Some(id), Some(system)) = (title.id, systems_map.get(&title.system).cloned()) {
+            titles_map.insert(id, TitleWithSystem::new(title, system, 0, 0));
         }
     }
-    let titles_map = titles_map;
-    let guilds_map = guilds_map;
+    Ok(titles_map)
+}
+
+/// Assemble a book with its title, owner and current rental
+fn book_with_details(
+    book: Book,
+    rental: Option<Rental>,
+    available: bool,
+    titles_map: &HashMap<TitleId, TitleWithSystem>,
+    resolver: &EntityResolver,
+) -> Result<BookWithTitleWithOwnerWithRental, Error> {
+    let rental = match rental {
+        Some(r) => Some(RentalWithRentee {
+            from: r.from,
+            to: r.to,
+            rentee: resolver.resolve(&r.rentee_type, r.rentee)?,
+        }),
+        None => None,
+    };
+    Ok(BookWithTitleWithOwnerWithRental {
+        id: book.id.expect("book id shall not be empty"),
+        quality: book.quality,
+        available,
+        external_inventory_id: book.external_inventory_id,
+        rental,
+        // The title may have been deleted after the map was read
+        title: match titles_map.get(&book.title) {
+            Some(title) => title.clone(),
+            None => return Err(Error::IllegalState),
+        },
+        owner: resolver.resolve(&book.owner_type, book.owner)?,
+    })
+}
+
+/// Get all books from database
+pub fn get_books<R: Repository>(
+    db: &R,
+    kc: &KeycloakCache,
+    claims: Option<Claims>,
+) -> Result<GetBooks, Error> {
+    authorize(&claims, Action::ReadBooks, &[])?;
+
+    let books = db.get_books_with_details()?;
+    let titles_map = get_titles_map(db)?;
+    let resolver = EntityResolver::new(db, kc, &claims)?;
 
     Ok(GetBooks {
         books: books
             .into_iter()
-            .map(move |(book, rental, available)| {
-                BookWithTitleWithOwnerWithRental {
-                    id: book.id.expect("book id shall not be empty"),
-                    quality: book.quality,
-                    available,
-                    external_inventory_id: book.external_inventory_id,
-                    rental: match rental {
-                        None => None,
-                        Some(r) => Some(RentalWithRentee {
-                            from: r.from,
-                            to: r.to,
-                            rentee: Entity {
-                                entity_type: r.rentee_type.clone(),
-                                id: r.rentee,
-                                name: match r.rentee_type {
-                                    EntityType::Guild => guilds_map
-                                        .get(&r.rentee)
-                                        .expect("invalid guild id")
-                                        .name
-                                        .clone(),
-                                    EntityType::Member => String::from("NO DATA"), // TODO use keycloak
-                                },
-                            },
-                        }),
-                    },
-                    title: titles_map
-                        .get(&book.title)
-                        .cloned()
-                        .expect("invalid book title"),
-                    owner: Entity {
-                        entity_type: book.owner_type.clone(),
-                        id: book.owner,
-                        name: match book.owner_type {
-                            EntityType::Guild => guilds_map
-                                .get(&book.owner)
-                                .expect("invalid guild id")
-                                .name
-                                .clone(),
-                            EntityType::Member => String::from("NO DATA"), // TODO use keycloak
-                        },
-                    },
-                }
+            .map(|(book, rental, available)| {
+                book_with_details(book, rental, available, &titles_map, &resolver)
             })
-            .collect(),
+            .collect::<Result<_, _>>()?,
     })
 }
 
-pub fn get_book(_db: &Database, _claims: Option<Claims>, _id: BookId) -> Result<(), Error> {
-    //TODO:: Stub
-    //TODO: authentication
-    Ok(())
+/// Get a book with its title, owner and current rental
+pub fn get_book<R: Repository>(
+    db: &R,
+    kc: &KeycloakCache,
+    claims: Option<Claims>,
+    id: BookId,
+) -> Result<GetBook, Error> {
+    authorize(&claims, Action::ReadBooks, &[])?;
+
+    let (book, rental, available) = match db.get_book_with_details(id)? {
+        Some(details) => details,
+        None => return Err(Error::ItemNotFound),
+    };
+
+    let mut titles_map: HashMap<TitleId, TitleWithSystem> = HashMap::new();
+    match db.get_title_with_details(book.title)? {
+        Some((title, system, stock, title_available)) => {
+            titles_map.insert(
+                book.title,
+                TitleWithSystem::new(title, system, stock, title_available),
+            );
+        }
+        None => return Err(Error::IllegalState),
+    }
+    let resolver = EntityResolver::new(db, kc, &claims)?;
+
+    Ok(GetBook {
+        book: book_with_details(book, rental, available, &titles_map, &resolver)?,
+    })
 }
 
-pub fn post_book(
-    _db: &Database,
-    _claims: Option<Claims>,
-    _book: PutPostBook,
+/// Insert a book into database.
+/// Members may only insert books into their own inventory.
+pub fn post_book<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    book: PutPostBook,
 ) -> Result<BookId, Error> {
-    //TODO:: Stub
-    //TODO: authentication
-    Ok(1234)
+    let mut book = book.book;
+    authorize(
+        &claims,
+        Action::CreateBook,
+        &owner_relations(db, &claims, &book.owner_type, book.owner)?,
+    )?;
+
+    book.id = None;
+    db.insert(&book)
 }
 
-pub fn put_book(_db: &Database, _claims: Option<Claims>, _book: PutPostBook) -> Result<(), Error> {
-    //TODO:: Stub
-    //TODO: authentication
-    Ok(())
+/// Update a specific book in database.
+/// Members may only edit books in their own inventory and may not give them away.
+pub fn put_book<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    book: PutPostBook,
+) -> Result<(), Error> {
+    let book = book.book;
+    let id = book.id.expect("book id shall be set by the path");
+    let existing = match db.get::<Book>(id)? {
+        Some(existing) => existing,
+        None => return Err(Error::ItemNotFound),
+    };
+    authorize(
+        &claims,
+        Action::UpdateBook,
+        &owner_relations(db, &claims, &existing.owner_type, existing.owner)?,
+    )?;
+    authorize(
+        &claims,
+        Action::UpdateBook,
+        &owner_relations(db, &claims, &book.owner_type, book.owner)?,
+    )?;
+
+    db.update(&book)
 }
 
-pub fn delete_book(db: &Database, _claims: Option<Claims>, id: BookId) -> Result<(), Error> {
+pub fn delete_book<R: Repository>(db: &R, claims: Option<Claims>, id: BookId) -> Result<(), Error> {
+    authorize(&claims, Action::DeleteBook, &[])?;
     //TODO:: Stub
     //TODO: Errorhandling
     db.delete::<Book>(id)?;
     Ok(())
 }
 
-pub fn get_members(_db: &Database, _claims: Option<Claims>) -> Result<GetMembers, Error> {
-    //TODO: Stub
-    //TODO: Get Members from Database
-    //TODO: Complete Infos from Keycloak
-    Ok(GetMembers { members: vec![] })
+/// Get a rental with given id from database.
+/// Members may only see rentals of their own books or rentals they are the rentee of.
+pub fn get_rental<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    id: RentalId,
+) -> Result<GetRental, Error> {
+    let rental = match db.get::<Rental>(id)? {
+        Some(rental) => rental,
+        None => return Err(Error::ItemNotFound),
+    };
+    let book = match db.get::<Book>(rental.book)? {
+        Some(book) => book,
+        None => return Err(Error::IllegalState),
+    };
+
+    let mut relations = owner_relations(db, &claims, &book.owner_type, book.owner)?;
+    if is_logged_in_as(db, &claims, &rental.rentee_type, rental.rentee)? {
+        relations.push(Relation::Rentee);
+    }
+    authorize(&claims, Action::ReadRental, &relations)?;
+
+    Ok(GetRental { rental })
 }
 
-pub fn get_member(_db: &Database, _claims: Option<Claims>, _id: MemberId) -> Result<(), Error> {
-    //TODO: Stub
-    //TODO: Get Members from Database
-    //TODO: Complete Infos from Keycloak
-    Ok(())
+/// Check out a book to a member or guild, starting today.
+/// Fails if the book is still rented or held for someone else's reservation.
+pub fn checkout_book<R: Repository>(
+    db: &R,
+    lending: &Lending,
+    claims: Option<Claims>,
+    rental: PostRental,
+) -> Result<RentalId, Error> {
+    let rental = rental.rental;
+    let book = match db.get::<Book>(rental.book)? {
+        Some(book) => book,
+        None => return Err(Error::ItemNotFound),
+    };
+    authorize(
+        &claims,
+        Action::CheckoutBook,
+        &owner_relations(db, &claims, &book.owner_type, book.owner)?,
+    )?;
+
+    let today = Local::today().naive_local();
+    if rental.to < today {
+        return Err(Error::IllegalValueForType(String::from("rental.to")));
+    }
+    // Passed on separately, so that a failing checkout does not keep the expired hold
+    db.transaction(|tx| release_expired_holds(tx, lending, book.title))?;
+    // A concurrent checkout of the book waits for the lock until this one is committed,
+    // then it finds the new rental
+    db.transaction(|tx| {
+        tx.lock_book(rental.book)?;
+        if tx.get_active_rental_for_book(rental.book)?.is_some() {
+            return Err(Error::BookNotAvailable);
+        }
+        let policy = lending.policy_for(&book.owner_type);
+        if rental.to > today + Duration::days(policy.max_loan_days as i64) {
+            return Err(Error::LendingPolicyViolation(format!(
+                "rentals may not exceed {} days",
+                policy.max_loan_days
+            )));
+        }
+        let rentee_is_member = rental.rentee.entity_type == EntityType::Member;
+        if let Some(reservation) = tx.get_reservation_holding_book(rental.book, today)? {
+            if !rentee_is_member || reservation.member != rental.rentee.id {
+                return Err(Error::BookNotAvailable);
+            }
+        }
+
+        let rental_id = tx.insert(&Rental::new(
+            None,
+            today,
+            rental.to,
+            rental.book,
+            rental.rentee.id,
+            rental.rentee.entity_type,
+        ))?;
+
+        // The rentee got a copy, so their reservation of this title is fulfilled
+        if rentee_is_member {
+            for reservation in tx.get_reservations_by_title(book.title, today)? {
+                let fulfilled = reservation.member == rental.rentee.id
+                    && (reservation.book.is_none() || reservation.book == Some(rental.book));
+                if fulfilled {
+                    tx.delete::<Reservation>(
+                        reservation.id.expect("reservation id shall not be empty"),
+                    )?;
+                }
+            }
+        }
+
+        Ok(rental_id)
+    })
 }
 
-pub fn get_guilds(_db: &Database, _claims: Option<Claims>) -> Result<GetGuilds, Error> {
-    //TODO: Stub
-    Ok(GetGuilds { guilds: vec![] })
+/// Check a rented book back in, recording today as the actual return date.
+/// The book is then held for the next reservation of its title.
+pub fn return_rental<R: Repository>(
+    db: &R,
+    lending: &Lending,
+    claims: Option<Claims>,
+    id: RentalId,
+) -> Result<(), Error> {
+    let rental = match db.get::<Rental>(id)? {
+        Some(rental) => rental,
+        None => return Err(Error::ItemNotFound),
+    };
+    let book = match db.get::<Book>(rental.book)? {
+        Some(book) => book,
+        None => return Err(Error::IllegalState),
+    };
+    authorize(
+        &claims,
+        Action::ReturnRental,
+        &owner_relations(db, &claims, &book.owner_type, book.owner)?,
+    )?;
+
+    // A concurrent return of the rental waits for the lock, then sees it returned
+    db.transaction(|tx| {
+        tx.lock_book(rental.book)?;
+        let mut rental = match tx.get::<Rental>(id)? {
+            Some(rental) => rental,
+            None => return Err(Error::ItemNotFound),
+        };
+        if !rental.is_active() {
+            return Err(Error::RentalAlreadyReturned);
+        }
+        rental.returned = Some(Local::today().naive_local());
+        tx.update(&rental)?;
+        release_expired_holds(tx, lending, book.title)?;
+        hold_for_next_reservation(tx, lending, rental.book, book.title)
+    })
 }
 
-pub fn get_guild(_db: &Database, _claims: Option<Claims>, _id: GuildId) -> Result<(), Error> {
-    //TODO: Stub
+/// Extend an active rental up to the given date.
+/// Only the rentee or a librarian may extend a rental, and only as the lending policy
+/// for the book's owner type allows.
+pub fn extend_rental<R: Repository>(
+    db: &R,
+    lending: &Lending,
+    claims: Option<Claims>,
+    id: RentalId,
+    extension: PostRentalExtension,
+) -> Result<(), Error> {
+    let rental = match db.get::<Rental>(id)? {
+        Some(rental) => rental,
+        None => return Err(Error::ItemNotFound),
+    };
+    let relations = match is_logged_in_as(db, &claims, &rental.rentee_type, rental.rentee)? {
+        true => vec![Relation::Rentee],
+        false => vec![],
+    };
+    authorize(&claims, Action::ExtendRental, &relations)?;
+
+    let book = match db.get::<Book>(rental.book)? {
+        Some(book) => book,
+        None => return Err(Error::IllegalState),
+    };
+    let policy = lending.policy_for(&book.owner_type);
+    db.transaction(|tx| release_expired_holds(tx, lending, book.title))?;
+    // A concurrent extension or return of the rental waits for the lock, then sees this one
+    db.transaction(|tx| {
+        tx.lock_book(rental.book)?;
+        let mut rental = match tx.get::<Rental>(id)? {
+            Some(rental) => rental,
+            None => return Err(Error::ItemNotFound),
+        };
+        if !rental.is_active() {
+            return Err(Error::RentalAlreadyReturned);
+        }
+        if rental.extensions >= policy.max_extensions {
+            return Err(Error::LendingPolicyViolation(format!(
+                "rentals may not be extended more than {} times",
+                policy.max_extensions
+            )));
+        }
+        if tx.get_next_reservation_for_title(book.title)?.is_some() {
+            return Err(Error::LendingPolicyViolation(String::from(
+                "the title is reserved by someone else",
+            )));
+        }
+        if extension.to <= rental.to {
+            return Err(Error::IllegalValueForType(String::from("extension.to")));
+        }
+        // Measured from the checkout, extensions do not start a new loan
+        if extension.to > rental.from + Duration::days(policy.max_loan_days as i64) {
+            return Err(Error::LendingPolicyViolation(format!(
+                "rentals may not exceed {} days",
+                policy.max_loan_days
+            )));
+        }
+
+        rental.to = extension.to;
+        rental.extensions += 1;
+        tx.update(&rental)
+    })
+}
+
+/// Hold a book for the first reservation of the title waiting for a copy
+fn hold_for_next_reservation<R: Repository>(
+    db: &R,
+    lending: &Lending,
+    book_id: BookId,
+    title_id: TitleId,
+) -> Result<(), Error> {
+    match db.get_next_reservation_for_title(title_id)? {
+        Some(mut reservation) => {
+            reservation.book = Some(book_id);
+            reservation.held_until =
+                Some(Local::today().naive_local() + Duration::days(lending.hold_days as i64));
+            db.update(&reservation)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Pass on the copies of a title whose hold expired before today.
+/// The member did not pick up the copy in time, so their reservation is dropped.
+fn release_expired_holds<R: Repository>(
+    db: &R,
+    lending: &Lending,
+    title_id: TitleId,
+) -> Result<(), Error> {
+    let today = Local::today().naive_local();
+    for reservation in db.get_expired_holds_for_title(title_id, today)? {
+        db.delete::<Reservation>(reservation.id.expect("reservation id shall not be empty"))?;
+        match reservation.book {
+            // The copy may have been checked out to someone else after the hold expired
+            Some(book_id) if db.get_active_rental_for_book(book_id)?.is_none() => {
+                hold_for_next_reservation(db, lending, book_id, title_id)?
+            }
+            _ => (),
+        }
+    }
     Ok(())
 }
 
-pub fn post_guild(
-    _db: &Database,
-    _claims: Option<Claims>,
-    _guild: PutPostGuild,
+/// Get all rentals which should have been returned before today
+pub fn get_overdue_rentals<R: Repository>(
+    db: &R,
+    kc: &KeycloakCache,
+    claims: Option<Claims>,
+) -> Result<GetOverdueRentals, Error> {
+    authorize(&claims, Action::ReadOverdueRentals, &[])?;
+
+    let today = Local::today().naive_local();
+    let titles_map = get_titles_map(db)?;
+    let resolver = EntityResolver::new(db, kc, &claims)?;
+
+    let mut rentals = vec![];
+    for rental in db.get_overdue_rentals(today)? {
+        let book = match db.get::<Book>(rental.book)? {
+            Some(book) => book,
+            None => return Err(Error::IllegalState),
+        };
+        rentals.push(OverdueRental {
+            id: rental.id.expect("rental id shall not be empty"),
+            from: rental.from,
+            to: rental.to,
+            daysoverdue: (today - rental.to).num_days(),
+            book: BookWithTitleWithOwner {
+                id: rental.book,
+                title: match titles_map.get(&book.title) {
+                    Some(title) => title.clone(),
+                    None => return Err(Error::IllegalState),
+                },
+                owner: resolver.resolve(&book.owner_type, book.owner)?,
+                quality: book.quality,
+                external_inventory_id: book.external_inventory_id,
+            },
+            rentee: resolver.resolve(&rental.rentee_type, rental.rentee)?,
+        });
+    }
+
+    Ok(GetOverdueRentals { rentals })
+}
+
+/// Get the reservation queue of a title
+pub fn get_reservations<R: Repository>(
+    db: &R,
+    lending: &Lending,
+    claims: Option<Claims>,
+    title_id: TitleId,
+) -> Result<GetReservations, Error> {
+    authorize(&claims, Action::ReadReservations, &[])?;
+    if db.get::<Title>(title_id)?.is_none() {
+        return Err(Error::ItemNotFound);
+    }
+    db.transaction(|tx| release_expired_holds(tx, lending, title_id))?;
+
+    Ok(GetReservations {
+        reservations: db.get_reservations_by_title(title_id, Local::today().naive_local())?,
+    })
+}
+
+/// Reserve a title for the logged in member.
+/// Only possible when no copy of the title is available.
+pub fn post_reservation<R: Repository>(
+    db: &R,
+    lending: &Lending,
+    claims: Option<Claims>,
+    title_id: TitleId,
+) -> Result<ReservationId, Error> {
+    authorize(&claims, Action::Reserve, &[])?;
+    let member_id = get_member_id(db, &claims)?;
+    let today = Local::today().naive_local();
+
+    if db.get::<Title>(title_id)?.is_none() {
+        return Err(Error::ItemNotFound);
+    }
+    db.transaction(|tx| release_expired_holds(tx, lending, title_id))?;
+    // Copies returned or checked out meanwhile wait for the lock, so the stock is current
+    db.transaction(|tx| {
+        tx.lock_title(title_id)?;
+        let available = match tx.get_title_with_details(title_id)? {
+            Some((_, _, _, available)) => available,
+            None => return Err(Error::ItemNotFound),
+        };
+        let reservations = tx.get_reservations_by_title(title_id, today)?;
+        // Copies held for other reservations are not available to the member
+        let held = reservations.iter().filter(|r| r.is_holding(today)).count() as u32;
+        if available > held {
+            return Err(Error::TitleAvailable);
+        }
+        if reservations.iter().any(|r| r.member == member_id) {
+            return Err(Error::AlreadyReserved);
+        }
+
+        match tx.insert(&Reservation::new(None, title_id, member_id, today)) {
+            // Only one reservation per member and title is stored
+            Err(Error::DuplicateEntry) => Err(Error::AlreadyReserved),
+            result => result,
+        }
+    })
+}
+
+/// Cancel a reservation. Members may only cancel their own reservations.
+/// A copy held for the reservation is passed on to the next one in the queue.
+pub fn delete_reservation<R: Repository>(
+    db: &R,
+    lending: &Lending,
+    claims: Option<Claims>,
+    title_id: TitleId,
+    id: ReservationId,
+) -> Result<(), Error> {
+    let reservation = match db.get::<Reservation>(id)? {
+        Some(reservation) if reservation.title == title_id => reservation,
+        _ => return Err(Error::ItemNotFound),
+    };
+    authorize(
+        &claims,
+        Action::CancelReservation,
+        &owner_relations(db, &claims, &EntityType::Member, reservation.member)?,
+    )?;
+
+    db.transaction(|tx| {
+        tx.delete::<Reservation>(id)?;
+
+        match reservation.book {
+            Some(book_id) if reservation.is_holding(Local::today().naive_local()) => {
+                hold_for_next_reservation(tx, lending, book_id, title_id)
+            }
+            _ => Ok(()),
+        }
+    })
+}
+
+/// Build the profile of a member from the Keycloak cache.
+/// Contact details and roles are only included when `with_details` is set.
+fn member_with_roles(
+    kc: &KeycloakCache,
+    member: crate::database::Member,
+    with_details: bool,
+) -> Result<MemberWithRoles, Error> {
+    let id = member.id.expect("member id shall not be empty");
+    let user = kc.get_user(&member.external_id)?;
+    if user.is_none() {
+        debug!("member {} is missing in the Keycloak cache", id);
+    }
+
+    let name = user
+        .as_ref()
+        .map_or(String::from(UNKNOWN_MEMBER_NAME), |user| user.display_name());
+
+    if !with_details {
+        return Ok(MemberWithRoles {
+            id,
+            name,
+            username: None,
+            email: None,
+            enabled: None,
+            roles: None,
+        });
+    }
+
+    Ok(MemberWithRoles {
+        id,
+        name,
+        username: user.as_ref().map(|user| user.username().clone()),
+        email: user.as_ref().and_then(|user| user.email().cloned()),
+        enabled: user.as_ref().map(|user| user.enabled()),
+        roles: Some(kc.get_roles(&member.external_id)),
+    })
+}
+
+/// Whether the logged in user may see the contact details of the given member
+fn may_see_member_details<R: Repository>(
+    db: &R,
+    claims: &Option<Claims>,
+    id: MemberId,
+) -> Result<bool, Error> {
+    Ok(permits(
+        claims,
+        Action::ReadMemberDetails,
+        &owner_relations(db, claims, &EntityType::Member, id)?,
+    ))
+}
+
+/// Get all members from database, completed with infos from Keycloak
+pub fn get_members<R: Repository>(
+    db: &R,
+    kc: &KeycloakCache,
+    claims: Option<Claims>,
+) -> Result<GetMembers, Error> {
+    authorize(&claims, Action::ReadMembers, &[])?;
+
+    let mut members = vec![];
+    for member in db.get_all::<crate::database::Member>()? {
+        let id = member.id.expect("member id shall not be empty");
+        let with_details = may_see_member_details(db, &claims, id)?;
+        members.push(member_with_roles(kc, member, with_details)?);
+    }
+    Ok(GetMembers { members })
+}
+
+/// Get a member with given id from database, completed with infos from Keycloak
+pub fn get_member<R: Repository>(
+    db: &R,
+    kc: &KeycloakCache,
+    claims: Option<Claims>,
+    id: MemberId,
+) -> Result<GetMember, Error> {
+    authorize(&claims, Action::ReadMembers, &[])?;
+
+    match db.get::<crate::database::Member>(id)? {
+        Some(member) => {
+            let with_details = may_see_member_details(db, &claims, id)?;
+            Ok(GetMember {
+                member: member_with_roles(kc, member, with_details)?,
+            })
+        }
+        None => Err(Error::ItemNotFound),
+    }
+}
+
+/// Get the books owned and rented by an entity
+fn get_inventory<R: Repository>(
+    db: &R,
+    kc: &KeycloakCache,
+    claims: &Option<Claims>,
+    entity_type: &EntityType,
+    id: EntityId,
+) -> Result<Inventory, Error> {
+    let titles_map = get_titles_map(db)?;
+    let resolver = EntityResolver::new(db, kc, claims)?;
+
+    Ok(Inventory {
+        ownedbookds: db
+            .get_books_with_details_by_owner(entity_type, id)?
+            .into_iter()
+            .map(|(book, rental, available)| {
+                book_with_details(book, rental, available, &titles_map, &resolver)
+            })
+            .collect::<Result<_, _>>()?,
+        rentedbooks: db
+            .get_books_with_details_by_rentee(entity_type, id)?
+            .into_iter()
+            .map(|(book, rental, available)| {
+                book_with_details(book, rental, available, &titles_map, &resolver)
+            })
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// Insert a book into the inventory of an entity.
+/// The owner given with the book has to match the entity.
+fn add_to_inventory<R: Repository>(
+    db: &R,
+    entity_type: EntityType,
+    id: EntityId,
+    book: PartialBook,
+) -> Result<BookId, Error> {
+    if book.owner.entity_type != entity_type || book.owner.id != id {
+        return Err(Error::IllegalValueForType(String::from("book.owner")));
+    }
+
+    let external_inventory_id = match book.external_inventory_id {
+        Some(external_inventory_id) => external_inventory_id,
+        None => db.get_next_external_inventory_id()?,
+    };
+
+    db.insert(&Book::new(
+        None,
+        book.title,
+        id,
+        entity_type,
+        book.quality,
+        external_inventory_id,
+    ))
+}
+
+/// Get the inventory of a member
+pub fn get_member_inventory<R: Repository>(
+    db: &R,
+    kc: &KeycloakCache,
+    claims: Option<Claims>,
+    id: MemberId,
+) -> Result<GetMemberInventory, Error> {
+    authorize(&claims, Action::ReadInventory, &[])?;
+    if db.get::<crate::database::Member>(id)?.is_none() {
+        return Err(Error::ItemNotFound);
+    }
+
+    Ok(GetMemberInventory {
+        member: EntityResolver::new(db, kc, &claims)?.resolve(&EntityType::Member, id)?,
+        inventory: get_inventory(db, kc, &claims, &EntityType::Member, id)?,
+    })
+}
+
+/// Get the logged in member with their roles and an inventory summary
+pub fn get_me<R: Repository>(db: &R, claims: Option<Claims>) -> Result<GetMe, Error> {
+    let id = get_member_id(db, &claims)?;
+    let roles = claims.map_or(vec![], |claims| claims.roles);
+
+    Ok(GetMe {
+        id,
+        roles,
+        inventory: InventorySummary {
+            ownedbooks: db
+                .get_books_with_details_by_owner(&EntityType::Member, id)?
+                .len(),
+            rentedbooks: db
+                .get_books_with_details_by_rentee(&EntityType::Member, id)?
+                .len(),
+        },
+    })
+}
+
+/// Api tokens may not manage api tokens, so a leaked token can not create new ones
+fn assert_interactive_login(claims: &Option<Claims>) -> Result<(), Error> {
+    match claims {
+        None => Err(Error::SpeakFriendAndEnterError),
+        Some(Claims {
+            api_token: Some(_), ..
+        }) => Err(Error::YouShallNotPassError),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Get the api tokens of the logged in member
+pub fn get_api_tokens<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+) -> Result<GetApiTokens, Error> {
+    assert_interactive_login(&claims)?;
+    let member_id = get_member_id(db, &claims)?;
+
+    Ok(GetApiTokens {
+        tokens: db.get_api_tokens_by_member(member_id)?,
+    })
+}
+
+/// Create an api token for the logged in member.
+/// The token may only carry roles the member has right now.
+pub fn post_api_token<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    token: PostApiToken,
+) -> Result<CreatedApiToken, Error> {
+    assert_interactive_login(&claims)?;
+    let member_id = get_member_id(db, &claims)?;
+    let token = token.token;
+
+    let member_roles = claims.map_or(vec![], |claims| claims.roles);
+    let roles = match token.roles {
+        Some(roles) => roles,
+        None => member_roles.clone(),
+    };
+    if roles.iter().any(|role| !member_roles.contains(role)) {
+        return Err(Error::YouShallNotPassError);
+    }
+
+    let today = Local::today().naive_local();
+    let expires = token
+        .expires
+        .unwrap_or_else(|| today + Duration::days(api_token::API_TOKEN_DAYS));
+    if expires < today {
+        return Err(Error::BadRequestFormat);
+    }
+
+    let secret = api_token::generate_secret()?;
+    let id = db.insert(&ApiToken::new(
+        None,
+        member_id,
+        token.name,
+        api_token::hash_secret(&secret),
+        roles,
+        today,
+        expires,
+    ))?;
+
+    Ok(CreatedApiToken { id, secret })
+}
+
+/// Revoke an api token of the logged in member
+pub fn delete_api_token<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    id: ApiTokenId,
+) -> Result<(), Error> {
+    assert_interactive_login(&claims)?;
+    let member_id = get_member_id(db, &claims)?;
+
+    match db.get::<ApiToken>(id)? {
+        Some(ref token) if token.member == member_id => (),
+        _ => return Err(Error::ItemNotFound),
+    }
+    match db.delete::<ApiToken>(id) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Error::ItemNotFound),
+        Err(e) => Err(e),
+    }
+}
+
+/// Insert a book into the inventory of a member. Only the member may add books.
+pub fn post_member_inventory<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    id: MemberId,
+    inventory: PutMemberInvetory,
+) -> Result<BookId, Error> {
+    if db.get::<crate::database::Member>(id)?.is_none() {
+        return Err(Error::ItemNotFound);
+    }
+    authorize(
+        &claims,
+        Action::AddToMemberInventory,
+        &owner_relations(db, &claims, &EntityType::Member, id)?,
+    )?;
+
+    add_to_inventory(db, EntityType::Member, id, inventory.book)
+}
+
+/// Get the inventory of a guild
+pub fn get_guild_inventory<R: Repository>(
+    db: &R,
+    kc: &KeycloakCache,
+    claims: Option<Claims>,
+    id: GuildId,
+) -> Result<GetGuildInventory, Error> {
+    authorize(&claims, Action::ReadInventory, &[])?;
+    if db.get::<Guild>(id)?.is_none() {
+        return Err(Error::ItemNotFound);
+    }
+
+    Ok(GetGuildInventory {
+        guild: EntityResolver::new(db, kc, &claims)?.resolve(&EntityType::Guild, id)?,
+        inventory: get_inventory(db, kc, &claims, &EntityType::Guild, id)?,
+    })
+}
+
+/// Insert a book into the inventory of a guild.
+/// Only librarians and the guild's own librarians may add books.
+pub fn post_guild_inventory<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    id: GuildId,
+    inventory: PutGuildInventory,
+) -> Result<BookId, Error> {
+    let guild = match db.get::<Guild>(id)? {
+        Some(guild) => guild,
+        None => return Err(Error::ItemNotFound),
+    };
+    authorize(
+        &claims,
+        Action::AddToGuildInventory,
+        &guild_relations(db, &claims, &guild)?,
+    )?;
+
+    add_to_inventory(db, EntityType::Guild, id, inventory.book)
+}
+
+/// Relations of the logged in user to the given guild.
+/// The guild's contact person counts as contact, whatever their guild membership says.
+fn guild_relations<R: Repository>(
+    db: &R,
+    claims: &Option<Claims>,
+    guild: &Guild,
+) -> Result<Vec<Relation>, Error> {
+    let member_id = match logged_in_member_id(db, claims)? {
+        Some(member_id) => member_id,
+        None => return Ok(vec![]),
+    };
+    let role = match guild.contact == member_id {
+        true => Some(GuildRole::Contact),
+        false => db
+            .get_guild_membership(guild.id.expect("guild id shall not be empty"), member_id)?
+            .map(|membership| membership.role),
+    };
+    Ok(match role {
+        Some(GuildRole::Contact) => vec![Relation::Contact, Relation::GuildLibrarian],
+        Some(GuildRole::Librarian) => vec![Relation::GuildLibrarian],
+        Some(GuildRole::Member) | None => vec![],
+    })
+}
+
+/// Get the members of a guild with their roles within it
+pub fn get_guild_members<R: Repository>(
+    db: &R,
+    kc: &KeycloakCache,
+    claims: Option<Claims>,
+    id: GuildId,
+) -> Result<GetGuildMembers, Error> {
+    authorize(&claims, Action::ReadGuildMembers, &[])?;
+    if db.get::<Guild>(id)?.is_none() {
+        return Err(Error::ItemNotFound);
+    }
+
+    let resolver = EntityResolver::new(db, kc, &claims)?;
+    Ok(GetGuildMembers {
+        members: db
+            .get_guild_memberships_by_guild(id)?
+            .into_iter()
+            .map(|membership| GuildMember {
+                member: resolver.resolve_member(membership.member),
+                role: membership.role,
+            })
+            .collect(),
+    })
+}
+
+/// Add a member to a guild or change their role within it.
+/// Besides admins and aristocrats only the guild's contact persons may manage its members.
+pub fn put_guild_member<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    guild_id: GuildId,
+    member_id: MemberId,
+    membership: PutGuildMember,
+) -> Result<(), Error> {
+    let guild = match db.get::<Guild>(guild_id)? {
+        Some(guild) => guild,
+        None => return Err(Error::ItemNotFound),
+    };
+    authorize(
+        &claims,
+        Action::ManageGuildMembers,
+        &guild_relations(db, &claims, &guild)?,
+    )?;
+    if db.get::<crate::database::Member>(member_id)?.is_none() {
+        return Err(Error::ItemNotFound);
+    }
+
+    let role = membership.membership.role;
+    match db.get_guild_membership(guild_id, member_id)? {
+        Some(mut existing) => {
+            existing.role = role;
+            db.update(&existing)
+        }
+        None => db
+            .insert(&GuildMembership::new(None, guild_id, member_id, role))
+            .and(Ok(())),
+    }
+}
+
+/// Remove a member from a guild
+pub fn delete_guild_member<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    guild_id: GuildId,
+    member_id: MemberId,
+) -> Result<(), Error> {
+    let guild = match db.get::<Guild>(guild_id)? {
+        Some(guild) => guild,
+        None => return Err(Error::ItemNotFound),
+    };
+    authorize(
+        &claims,
+        Action::ManageGuildMembers,
+        &guild_relations(db, &claims, &guild)?,
+    )?;
+
+    match db.get_guild_membership(guild_id, member_id)? {
+        Some(membership) => db
+            .delete::<GuildMembership>(membership.id.expect("membership id shall not be empty"))
+            .and(Ok(())),
+        None => Err(Error::ItemNotFound),
+    }
+}
+
+/// Assemble a guild with its resolved contact person
+fn guild_with_contact(guild: Guild, resolver: &EntityResolver) -> GuildWithContact {
+    GuildWithContact {
+        id: guild.id.expect("guild id shall not be empty"),
+        name: guild.name,
+        address: guild.address,
+        contact: resolver.resolve_member(guild.contact),
+    }
+}
+
+/// Get all guilds from database
+pub fn get_guilds<R: Repository>(
+    db: &R,
+    kc: &KeycloakCache,
+    claims: Option<Claims>,
+) -> Result<GetGuilds, Error> {
+    authorize(&claims, Action::ReadGuilds, &[])?;
+    let resolver = EntityResolver::new(db, kc, &claims)?;
+
+    Ok(GetGuilds {
+        guilds: db
+            .get_all::<Guild>()?
+            .into_iter()
+            .map(|guild| guild_with_contact(guild, &resolver))
+            .collect(),
+    })
+}
+
+/// Get a guild with given id from database
+pub fn get_guild<R: Repository>(
+    db: &R,
+    kc: &KeycloakCache,
+    claims: Option<Claims>,
+    id: GuildId,
+) -> Result<GetGuild, Error> {
+    authorize(&claims, Action::ReadGuilds, &[])?;
+    match db.get::<Guild>(id)? {
+        Some(guild) => Ok(GetGuild {
+            guild: guild_with_contact(guild, &EntityResolver::new(db, kc, &claims)?),
+        }),
+        None => Err(Error::ItemNotFound),
+    }
+}
+
+/// Insert a guild into database
+pub fn post_guild<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    guild: PutPostGuild,
 ) -> Result<GuildId, Error> {
-    //TODO: Stub
-    Ok(1234)
+    authorize(&claims, Action::CreateGuild, &[])?;
+    let mut guild = guild.guild;
+    guild.id = None;
+    db.insert(&guild)
 }
 
-pub fn put_guild(_db: &Database, _claims: Option<Claims>, _guild: PutPostGuild) -> Result<(), Error> {
-    //TODO: Stub
-    Ok(())
+/// Update a specific guild in database.
+/// Besides admins and aristocrats only the guild's contact person may edit it.
+pub fn put_guild<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    guild: PutPostGuild,
+) -> Result<(), Error> {
+    let guild = guild.guild;
+    let id = guild.id.expect("guild id shall be set by the path");
+    match db.get::<Guild>(id)? {
+        Some(existing) => authorize(
+            &claims,
+            Action::UpdateGuild,
+            &guild_relations(db, &claims, &existing)?,
+        )?,
+        None => return Err(Error::ItemNotFound),
+    }
+
+    db.update(&guild)
 }
 
-pub fn delete_guild(_db: &Database, _claims: Option<Claims>, _guild: GuildId) -> Result<(), Error> {
-    //TODO: Stub
-    Ok(())
+/// Delete the guild with given id from database.
+/// Besides admins and aristocrats only the guild's contact person may delete it.
+pub fn delete_guild<R: Repository>(
+    db: &R,
+    claims: Option<Claims>,
+    id: GuildId,
+) -> Result<(), Error> {
+    match db.get::<Guild>(id)? {
+        Some(existing) => authorize(
+            &claims,
+            Action::DeleteGuild,
+            &guild_relations(db, &claims, &existing)?,
+        )?,
+        None => return Err(Error::ItemNotFound),
+    }
+
+    match db.delete::<Guild>(id) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Error::ItemNotFound),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::mock_idp;
+    use crate::auth::roles::{ROLE_LIBRARIAN, ROLE_MEMBER};
+    use crate::database::memory::MemoryRepository;
+    use crate::settings::LendingPolicy;
+
+    fn lending() -> Lending {
+        let policy = LendingPolicy {
+            max_loan_days: 28,
+            max_extensions: 2,
+        };
+        Lending {
+            hold_days: 7,
+            member: policy.clone(),
+            guild: policy,
+        }
+    }
+
+    /// A member with the given uid and the claims of a login as this member
+    fn member(db: &MemoryRepository, uid: &str, roles: Vec<&str>) -> (MemberId, Option<Claims>) {
+        let id = db
+            .insert(&crate::database::Member::new(None, String::from(uid)))
+            .unwrap();
+        (id, Some(mock_idp::claims(uid, roles)))
+    }
+
+    /// A title with two copies owned by the given member
+    fn title_with_books(db: &MemoryRepository, owner: MemberId) -> (TitleId, BookId, BookId) {
+        let system = db
+            .insert(&RpgSystem::new(None, String::from("Kobolde"), None))
+            .unwrap();
+        let title = db
+            .insert(&Title::new(
+                None,
+                String::from("Kobolde"),
+                system,
+                String::from("de"),
+                String::from("Prometheus Games"),
+                2031,
+                None,
+            ))
+            .unwrap();
+        let first = db
+            .insert(&Book::new(
+                None,
+                title,
+                owner,
+                EntityType::Member,
+                String::from("vergilbt"),
+                1,
+            ))
+            .unwrap();
+        let second = db
+            .insert(&Book::new(
+                None,
+                title,
+                owner,
+                EntityType::Member,
+                String::from("neu"),
+                2,
+            ))
+            .unwrap();
+        (title, first, second)
+    }
+
+    fn post_rental(book: BookId, rentee: MemberId) -> PostRental {
+        PostRental {
+            rental: PartialRental {
+                book,
+                rentee: UnresolvedEntity {
+                    entity_type: EntityType::Member,
+                    id: rentee,
+                },
+                to: Local::today().naive_local() + Duration::days(14),
+            },
+        }
+    }
+
+    fn days(days: i64) -> Date {
+        Local::today().naive_local() + Duration::days(days)
+    }
+
+    /// Check out a copy of a new title to a new member, returns the rental and a librarian's claims
+    fn rented_book(db: &MemoryRepository) -> (RentalId, TitleId, Option<Claims>) {
+        let (owner, _) = member(db, "owner", vec![ROLE_MEMBER]);
+        let (rentee, _) = member(db, "rentee", vec![ROLE_MEMBER]);
+        let (_, librarian) = member(db, "librarian", vec![ROLE_MEMBER, ROLE_LIBRARIAN]);
+        let (title, book, _) = title_with_books(db, owner);
+        let rental = checkout_book(db, &lending(), librarian.clone(), post_rental(book, rentee));
+        (rental.unwrap(), title, librarian)
+    }
+
+    fn extend(
+        db: &MemoryRepository,
+        claims: &Option<Claims>,
+        rental: RentalId,
+        to: Date,
+    ) -> Result<(), Error> {
+        let extension = PostRentalExtension { to };
+        extend_rental(db, &lending(), claims.clone(), rental, extension)
+    }
+
+    #[test]
+    fn checkout_book_longer_than_max_loan_days_fails() {
+        let db = MemoryRepository::new();
+        let (owner, _) = member(&db, "owner", vec![ROLE_MEMBER]);
+        let (rentee, _) = member(&db, "rentee", vec![ROLE_MEMBER]);
+        let (_, librarian) = member(&db, "librarian", vec![ROLE_MEMBER, ROLE_LIBRARIAN]);
+        let (_, book, _) = title_with_books(&db, owner);
+
+        let mut rental = post_rental(book, rentee);
+        rental.rental.to = days(29);
+        match checkout_book(&db, &lending(), librarian.clone(), rental) {
+            Err(Error::LendingPolicyViolation(_)) => (),
+            _ => panic!("Expected LendingPolicyViolation error"),
+        }
+        assert_eq!(db.get_all::<Rental>().unwrap().len(), 0);
+
+        let mut rental = post_rental(book, rentee);
+        rental.rental.to = days(28);
+        checkout_book(&db, &lending(), librarian, rental).unwrap();
+    }
+
+    #[test]
+    fn extend_rental_at_most_max_extensions_times() {
+        let db = MemoryRepository::new();
+        let (rental, _, librarian) = rented_book(&db);
+
+        extend(&db, &librarian, rental, days(20)).unwrap();
+        extend(&db, &librarian, rental, days(25)).unwrap();
+        match extend(&db, &librarian, rental, days(28)) {
+            Err(Error::LendingPolicyViolation(_)) => (),
+            _ => panic!("Expected LendingPolicyViolation error"),
+        }
+        let rental = db.get::<Rental>(rental).unwrap().unwrap();
+        assert_eq!((rental.to, rental.extensions), (days(25), 2));
+    }
+
+    /// Let a rental have started the given number of days ago
+    fn started_days_ago(db: &MemoryRepository, rental: RentalId, ago: i64) {
+        let mut rental = db.get::<Rental>(rental).unwrap().unwrap();
+        rental.from = days(-ago);
+        db.update(&rental).unwrap();
+    }
+
+    #[test]
+    fn extend_rental_longer_than_max_loan_days_fails() {
+        let db = MemoryRepository::new();
+        let (rental, _, librarian) = rented_book(&db);
+        started_days_ago(&db, rental, 10);
+
+        match extend(&db, &librarian, rental, days(19)) {
+            Err(Error::LendingPolicyViolation(_)) => (),
+            _ => panic!("Expected LendingPolicyViolation error"),
+        }
+        extend(&db, &librarian, rental, days(18)).unwrap();
+    }
+
+    #[test]
+    fn extend_rental_twice_longer_than_max_loan_days_fails() {
+        let db = MemoryRepository::new();
+        let (rental, _, librarian) = rented_book(&db);
+
+        started_days_ago(&db, rental, 10);
+
+        extend(&db, &librarian, rental, days(16)).unwrap();
+        match extend(&db, &librarian, rental, days(20)) {
+            Err(Error::LendingPolicyViolation(_)) => (),
+            _ => panic!("Expected LendingPolicyViolation error"),
+        }
+        let rental = db.get::<Rental>(rental).unwrap().unwrap();
+        assert_eq!((rental.to, rental.extensions), (days(16), 1));
+    }
+
+    #[test]
+    fn extend_reserved_rental_fails() {
+        let db = MemoryRepository::new();
+        let (rental, title, librarian) = rented_book(&db);
+        let (waiting, _) = member(&db, "waiting", vec![ROLE_MEMBER]);
+        db.insert(&Reservation::new(None, title, waiting, days(0)))
+            .unwrap();
+
+        match extend(&db, &librarian, rental, days(20)) {
+            Err(Error::LendingPolicyViolation(_)) => (),
+            _ => panic!("Expected LendingPolicyViolation error"),
+        }
+        let rental = db.get::<Rental>(rental).unwrap().unwrap();
+        assert_eq!((rental.to, rental.extensions), (days(14), 0));
+    }
+
+    #[test]
+    fn return_rental_twice_fails() {
+        let db = MemoryRepository::new();
+        let (rental, title, librarian) = rented_book(&db);
+        let (first, _) = member(&db, "first", vec![ROLE_MEMBER]);
+        let (second, _) = member(&db, "second", vec![ROLE_MEMBER]);
+        db.insert(&Reservation::new(None, title, first, days(-2)))
+            .unwrap();
+        db.insert(&Reservation::new(None, title, second, days(-1)))
+            .unwrap();
+
+        let lending = lending();
+        return_rental(&db, &lending, librarian.clone(), rental).unwrap();
+        match return_rental(&db, &lending, librarian, rental) {
+            Err(Error::RentalAlreadyReturned) => (),
+            _ => panic!("Expected RentalAlreadyReturned error"),
+        }
+        let book = db.get::<Rental>(rental).unwrap().unwrap().book;
+        let held = db
+            .get_all::<Reservation>()
+            .unwrap()
+            .into_iter()
+            .map(|reservation| (reservation.member, reservation.book))
+            .collect::<Vec<_>>();
+        assert_eq!(held, vec![(first, Some(book)), (second, None)]);
+    }
+
+    #[test]
+    fn get_titles_without_login() {
+        let db = MemoryRepository::new();
+        let (owner, _) = member(&db, "owner", vec![ROLE_MEMBER]);
+        title_with_books(&db, owner);
+
+        assert_eq!(get_titles(&db, None).unwrap().titles.len(), 1);
+        assert_eq!(get_rpgsystems(&db, None).unwrap().rpgsystems.len(), 1);
+    }
+
+    #[test]
+    fn get_unknown_title_fails() {
+        let db = MemoryRepository::new();
+        match get_title(&db, None, 42) {
+            Err(Error::ItemNotFound) => (),
+            _ => panic!("Expected ItemNotFound error"),
+        }
+    }
+
+    #[test]
+    fn get_books_of_deleted_guild_fails() {
+        let db = MemoryRepository::new();
+        let kc = KeycloakCache::new();
+        let (owner, librarian) = member(&db, "librarian", vec![ROLE_MEMBER, ROLE_LIBRARIAN]);
+        let (title, book, _) = title_with_books(&db, owner);
+        db.delete::<Book>(book).unwrap();
+        db.insert(&Book::new(
+            None,
+            title,
+            42,
+            EntityType::Guild,
+            String::from("neu"),
+            3,
+        ))
+        .unwrap();
+
+        match get_books(&db, &kc, librarian) {
+            Err(Error::IllegalState) => (),
+            _ => panic!("Expected IllegalState error"),
+        }
+    }
+
+    #[test]
+    fn get_guild_shows_contact_email_only_to_contact_and_librarians() {
+        let db = MemoryRepository::new();
+        let kc = KeycloakCache::new();
+        let (contact, contact_claims) = member(&db, "contact", vec![ROLE_MEMBER]);
+        let (_, other) = member(&db, "other", vec![ROLE_MEMBER]);
+        let (_, librarian) = member(&db, "librarian", vec![ROLE_MEMBER, ROLE_LIBRARIAN]);
+        kc.insert_user(mock_idp::MockUser::new("contact", vec![ROLE_MEMBER]).to_keycloak_user());
+        let guild = Guild::new(None, String::from("Gilde"), String::new(), contact);
+        let guild = db.insert(&guild).unwrap();
+
+        for (claims, email) in &[
+            (other, ""),
+            (contact_claims, "contact@example.com"),
+            (librarian, "contact@example.com"),
+        ] {
+            let found = get_guild(&db, &kc, claims.clone(), guild).unwrap().guild;
+            assert_eq!(&found.contact.email, email);
+        }
+    }
+
+    /// A reservation of the given member whose hold of the book expired yesterday
+    fn expired_hold(db: &MemoryRepository, title: TitleId, member: MemberId, book: BookId) {
+        let today = Local::today().naive_local();
+        let mut reservation = Reservation::new(None, title, member, today - Duration::days(10));
+        reservation.book = Some(book);
+        reservation.held_until = Some(today - Duration::days(1));
+        db.insert(&reservation).unwrap();
+    }
+
+    #[test]
+    fn checkout_book_passes_on_expired_hold() {
+        let db = MemoryRepository::new();
+        let (owner, _) = member(&db, "owner", vec![ROLE_MEMBER]);
+        let (late, _) = member(&db, "late", vec![ROLE_MEMBER]);
+        let (next, _) = member(&db, "next", vec![ROLE_MEMBER]);
+        let (_, librarian) = member(&db, "librarian", vec![ROLE_MEMBER, ROLE_LIBRARIAN]);
+        let (title, book, _) = title_with_books(&db, owner);
+        expired_hold(&db, title, late, book);
+        db.insert(&Reservation::new(None, title, next, days(0)))
+            .unwrap();
+
+        let lending = lending();
+        match checkout_book(&db, &lending, librarian.clone(), post_rental(book, owner)) {
+            Err(Error::BookNotAvailable) => (),
+            _ => panic!("Expected BookNotAvailable error"),
+        }
+        let reservations = db.get_all::<Reservation>().unwrap();
+        assert_eq!(reservations.len(), 1);
+        assert_eq!(reservations[0].member, next);
+        assert_eq!(reservations[0].book, Some(book));
+        assert_eq!(
+            reservations[0].held_until,
+            Some(Local::today().naive_local() + Duration::days(lending.hold_days as i64))
+        );
+
+        checkout_book(&db, &lending, librarian, post_rental(book, next)).unwrap();
+        assert_eq!(db.get_all::<Reservation>().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn get_reservations_passes_on_expired_hold() {
+        let db = MemoryRepository::new();
+        let (owner, _) = member(&db, "owner", vec![ROLE_MEMBER]);
+        let (late, _) = member(&db, "late", vec![ROLE_MEMBER]);
+        let (next, claims) = member(&db, "next", vec![ROLE_MEMBER]);
+        let (title, book, _) = title_with_books(&db, owner);
+        expired_hold(&db, title, late, book);
+        db.insert(&Reservation::new(None, title, next, days(0)))
+            .unwrap();
+
+        let reservations = get_reservations(&db, &lending(), claims, title)
+            .unwrap()
+            .reservations;
+        assert_eq!(reservations.len(), 1);
+        assert_eq!(reservations[0].member, next);
+        assert!(reservations[0].is_holding(Local::today().naive_local()));
+    }
+
+    #[test]
+    fn checkout_book_reduces_availability() {
+        let db = MemoryRepository::new();
+        let (owner, _) = member(&db, "owner", vec![ROLE_MEMBER]);
+        let (rentee, _) = member(&db, "rentee", vec![ROLE_MEMBER]);
+        let (_, librarian) = member(&db, "librarian", vec![ROLE_MEMBER, ROLE_LIBRARIAN]);
+        let (_, book, _) = title_with_books(&db, owner);
+
+        checkout_book(&db, &lending(), librarian, post_rental(book, rentee)).unwrap();
+
+        let titles = get_titles(&db, None).unwrap().titles;
+        assert_eq!(titles.len(), 1);
+        assert_eq!((titles[0].stock, titles[0].available), (Some(2), Some(1)));
+    }
+
+    #[test]
+    fn checkout_rented_book_fails() {
+        let db = MemoryRepository::new();
+        let (owner, _) = member(&db, "owner", vec![ROLE_MEMBER]);
+        let (rentee, _) = member(&db, "rentee", vec![ROLE_MEMBER]);
+        let (_, librarian) = member(&db, "librarian", vec![ROLE_MEMBER, ROLE_LIBRARIAN]);
+        let (_, book, _) = title_with_books(&db, owner);
+
+        let lending = lending();
+        checkout_book(&db, &lending, librarian.clone(), post_rental(book, rentee)).unwrap();
+        match checkout_book(&db, &lending, librarian, post_rental(book, owner)) {
+            Err(Error::BookNotAvailable) => (),
+            _ => panic!("Expected BookNotAvailable error"),
+        }
+    }
+
+    #[test]
+    fn members_may_not_checkout_books() {
+        let db = MemoryRepository::new();
+        let (owner, claims) = member(&db, "owner", vec![ROLE_MEMBER]);
+        let (rentee, _) = member(&db, "rentee", vec![ROLE_MEMBER]);
+        let (_, book, _) = title_with_books(&db, owner);
+
+        match checkout_book(&db, &lending(), claims, post_rental(book, rentee)) {
+            Err(Error::YouShallNotPassError) => (),
+            _ => panic!("Expected YouShallNotPassError"),
+        }
+        assert_eq!(db.get_all::<Rental>().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn post_member_inventory_assigns_next_external_inventory_id() {
+        let db = MemoryRepository::new();
+        let (owner, claims) = member(&db, "owner", vec![ROLE_MEMBER]);
+        let (title, _, _) = title_with_books(&db, owner);
+        let inventory = PutMemberInvetory {
+            book: PartialBook {
+                title,
+                owner: UnresolvedEntity {
+                    entity_type: EntityType::Member,
+                    id: owner,
+                },
+                quality: String::from("neu"),
+                external_inventory_id: None,
+            },
+        };
+
+        let book = post_member_inventory(&db, claims, owner, inventory).unwrap();
+
+        let book = db.get::<Book>(book).unwrap().unwrap();
+        assert_eq!(book.external_inventory_id, 3);
+    }
+
+    #[test]
+    fn post_member_inventory_of_other_member_fails() {
+        let db = MemoryRepository::new();
+        let (owner, _) = member(&db, "owner", vec![ROLE_MEMBER]);
+        let (_, claims) = member(&db, "other", vec![ROLE_MEMBER]);
+        let (title, _, _) = title_with_books(&db, owner);
+        let inventory = PutMemberInvetory {
+            book: PartialBook {
+                title,
+                owner: UnresolvedEntity {
+                    entity_type: EntityType::Member,
+                    id: owner,
+                },
+                quality: String::from("neu"),
+                external_inventory_id: Some(42),
+            },
+        };
+
+        match post_member_inventory(&db, claims, owner, inventory) {
+            Err(Error::YouShallNotPassError) => (),
+            _ => panic!("Expected YouShallNotPassError"),
+        }
+        assert_eq!(db.get_all::<Book>().unwrap().len(), 2);
+    }
 }
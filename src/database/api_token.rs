@@ -0,0 +1,222 @@
+use super::*;
+use crate::serde_formats;
+use serde::Serialize;
+
+/// Id type for ApiToken
+pub type ApiTokenId = Id;
+
+/// Long-lived credential of a member for scripts and kiosks.
+/// Only the SHA-256 hash of the secret is stored.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone)]
+pub struct ApiToken {
+    /// Id
+    pub id: Option<ApiTokenId>,
+    /// Member acting with the token
+    pub member: MemberId,
+    /// Name given by the member
+    pub name: String,
+    /// Hex encoded SHA-256 hash of the secret
+    #[serde(skip_serializing)]
+    pub hash: String,
+    /// Roles granted to the token, a subset of the member's roles
+    pub roles: Vec<String>,
+    /// Day the token was created
+    #[serde(with = "serde_formats::naive_date")]
+    pub created: Date,
+    /// Last day the token is accepted
+    #[serde(with = "serde_formats::naive_date")]
+    pub expires: Date,
+}
+
+impl ApiToken {
+    /// Construct a new ApiToken object with given parameters
+    pub fn new(
+        id: Option<ApiTokenId>,
+        member: MemberId,
+        name: String,
+        hash: String,
+        roles: Vec<String>,
+        created: Date,
+        expires: Date,
+    ) -> ApiToken {
+        ApiToken {
+            id: id,
+            member: member,
+            name: name,
+            hash: hash,
+            roles: roles,
+            created: created,
+            expires,
+        }
+    }
+
+    pub fn from_db(
+        id: Option<ApiTokenId>,
+        member: MemberId,
+        name: String,
+        hash: String,
+        roles: String,
+        created: Date,
+        expires: Date,
+    ) -> ApiToken {
+        ApiToken::new(
+            id,
+            member,
+            name,
+            hash,
+            roles
+                .split(',')
+                .filter(|role| !role.is_empty())
+                .map(String::from)
+                .collect(),
+            created,
+            expires,
+        )
+    }
+}
+
+impl DMO for ApiToken {
+    type Id = ApiTokenId;
+
+    fn get(db: &Database, token_id: ApiTokenId) -> Result<Option<ApiToken>, Error> {
+
+        let mut results = db.backend
+        .query(
+            "select token_id, member_by_id, name, token_hash, roles, created_date, expires_date from api_tokens where token_id=:token_id;",
+            params!{
+                "token_id" => token_id,
+            },
+        )?
+        .into_iter()
+        .map(|row| {
+            let (id, member, name, hash, roles, created, expires) = from_row(row);
+            ApiToken::from_db(id, member, name, hash, roles, created, expires)
+        }).collect::<Vec<ApiToken>>();
+        return Ok(results.pop());
+    }
+
+    fn get_all(db: &Database) -> Result<Vec<ApiToken>, Error> {
+        Ok(db.backend.query("select token_id, member_by_id, name, token_hash, roles, created_date, expires_date from api_tokens;", params!{})?
+        .into_iter()
+        .map(|row| {
+            let (id, member, name, hash, roles, created, expires) = from_row(row);
+            ApiToken::from_db(id, member, name, hash, roles, created, expires)
+        }).collect())
+    }
+
+    fn insert(db: &Database, inp: &ApiToken) -> Result<ApiTokenId, Error> {
+        let roles = inp.roles.join(",");
+        check_varchar_length!(inp.name, roles);
+        check_date!(inp.created, inp.expires);
+        Ok(db.backend.execute("insert into api_tokens (member_by_id, name, token_hash, roles, created_date, expires_date) values (:member, :name, :hash, :roles, :created, :expires)",
+        params!{
+            "member" => inp.member,
+            "name" => inp.name.clone(),
+            "hash" => inp.hash.clone(),
+            "roles" => roles.clone(),
+            "created" => inp.created,
+            "expires" => inp.expires,
+        }).map(|changes| changes.last_insert_id)?)
+    }
+
+    fn update(db: &Database, token: &ApiToken) -> Result<(), Error> {
+        let roles = token.roles.join(",");
+        check_varchar_length!(token.name, roles);
+        check_date!(token.created, token.expires);
+        Ok(db.backend.execute("update api_tokens set member_by_id=:member, name=:name, token_hash=:hash, roles=:roles, created_date=:created, expires_date=:expires where token_id=:id;",
+        params!{
+            "member" => token.member,
+            "name" => token.name.clone(),
+            "hash" => token.hash.clone(),
+            "roles" => roles.clone(),
+            "created" => token.created,
+            "expires" => token.expires,
+            "id" => token.id,
+        }).and(Ok(()))?)
+    }
+
+    fn delete(db: &Database, id: Id) -> Result<bool, Error> {
+        Ok(db
+            .backend
+            .execute(
+                "delete from api_tokens where token_id=:id",
+                params! {
+                    "id" => id,
+                },
+            )
+            .and_then(|changes| match changes.affected_rows {
+                1 => Ok(true),
+                0 => Ok(false),
+                _ => Err(Error::IllegalState),
+            })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::test_util::*;
+    use crate::database::*;
+
+    #[test]
+    fn insert_api_token_correct() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = db
+            .insert(&Member::new(None, _s("external_id")))
+            .and_then(|member_id| {
+                let token = ApiToken::new(
+                    None,
+                    member_id,
+                    _s("kiosk"),
+                    _s("0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"),
+                    vec![_s("member"), _s("librarian")],
+                    _d(2019, 3, 12),
+                    _d(2020, 3, 12),
+                );
+                db.insert(&token).and_then(|id| Ok((id, token)))
+            })
+            .and_then(|(id, token)| {
+                db.get_api_token_by_hash(&token.hash).and_then(|rec_token| {
+                    Ok(rec_token.map_or(false, |fetched_token| {
+                        ApiToken {
+                            id: Some(id),
+                            ..token
+                        } == fetched_token
+                    }))
+                })
+            });
+        teardown(settings);
+        match result {
+            Ok(true) => (),
+            Ok(false) => panic!("Inserted api token is not in DB :("),
+            _ => {
+                result.unwrap();
+                ()
+            }
+        }
+    }
+
+    #[test]
+    fn insert_api_token_name_too_long() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = db
+            .insert(&Member::new(None, _s("external_id")))
+            .and_then(|member_id| {
+                db.insert(&ApiToken::new(
+                    None,
+                    member_id,
+                    _s(TOO_LONG_STRING),
+                    _s("0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"),
+                    vec![_s("member")],
+                    _d(2019, 3, 12),
+                    _d(2020, 3, 12),
+                ))
+            });
+        teardown(settings);
+        match result {
+            Err(Error::DataTooLong(_)) => (),
+            _ => panic!("Expected DatabaseError::FieldError(FieldError::DataTooLong(\"name\")"),
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use crate::error::Error;
+use crate::settings;
+use chrono::NaiveDate;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+mod mysql_backend;
+mod sqlite_backend;
+
+pub use self::mysql_backend::MySqlBackend;
+pub use self::sqlite_backend::SqliteBackend;
+
+/// A value bound to or read from a query
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Text(String),
+    Date(NaiveDate),
+}
+
+/// Named parameters of a query, written as `:name` in the statement
+pub type Params = Vec<(&'static str, Value)>;
+
+/// SQL dialect spoken by a backend, for the few statements that differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    MySql,
+    Sqlite,
+}
+
+/// Result of a statement changing rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Changes {
+    pub affected_rows: u64,
+    pub last_insert_id: u64,
+}
+
+/// Storage the `Database` runs its statements on
+pub trait Backend: Send + Sync {
+    /// Dialect of the statements this backend understands
+    fn dialect(&self) -> Dialect;
+    /// Runs a statement and returns all rows of its result
+    fn query(&self, sql: &str, params: Params) -> Result<Vec<Row>, Error>;
+    /// Runs a statement changing rows
+    fn execute(&self, sql: &str, params: Params) -> Result<Changes, Error>;
+    /// Runs several statements without parameters, e.g. a migration
+    fn execute_batch(&self, sql: &str) -> Result<(), Error>;
+    /// Runs `f` while holding the lock with the given name,
+    /// so concurrently starting instances do not run it at the same time
+    fn exclusive(&self, name: &str, f: &mut dyn FnMut() -> Result<(), Error>)
+        -> Result<(), Error>;
+    /// Starts a transaction on a connection of its own.
+    /// The returned backend runs all statements within the transaction
+    /// until `commit;` or `rollback;` is executed on it, see `Database::transaction`.
+    fn begin(&self) -> Result<Box<dyn Backend>, Error>;
+}
+
+/// Opens the backend selected in the settings
+pub fn connect(settings: &settings::Database) -> Result<Arc<dyn Backend>, Error> {
+    Ok(match settings.backend {
+        settings::Backend::Mysql => Arc::new(MySqlBackend::connect(settings)?),
+        settings::Backend::Sqlite => Arc::new(SqliteBackend::open(settings)?),
+    })
+}
+
+/// A row of a query result
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    values: Vec<Value>,
+}
+
+impl Row {
+    pub fn new(values: Vec<Value>) -> Row {
+        Row { values }
+    }
+}
+
+impl IntoIterator for Row {
+    type Item = Value;
+    type IntoIter = std::vec::IntoIter<Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.values.into_iter()
+    }
+}
+
+/// Converts a row into the given type.
+/// Panics if the columns do not fit the type, like a wrong query would.
+pub fn from_row<T: FromRow>(row: Row) -> T {
+    T::from_row(row).unwrap_or_else(|err| panic!("Could not convert row: {}", err))
+}
+
+/// Types a single column can be converted to
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, String>;
+}
+
+/// Types a whole row can be converted to
+pub trait FromRow: Sized {
+    fn from_row(row: Row) -> Result<Self, String>;
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Value {
+        Value::Text(String::from(value))
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Value {
+        Value::Text(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Int(value as i64)
+    }
+}
+
+impl From<NaiveDate> for Value {
+    fn from(value: NaiveDate) -> Value {
+        Value::Date(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Value {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+macro_rules! value_from_int {
+    ($variant:ident, $( $t:ty ),+) => {
+        $(impl From<$t> for Value {
+            fn from(value: $t) -> Value {
+                Value::$variant(value.into())
+            }
+        })*
+    }
+}
+
+value_from_int!(Int, i16, i32, i64);
+value_from_int!(UInt, u16, u32, u64);
+
+macro_rules! int_from_value {
+    ($( $t:ty ),+) => {
+        $(impl FromValue for $t {
+            fn from_value(value: Value) -> Result<$t, String> {
+                match value {
+                    Value::Int(x) => <$t>::try_from(x).map_err(|err| err.to_string()),
+                    Value::UInt(x) => <$t>::try_from(x).map_err(|err| err.to_string()),
+                    // Sums of integers are decimals in MySQL
+                    Value::Text(ref x) => x.parse::<$t>().map_err(|err| err.to_string()),
+                    other => Err(format!("Can not convert {:?} to {}", other, stringify!($t))),
+                }
+            }
+        })*
+    }
+}
+
+int_from_value!(i16, i32, i64, u16, u32, u64);
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<bool, String> {
+        u64::from_value(value).map(|x| x != 0)
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<String, String> {
+        match value {
+            Value::Text(x) => Ok(x),
+            other => Err(format!("Can not convert {:?} to String", other)),
+        }
+    }
+}
+
+impl FromValue for NaiveDate {
+    fn from_value(value: Value) -> Result<NaiveDate, String> {
+        match value {
+            Value::Date(x) => Ok(x),
+            // SQLite stores dates as text
+            Value::Text(ref x) => {
+                NaiveDate::parse_from_str(x, "%Y-%m-%d").map_err(|err| err.to_string())
+            }
+            other => Err(format!("Can not convert {:?} to NaiveDate", other)),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Option<T>, String> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+/// A single column
+impl<T: FromValue> FromRow for T {
+    fn from_row(row: Row) -> Result<T, String> {
+        let mut values = row.into_iter();
+        match (values.next(), values.next()) {
+            (Some(value), None) => T::from_value(value),
+            _ => Err(String::from("Expected exactly one column")),
+        }
+    }
+}
+
+macro_rules! tuple_from_row {
+    ($( $t:ident ),+) => {
+        impl<$( $t: FromValue ),+> FromRow for ($( $t, )+) {
+            fn from_row(row: Row) -> Result<Self, String> {
+                let mut values = row.into_iter();
+                let tuple = ($(
+                    $t::from_value(values.next().ok_or_else(|| String::from("Too few columns"))?)?,
+                )+);
+                match values.next() {
+                    None => Ok(tuple),
+                    Some(_) => Err(String::from("Too many columns")),
+                }
+            }
+        }
+    }
+}
+
+tuple_from_row!(A);
+tuple_from_row!(A, B);
+tuple_from_row!(A, B, C);
+tuple_from_row!(A, B, C, D);
+tuple_from_row!(A, B, C, D, E);
+tuple_from_row!(A, B, C, D, E, F);
+tuple_from_row!(A, B, C, D, E, F, G);
+tuple_from_row!(A, B, C, D, E, F, G, H);
+tuple_from_row!(A, B, C, D, E, F, G, H, I);
+tuple_from_row!(A, B, C, D, E, F, G, H, I, J);
+tuple_from_row!(A, B, C, D, E, F, G, H, I, J, K);
+tuple_from_row!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_row_into_tuple() {
+        let row = Row::new(vec![
+            Value::UInt(1),
+            Value::Text(String::from("Kobolde")),
+            Value::Null,
+            Value::Text(String::from("2019-03-12")),
+        ]);
+        let (id, name, shortname, date): (u64, String, Option<String>, NaiveDate) = from_row(row);
+        assert_eq!(
+            (id, name, shortname, date),
+            (1, String::from("Kobolde"), None, NaiveDate::from_ymd(2019, 3, 12))
+        );
+    }
+
+    #[test]
+    fn convert_decimal_sum_into_integer() {
+        assert_eq!(u32::from_value(Value::Text(String::from("3"))), Ok(3));
+        assert!(u32::from_value(Value::Int(-1)).is_err());
+    }
+
+    #[test]
+    fn refuse_in_memory_sqlite_databases() {
+        let mut settings = crate::settings::TestSettings::new().unwrap().database;
+        settings.backend = settings::Backend::Sqlite;
+        for path in &[
+            "",
+            ":memory:",
+            "file::memory:?cache=shared",
+            "file:db?mode=memory",
+        ] {
+            settings.database = String::from(*path);
+            match connect(&settings) {
+                Err(Error::UnsupportedDatabase(_)) => (),
+                _ => panic!("Expected UnsupportedDatabase error for {:?}", path),
+            }
+        }
+    }
+
+    #[test]
+    fn refuse_row_with_wrong_column_count() {
+        let row = Row::new(vec![Value::Int(1), Value::Int(2)]);
+        assert!(<(i64,)>::from_row(row.clone()).is_err());
+        assert!(<(i64, i64, i64)>::from_row(row).is_err());
+    }
+}
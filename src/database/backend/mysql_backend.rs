@@ -0,0 +1,194 @@
+use super::*;
+use chrono::Datelike;
+use std::sync::{Mutex, MutexGuard};
+
+/// MySQL or MariaDB server, accessed through a connection pool
+pub struct MySqlBackend {
+    pool: mysql::Pool,
+}
+
+impl MySqlBackend {
+    pub fn connect(settings: &settings::Database) -> Result<MySqlBackend, Error> {
+        let mut opts = mysql::OptsBuilder::default();
+        opts.ip_or_hostname(settings.hostname.clone())
+            .user(settings.username.clone())
+            .pass(settings.password.clone())
+            .db_name(Some(settings.database.clone()))
+            .prefer_socket(false);
+
+        match settings.port {
+            Some(port) => {
+                opts.tcp_port(port);
+            }
+            None => {}
+        }
+
+        let pool = mysql::Pool::new(opts)?;
+
+        return Ok(MySqlBackend { pool: pool });
+    }
+}
+
+/// A connection taken from the pool for a transaction, see `Backend::begin`
+pub struct MySqlTransaction {
+    conn: Mutex<mysql::PooledConn>,
+}
+
+impl MySqlTransaction {
+    fn lock(&self) -> MutexGuard<mysql::PooledConn> {
+        self.conn.lock().expect("Can not lock mysql connection mutex.")
+    }
+}
+
+impl Drop for MySqlTransaction {
+    fn drop(&mut self) {
+        // The connection goes back to the pool, so nothing may be left open, e.g. after a panic.
+        // After a commit this does nothing.
+        if let Ok(conn) = self.conn.get_mut() {
+            let _ = conn.query("rollback;");
+        }
+    }
+}
+
+/// Waits up to a minute for the named lock, which get_lock reports with 0 on timeout
+fn get_lock(conn: &mut mysql::PooledConn, name: &str) -> Result<(), Error> {
+    match conn.first_exec::<_, _, Option<i64>>("select get_lock(?, 60);", (name,))? {
+        Some(Some(1)) => Ok(()),
+        _ => Err(Error::LockTimeout(String::from(name))),
+    }
+}
+
+fn to_mysql_params(params: Params) -> mysql::Params {
+    match params.is_empty() {
+        true => mysql::Params::Empty,
+        false => mysql::Params::Named(
+            params
+                .into_iter()
+                .map(|(name, value)| (String::from(name), to_mysql_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn to_mysql_value(value: Value) -> mysql::Value {
+    match value {
+        Value::Null => mysql::Value::NULL,
+        Value::Int(x) => mysql::Value::Int(x),
+        Value::UInt(x) => mysql::Value::UInt(x),
+        Value::Float(x) => mysql::Value::Float(x),
+        Value::Text(x) => mysql::Value::Bytes(x.into_bytes()),
+        Value::Date(x) => {
+            mysql::Value::Date(x.year() as u16, x.month() as u8, x.day() as u8, 0, 0, 0, 0)
+        }
+    }
+}
+
+fn from_mysql_value(value: mysql::Value) -> Value {
+    match value {
+        mysql::Value::NULL => Value::Null,
+        mysql::Value::Int(x) => Value::Int(x),
+        mysql::Value::UInt(x) => Value::UInt(x),
+        mysql::Value::Float(x) => Value::Float(x),
+        mysql::Value::Bytes(x) => Value::Text(String::from_utf8_lossy(&x).into_owned()),
+        mysql::Value::Date(year, month, day, _, _, _, _) => {
+            NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32)
+                .map_or_else(
+                    || Value::Text(format!("{:04}-{:02}-{:02}", year, month, day)),
+                    Value::Date,
+                )
+        }
+        other => Value::Text(other.as_sql(true)),
+    }
+}
+
+fn to_rows(result: mysql::QueryResult) -> Result<Vec<Row>, Error> {
+    let mut rows = vec![];
+    for row in result {
+        rows.push(Row::new(
+            row?.unwrap().into_iter().map(from_mysql_value).collect(),
+        ));
+    }
+    Ok(rows)
+}
+
+fn to_changes(result: mysql::QueryResult) -> Changes {
+    Changes {
+        affected_rows: result.affected_rows(),
+        last_insert_id: result.last_insert_id(),
+    }
+}
+
+impl Backend for MySqlBackend {
+    fn dialect(&self) -> Dialect {
+        Dialect::MySql
+    }
+
+    fn query(&self, sql: &str, params: Params) -> Result<Vec<Row>, Error> {
+        to_rows(self.pool.prep_exec(sql, to_mysql_params(params))?)
+    }
+
+    fn execute(&self, sql: &str, params: Params) -> Result<Changes, Error> {
+        Ok(to_changes(self.pool.prep_exec(sql, to_mysql_params(params))?))
+    }
+
+    fn execute_batch(&self, sql: &str) -> Result<(), Error> {
+        self.pool.get_conn()?.query(sql)?;
+        Ok(())
+    }
+
+    fn exclusive(
+        &self,
+        name: &str,
+        f: &mut dyn FnMut() -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        // Named locks belong to a connection, so it is kept until the lock is released
+        let mut conn = self.pool.get_conn()?;
+        get_lock(&mut conn, name)?;
+        let result = f();
+        conn.prep_exec("select release_lock(?);", (name,))?;
+        result
+    }
+
+    fn begin(&self) -> Result<Box<dyn Backend>, Error> {
+        let mut conn = self.pool.get_conn()?;
+        conn.query("start transaction;")?;
+        Ok(Box::new(MySqlTransaction {
+            conn: Mutex::new(conn),
+        }))
+    }
+}
+
+impl Backend for MySqlTransaction {
+    fn dialect(&self) -> Dialect {
+        Dialect::MySql
+    }
+
+    fn query(&self, sql: &str, params: Params) -> Result<Vec<Row>, Error> {
+        to_rows(self.lock().prep_exec(sql, to_mysql_params(params))?)
+    }
+
+    fn execute(&self, sql: &str, params: Params) -> Result<Changes, Error> {
+        Ok(to_changes(self.lock().prep_exec(sql, to_mysql_params(params))?))
+    }
+
+    fn execute_batch(&self, sql: &str) -> Result<(), Error> {
+        self.lock().query(sql)?;
+        Ok(())
+    }
+
+    fn exclusive(
+        &self,
+        name: &str,
+        f: &mut dyn FnMut() -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        get_lock(&mut self.lock(), name)?;
+        let result = f();
+        self.lock().prep_exec("select release_lock(?);", (name,))?;
+        result
+    }
+
+    fn begin(&self) -> Result<Box<dyn Backend>, Error> {
+        // Database::transaction joins a running transaction instead
+        Err(Error::IllegalState)
+    }
+}
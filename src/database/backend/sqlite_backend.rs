@@ -0,0 +1,158 @@
+use super::*;
+use rusqlite::types::{ToSql, ToSqlOutput, Value as SqliteValue};
+use rusqlite::Connection;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// SQLite file database, `database` in the settings is the path of the file
+pub struct SqliteBackend {
+    path: String,
+    conn: Mutex<Connection>,
+}
+
+impl SqliteBackend {
+    pub fn open(settings: &settings::Database) -> Result<SqliteBackend, Error> {
+        if is_in_memory(&settings.database) {
+            return Err(Error::UnsupportedDatabase(format!(
+                "sqlite database {:?} is not a file, transactions would not see its data",
+                settings.database
+            )));
+        }
+        SqliteBackend::open_file(&settings.database)
+    }
+
+    fn open_file(path: &str) -> Result<SqliteBackend, Error> {
+        let conn = Connection::open(path)?;
+        // Other processes and transactions may hold the file lock for a moment
+        conn.busy_timeout(Duration::from_secs(10))?;
+        conn.execute_batch("pragma foreign_keys = on;")?;
+        Ok(SqliteBackend {
+            path: String::from(path),
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<Connection> {
+        self.conn.lock().expect("Can not lock sqlite connection mutex.")
+    }
+}
+
+/// In-memory and temporary databases exist for one connection only,
+/// but every transaction opens a connection of its own
+fn is_in_memory(path: &str) -> bool {
+    path.is_empty()
+        || path == ":memory:"
+        || (path.starts_with("file:")
+            && (path.contains(":memory:") || path.contains("mode=memory")))
+}
+
+impl ToSql for Value {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput> {
+        Ok(ToSqlOutput::Owned(match *self {
+            Value::Null => SqliteValue::Null,
+            Value::Int(x) => SqliteValue::Integer(x),
+            Value::UInt(x) => SqliteValue::Integer(
+                i64::try_from(x)
+                    .map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?,
+            ),
+            Value::Float(x) => SqliteValue::Real(x),
+            Value::Text(ref x) => SqliteValue::Text(x.clone()),
+            Value::Date(x) => SqliteValue::Text(x.format("%Y-%m-%d").to_string()),
+        }))
+    }
+}
+
+fn from_sqlite_value(value: SqliteValue) -> Value {
+    match value {
+        SqliteValue::Null => Value::Null,
+        SqliteValue::Integer(x) => Value::Int(x),
+        SqliteValue::Real(x) => Value::Float(x),
+        SqliteValue::Text(x) => Value::Text(x),
+        SqliteValue::Blob(x) => Value::Text(String::from_utf8_lossy(&x).into_owned()),
+    }
+}
+
+/// Binds only the parameters used by the statement, SQLite refuses unknown names
+fn bind<'a>(
+    stmt: &rusqlite::Statement,
+    names: &'a [String],
+    params: &'a Params,
+) -> Result<Vec<(&'a str, &'a dyn ToSql)>, Error> {
+    let mut bound: Vec<(&str, &dyn ToSql)> = vec![];
+    for (name, (_, value)) in names.iter().zip(params.iter()) {
+        if stmt.parameter_index(name)?.is_some() {
+            bound.push((name.as_str(), value));
+        }
+    }
+    Ok(bound)
+}
+
+fn parameter_names(params: &Params) -> Vec<String> {
+    params.iter().map(|(name, _)| format!(":{}", name)).collect()
+}
+
+impl Backend for SqliteBackend {
+    fn dialect(&self) -> Dialect {
+        Dialect::Sqlite
+    }
+
+    fn query(&self, sql: &str, params: Params) -> Result<Vec<Row>, Error> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(sql)?;
+        let names = parameter_names(&params);
+        let bound = bind(&stmt, &names, &params)?;
+        let columns = stmt.column_count();
+        let mut result = stmt.query_named(&bound)?;
+        let mut rows = vec![];
+        while let Some(row) = result.next()? {
+            let mut values = Vec::with_capacity(columns);
+            for index in 0..columns {
+                values.push(from_sqlite_value(row.get(index)?));
+            }
+            rows.push(Row::new(values));
+        }
+        Ok(rows)
+    }
+
+    fn execute(&self, sql: &str, params: Params) -> Result<Changes, Error> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(sql)?;
+        let names = parameter_names(&params);
+        let bound = bind(&stmt, &names, &params)?;
+        let affected_rows = stmt.execute_named(&bound)?;
+        Ok(Changes {
+            affected_rows: affected_rows as u64,
+            last_insert_id: conn.last_insert_rowid() as u64,
+        })
+    }
+
+    fn execute_batch(&self, sql: &str) -> Result<(), Error> {
+        Ok(self.lock().execute_batch(sql)?)
+    }
+
+    fn exclusive(
+        &self,
+        _name: &str,
+        f: &mut dyn FnMut() -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        // An immediate transaction keeps other processes from writing in between,
+        // and schema changes of SQLite are rolled back with it
+        self.execute_batch("begin immediate;")?;
+        match f() {
+            Ok(()) => self.execute_batch("commit;"),
+            Err(err) => {
+                self.execute_batch("rollback;")?;
+                Err(err)
+            }
+        }
+    }
+
+    fn begin(&self) -> Result<Box<dyn Backend>, Error> {
+        // A connection of its own keeps statements of other threads out of the transaction.
+        // Taking the write lock right away avoids failing to upgrade a read lock later on.
+        // Dropping the connection rolls back whatever was left open.
+        let tx = SqliteBackend::open_file(&self.path)?;
+        tx.execute_batch("begin immediate;")?;
+        Ok(Box::new(tx))
+    }
+}
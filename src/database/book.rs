@@ -1,23 +1,26 @@
 use super::*;
 use serde::{Serialize, Deserialize};
-use mysql::params;
 
 /// Id type for Book
 pub type BookId = Id;
 pub type ExternalInventoryId = u64;
 
 /// Book describes a specific (physical) book
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, DMO)]
+#[dmo(table = "books", id = "book_id")]
 pub struct Book {
     /// Unique id
     pub id: Option<BookId>,
     /// Title of (physical) book
+    #[dmo(column = "title_by_id")]
     pub title: TitleId,
     /// Type of current possessor
     pub owner_type: EntityType,
     /// Id of current possessor
+    #[dmo(entity_type = "owner_type", member = "owner_member_by_id", guild = "owner_guild_by_id")]
     pub owner: EntityId,
     /// Condition of book
+    #[dmo(varchar)]
     pub quality: String,
     /// External id written onto a book and in guild inventory lists
     pub external_inventory_id: ExternalInventoryId,
@@ -42,137 +45,13 @@ impl Book {
             external_inventory_id: external_inventory_id,
         };
     }
-
-    /// Construct a new Book object with given parameters with manual input of owner type
-    pub fn from_db(
-        id: BookId,
-        title: TitleId,
-        owner_member: Option<MemberId>,
-        owner_guild: Option<GuildId>,
-        owner_type: String,
-        quality: String,
-        external_inventory_id: ExternalInventoryId,
-    ) -> Result<Book, String> {
-        let owner_type = match EntityType::from_str(owner_type.as_str()) {
-            Ok(x) => x,
-            Err(_) => return Err(String::from("Bad owner_type")),
-        };
-
-        let owner: EntityId =
-            match match owner_type {
-                EntityType::Member => owner_member,
-                EntityType::Guild => owner_guild,
-            } {
-                Some(x) => x,
-                None => return Err(String::from(
-                    "Field 'owner_member' or 'owner_guild' is not set according to 'owner_type'.",
-                )),
-            };
-        Ok(Book::new(
-            Some(id),
-            title,
-            owner,
-            owner_type,
-            quality,
-            external_inventory_id,
-        ))
-    }
-}
-
-impl DMO for Book {
-    type Id = BookId;
-
-    fn get_all(db: &Database) -> Result<Vec<Book>, Error> {
-        Ok(db.pool.prep_exec("select book_id, title_by_id, owner_member_by_id, owner_guild_by_id, owner_type, quality, external_inventory_id from books;",())
-    .map(|result| {
-        result.map(|x| x.unwrap()).map(|row| {
-            let (id, title, owner_member, owner_guild, owner_type, quality, external_inventory_id) = mysql::from_row(row);
-            //FIXME: @FutureMe: You should have handled the error directly!!!! You stupid prick.
-            Book::from_db(id, title, owner_member, owner_guild, owner_type, quality, external_inventory_id).unwrap()
-        }).collect()
-    })?)
-    }
-
-    fn get(db: &Database, book_id: BookId) -> Result<Option<Book>, Error> {
-        let mut results = db.pool
-        .prep_exec(
-            "select book_id, title_by_id, owner_member_by_id, owner_guild_by_id, owner_type, quality, external_inventory_id from books where book_id=:book_id;",
-            params!{
-                "book_id" => book_id,
-            },
-        )
-        .map(|result| {
-            result.map(|x| x.unwrap()).map(|row| {
-                let (id, title, owner_member, owner_guild, owner_type, quality, external_inventory_id) = mysql::from_row(row);
-                //FIXME: @FutureMe: You should have handled the error directly!!!! You stupid prick.possessor
-                Book::from_db(id, title, owner_member, owner_guild, owner_type, quality, external_inventory_id).unwrap()
-            }).collect::<Vec<Book>>()
-        })?;
-        return Ok(results.pop());
-    }
-
-    fn insert(db: &Database, inp: &Book) -> Result<BookId, Error> {
-        check_varchar_length!(inp.quality);
-        Ok(db.pool.prep_exec("insert into books (title_by_id, owner_member_by_id, owner_guild_by_id, quality, external_inventory_id) values (:title, :owner_member, :owner_guild, :quality, :external_inventory_id)",
-        params!{
-            "title" => inp.title,
-            "owner_member" => match inp.owner_type {
-                EntityType::Member => Some(inp.owner),
-                EntityType::Guild => None,
-            },
-            "owner_guild" => match inp.owner_type {
-                EntityType::Member => None,
-                EntityType::Guild => Some(inp.owner),
-            },
-            "quality" => inp.quality.clone(),
-            "external_inventory_id" => inp.external_inventory_id,
-        }).map(|result| {
-                result.last_insert_id()
-        })?)
-    }
-
-    fn update(db: &Database, book: &Book) -> Result<(), Error> {
-        check_varchar_length!(book.quality);
-        Ok(db.pool.prep_exec("update books set title_by_id=:title, owner_member_by_id=:owner_member, owner_guild_by_id=:owner_guild, quality=:quality, external_inventory_id=:external_inventory_id where book_id=:id;",
-        params!{
-            "title" => book.title,
-            "owner_member" => match book.owner_type {
-                EntityType::Member => Some(book.owner),
-                EntityType::Guild => None,
-            },
-            "owner_guild" => match book.owner_type {
-                EntityType::Member => None,
-                EntityType::Guild => Some(book.owner),
-            },
-            "quality" => book.quality.clone(),
-            "external_inventory_id" => book.external_inventory_id,
-            "id" => book.id,
-        }).and(Ok(()))?)
-    }
-
-    fn delete(db: &Database, id: Id) -> Result<bool, Error> {
-        Ok(db
-            .pool
-            .prep_exec(
-                "delete from books where book_id=:id",
-                params! {
-                    "id" => id,
-                },
-            )
-            .map_err(|err| Error::DatabaseError(err))
-            .and_then(|result| match result.affected_rows() {
-                1 => Ok(true),
-                0 => Ok(false),
-                _ => Err(Error::IllegalState),
-            })?)
-    }
 }
 
 #[cfg(test)]
 mod tests {
-    use database::test_util::*;
+    use crate::database::test_util::*;
 
-    use database::*;
+    use crate::database::*;
 
     #[test]
     fn insert_and_get_book_correct() {
@@ -204,6 +83,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn get_books_with_details_by_owner_correct() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = insert_book_default(&db)
+            .and_then(|(book_id, book)| {
+                db.insert(&Member::new(None, _s("someone-else")))
+                    .and_then(|other_id| Ok((book_id, book, other_id)))
+            })
+            .and_then(|(book_id, book, other_id)| {
+                db.get_books_with_details_by_owner(&EntityType::Member, book.owner)
+                    .and_then(|owned| Ok((book_id, owned, other_id)))
+            })
+            .and_then(|(book_id, owned, other_id)| {
+                db.get_books_with_details_by_owner(&EntityType::Member, other_id)
+                    .and_then(|not_owned| {
+                        Ok(owned.len() == 1
+                            && owned[0].0.id == Some(book_id)
+                            && owned[0].2
+                            && not_owned.is_empty())
+                    })
+            });
+        teardown(settings);
+        match result {
+            Ok(true) => (),
+            Ok(false) => panic!("Expected only the books of the owner"),
+            _ => {
+                result.unwrap();
+                ()
+            }
+        }
+    }
+
     #[test]
     fn insert_book_quality_too_long() {
         let settings = setup();
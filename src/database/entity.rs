@@ -1,6 +1,5 @@
 use super::*;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
-use mysql::params;
 
 /// Id type for Entity
 pub type EntityId = Id;
@@ -52,3 +51,10 @@ impl<'de> Deserialize<'de> for EntityType {
         return EntityType::from_str(s.as_str()).map_err(de::Error::custom);
     }
 }
+
+/// Read from the generated `owner_type` and `rentee_type` columns
+impl FromValue for EntityType {
+    fn from_value(value: Value) -> Result<EntityType, String> {
+        EntityType::from_str(String::from_value(value)?.as_str())
+    }
+}
@@ -1,20 +1,23 @@
 use super::*;
 use serde::{Serialize, Deserialize};
-use mysql::params;
 
 /// Id type for guild
 pub type GuildId = EntityId;
 
 /// Any organisation involved in book renting
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, DMO)]
+#[dmo(table = "guilds", id = "guild_id")]
 pub struct Guild {
     /// Unique id
     pub id: Option<GuildId>,
     /// Name of Guild
+    #[dmo(varchar)]
     pub name: String,
     /// Address of Guild
+    #[dmo(varchar)]
     pub address: String,
     /// Id of Member to contact
+    #[dmo(column = "contact_by_member_id")]
     pub contact: MemberId,
 }
 
@@ -30,97 +33,10 @@ impl Guild {
     }
 }
 
-impl DMO for Guild {
-    type Id = GuildId;
-
-    fn insert(db: &Database, inp: &Guild) -> Result<GuildId, Error> {
-        check_varchar_length!(inp.name, inp.address);
-        Ok(db.pool.prep_exec("insert into guilds (name, address, contact_by_member_id) values (:name, :address, :contact)",
-        params!{
-            "name" => inp.name.clone(),
-            "address" => inp.address.clone(),
-            "contact" => inp.contact,
-        }).map(|result|result.last_insert_id()
-        )?)
-    }
-
-    fn get(db: &Database, guild_id: GuildId) -> Result<Option<Guild>, Error> {
-        let mut results = db.pool
-        .prep_exec(
-            "select guild_id, name, address, contact_by_member_id from guilds where guild_id=:guild_id;",
-            params!{
-                "guild_id" => guild_id,
-            },
-        )
-    .map(|result| {
-        result.map(|x| x.unwrap()).map(|row| {
-            let (id, name, address, contact) = mysql::from_row(row);
-            Guild {
-                id: id,
-                name: name,
-                address: address,
-                contact: contact
-            }
-        }).collect::<Vec<Guild>>()
-    })?;
-        return Ok(results.pop());
-    }
-
-    fn get_all(db: &Database) -> Result<Vec<Guild>, Error> {
-        Ok(db
-            .pool
-            .prep_exec(
-                "select guild_id, name, address, contact_by_member_id from guilds;",
-                (),
-            )
-            .map(|result| {
-                result
-                    .map(|x| x.unwrap())
-                    .map(|row| {
-                        let (id, name, address, contact) = mysql::from_row(row);
-                        Guild {
-                            id: id,
-                            name: name,
-                            address: address,
-                            contact: contact,
-                        }
-                    })
-                    .collect()
-            })?)
-    }
-
-    fn update(db: &Database, guild: &Guild) -> Result<(), Error> {
-        check_varchar_length!(guild.name, guild.address);
-        Ok(db.pool.prep_exec("update guilds set name=:name, address=:address, contact_by_member_id=:contact where guild_id=:id",
-        params!{
-            "name" => guild.name.clone(),
-            "address" => guild.address.clone(),
-            "contact" => guild.contact,
-            "id" => guild.id,
-        }).and(Ok(()))?)
-    }
-
-    fn delete(db: &Database, id: Id) -> Result<bool, Error> {
-        Ok(db
-            .pool
-            .prep_exec(
-                "delete from guilds where GuildId=:id",
-                params! {
-                    "id" => id,
-                },
-            )
-            .map_err(|err| Error::DatabaseError(err))
-            .and_then(|result| match result.affected_rows() {
-                1 => Ok(true),
-                0 => Ok(false),
-                _ => Err(Error::IllegalState),
-            })?)
-    }
-}
 #[cfg(test)]
 mod tests {
-    use database::test_util::*;
-    use database::*;
+    use crate::database::test_util::*;
+    use crate::database::*;
 
     #[test]
     fn insert_guild_correct() {
@@ -0,0 +1,174 @@
+use super::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Id type for GuildMembership
+pub type GuildMembershipId = Id;
+
+/// Role of a member within a single guild
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum GuildRole {
+    /// Belongs to the guild without further rights
+    Member,
+    /// Runs the inventory of the guild
+    Librarian,
+    /// Contact person, may additionally manage the guild and its members
+    Contact,
+}
+
+impl GuildRole {
+    /// Converts 'member', 'librarian' or 'contact' to a GuildRole
+    pub fn from_str(s: &str) -> Result<GuildRole, String> {
+        match s {
+            "member" => Ok(GuildRole::Member),
+            "librarian" => Ok(GuildRole::Librarian),
+            "contact" => Ok(GuildRole::Contact),
+            _ => Err(String::from("Expected 'member', 'librarian' or 'contact'")),
+        }
+    }
+
+    /// Converts a GuildRole to a corresponding string
+    pub fn to_string(&self) -> String {
+        match self {
+            GuildRole::Member => String::from("member"),
+            GuildRole::Librarian => String::from("librarian"),
+            GuildRole::Contact => String::from("contact"),
+        }
+    }
+}
+
+impl Serialize for GuildRole {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for GuildRole {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        return GuildRole::from_str(s.as_str()).map_err(de::Error::custom);
+    }
+}
+
+impl From<GuildRole> for Value {
+    fn from(role: GuildRole) -> Value {
+        Value::Text(role.to_string())
+    }
+}
+
+impl FromValue for GuildRole {
+    fn from_value(value: Value) -> Result<GuildRole, String> {
+        GuildRole::from_str(String::from_value(value)?.as_str())
+    }
+}
+
+/// Membership of a member in a guild
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, DMO)]
+#[dmo(table = "guild_memberships", id = "membership_id")]
+pub struct GuildMembership {
+    /// Id
+    pub id: Option<GuildMembershipId>,
+    /// Guild
+    #[dmo(column = "guild_by_id")]
+    pub guild: GuildId,
+    /// Member belonging to the guild
+    #[dmo(column = "member_by_id")]
+    pub member: MemberId,
+    /// Role of the member within the guild
+    pub role: GuildRole,
+}
+
+impl GuildMembership {
+    /// Construct a new GuildMembership object with given parameters
+    pub fn new(
+        id: Option<GuildMembershipId>,
+        guild: GuildId,
+        member: MemberId,
+        role: GuildRole,
+    ) -> GuildMembership {
+        GuildMembership {
+            id: id,
+            guild: guild,
+            member: member,
+            role: role,
+        }
+    }
+
+    pub fn from_db(
+        id: Option<GuildMembershipId>,
+        guild: GuildId,
+        member: MemberId,
+        role: String,
+    ) -> GuildMembership {
+        GuildMembership::new(
+            id,
+            guild,
+            member,
+            GuildRole::from_str(role.as_str()).expect("Bad guild role"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::test_util::*;
+    use crate::database::*;
+
+    #[test]
+    fn insert_guild_membership_correct() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = db
+            .insert(&Member::new(None, _s("external_id")))
+            .and_then(|member_id| {
+                db.insert(&Guild::new(None, _s("Librarium Aachen"), _s("Aachen"), member_id))
+                    .and_then(|guild_id| Ok((guild_id, member_id)))
+            })
+            .and_then(|(guild_id, member_id)| {
+                let membership =
+                    GuildMembership::new(None, guild_id, member_id, GuildRole::Librarian);
+                db.insert(&membership).and_then(|id| Ok((id, membership)))
+            })
+            .and_then(|(id, membership)| {
+                db.get_guild_membership(membership.guild, membership.member)
+                    .and_then(|rec_membership| {
+                        Ok(rec_membership.map_or(false, |fetched_membership| {
+                            GuildMembership {
+                                id: Some(id),
+                                ..membership
+                            } == fetched_membership
+                        }))
+                    })
+            });
+        teardown(settings);
+        match result {
+            Ok(true) => (),
+            Ok(false) => panic!("Inserted guild membership is not in DB :("),
+            _ => {
+                result.unwrap();
+                ()
+            }
+        }
+    }
+
+    #[test]
+    fn insert_guild_membership_invalid_guild() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = db
+            .insert(&Member::new(None, _s("external_id")))
+            .and_then(|member_id| {
+                db.insert(&GuildMembership::new(None, 12345, member_id, GuildRole::Member))
+            });
+        teardown(settings);
+        match result {
+            Err(Error::ConstraintError(_)) => (),
+            _ => panic!("Expected DatabaseError::FieldError(FieldError::ConstraintError)"),
+        }
+    }
+}
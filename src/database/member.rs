@@ -1,6 +1,5 @@
 use super::*;
 use serde::Serialize;
-use mysql::params;
 
 /// Id type for Member
 pub type MemberId = EntityId;
@@ -8,11 +7,13 @@ pub type MemberId = EntityId;
 pub type ExternalId = String;
 
 /// Identification information for a person
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, DMO)]
+#[dmo(table = "members", id = "member_id")]
 pub struct Member {
     /// Id
     pub id: Option<MemberId>,
     /// External id for identification with KeyCloak
+    #[dmo(varchar)]
     pub external_id: ExternalId,
 }
 
@@ -26,98 +27,10 @@ impl Member {
     }
 }
 
-impl DMO for Member {
-    type Id = MemberId;
-    fn insert(db: &Database, inp: &Member) -> Result<MemberId, Error> {
-        check_varchar_length!(inp.external_id);
-        Ok(db
-            .pool
-            .prep_exec(
-                "insert into members (external_id) values (:external_id)",
-                params! {
-                    "external_id" => inp.external_id.clone(),
-                },
-            )
-            .map(|result| result.last_insert_id())?)
-    }
-
-    fn get(db: &Database, member_id: MemberId) -> Result<Option<Member>, Error> {
-        let mut results = db
-            .pool
-            .prep_exec(
-                "select member_id, external_id from members where member_id=:member_id;",
-                params! {
-                    "member_id" => member_id,
-                },
-            )
-            .map(|result| {
-                result
-                    .map(|x| x.unwrap())
-                    .map(|row| {
-                        let (id, external_id) = mysql::from_row(row);
-                        Member {
-                            id: id,
-                            external_id: external_id,
-                        }
-                    })
-                    .collect::<Vec<Member>>()
-            })?;
-        return Ok(results.pop());
-    }
-
-    fn get_all(db: &Database) -> Result<Vec<Member>, Error> {
-        Ok(db
-            .pool
-            .prep_exec("select member_id, external_id from members;", ())
-            .map(|result| {
-                result
-                    .map(|x| x.unwrap())
-                    .map(|row| {
-                        let (id, external_id) = mysql::from_row(row);
-                        Member {
-                            id: id,
-                            external_id: external_id,
-                        }
-                    })
-                    .collect()
-            })?)
-    }
-
-    fn update(db: &Database, member: &Member) -> Result<(), Error> {
-        check_varchar_length!(member.external_id);
-        Ok(db
-            .pool
-            .prep_exec(
-                "update members set external_id=:external_id where member_id=:id",
-                params! {
-                    "external_id" => member.external_id.clone(),
-                    "id" => member.id,
-                },
-            )
-            .and(Ok(()))?)
-    }
-
-    fn delete(db: &Database, id: Id) -> Result<bool, Error> {
-        Ok(db
-            .pool
-            .prep_exec(
-                "delete from members where member_id=:id",
-                params! {
-                    "id" => id,
-                },
-            )
-            .map_err(|err| Error::DatabaseError(err))
-            .and_then(|result| match result.affected_rows() {
-                1 => Ok(true),
-                0 => Ok(false),
-                _ => Err(Error::IllegalState),
-            })?)
-    }
-}
 #[cfg(test)]
 mod tests {
-    use database::test_util::*;
-    use database::*;
+    use crate::database::test_util::*;
+    use crate::database::*;
     #[test]
     fn insert_member_correct() {
         let settings = setup();
@@ -139,6 +52,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn provision_member_creates_once() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = db.provision_member(&_s("someexternalId")).and_then(|first_id| {
+            db.provision_member(&_s("someexternalId")).and_then(|second_id| {
+                db.get_all::<Member>()
+                    .and_then(|members| Ok(first_id == second_id && members.len() == 1))
+            })
+        });
+
+        teardown(settings);
+        match result {
+            Ok(true) => (),
+            Ok(false) => panic!("Expected member to be provisioned exactly once"),
+            _ => {
+                result.unwrap();
+                ()
+            }
+        }
+    }
+
     #[test]
     fn insert_member_external_id_too_long() {
         let settings = setup();
@@ -0,0 +1,310 @@
+use super::repository::*;
+use super::*;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+/// Repository keeping all entities in memory, to test the business logic without a database.
+/// Constraints of the database schema are not checked.
+pub struct MemoryRepository {
+    /// Table of each entity type, a `BTreeMap<Id, T>` keyed by the `TypeId` of `T`
+    tables: RefCell<HashMap<TypeId, Box<dyn Any>>>,
+}
+
+impl MemoryRepository {
+    pub fn new() -> MemoryRepository {
+        MemoryRepository {
+            tables: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Runs `f` on the table of the given entity type
+    fn with_table<T: Record, R>(&self, f: impl FnOnce(&mut BTreeMap<Id, T>) -> R) -> R {
+        let mut tables = self.tables.borrow_mut();
+        let table = tables
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(BTreeMap::<Id, T>::new()))
+            .downcast_mut::<BTreeMap<Id, T>>()
+            .expect("table has the type of its key");
+        f(table)
+    }
+
+    fn all<T: Record>(&self) -> Vec<T> {
+        self.with_table(|table: &mut BTreeMap<Id, T>| table.values().cloned().collect())
+    }
+
+    fn find<T: Record>(&self, predicate: impl Fn(&T) -> bool) -> Vec<T> {
+        self.all::<T>().into_iter().filter(|x| predicate(x)).collect()
+    }
+
+    /// Number of copies of a title and how many of them are not rented
+    fn stock(&self, title_id: TitleId) -> (u32, u32) {
+        let books = self.find(|book: &Book| book.title == title_id);
+        let available = books
+            .iter()
+            .filter(|book| self.active_rental(book.id.expect("stored book has an id")).is_none())
+            .count();
+        (books.len() as u32, available as u32)
+    }
+
+    fn active_rental(&self, book_id: BookId) -> Option<Rental> {
+        self.find(|rental: &Rental| rental.book == book_id && rental.is_active())
+            .pop()
+    }
+
+    fn with_details(&self, books: Vec<Book>) -> Vec<(Book, Option<Rental>, bool)> {
+        books
+            .into_iter()
+            .map(|book| {
+                let rental = self.active_rental(book.id.expect("stored book has an id"));
+                let available = rental.is_none();
+                (book, rental, available)
+            })
+            .collect()
+    }
+
+    fn title_with_details(&self, title: Title) -> Option<(Title, RpgSystem, u32, u32)> {
+        let system = self.get::<RpgSystem>(title.system).ok()??;
+        let (stock, available) = self.stock(title.id.expect("stored title has an id"));
+        Some((title, system, stock, available))
+    }
+
+    /// Reservations in queue order
+    fn reservations(&self, predicate: impl Fn(&Reservation) -> bool) -> Vec<Reservation> {
+        let mut reservations = self.find(predicate);
+        reservations.sort_by_key(|reservation| (reservation.date, reservation.id));
+        reservations
+    }
+}
+
+impl EntityRepository for MemoryRepository {
+    fn get_all<T: Record>(&self) -> Result<Vec<T>, Error> {
+        Ok(self.all())
+    }
+
+    fn get<T: Record>(&self, id: Id) -> Result<Option<T>, Error> {
+        Ok(self.with_table(|table: &mut BTreeMap<Id, T>| table.get(&id).cloned()))
+    }
+
+    fn insert<T: Record>(&self, inp: &T) -> Result<Id, Error> {
+        Ok(self.with_table(|table: &mut BTreeMap<Id, T>| {
+            let id = table.keys().next_back().map_or(1, |last| last + 1);
+            let mut record = inp.clone();
+            record.set_id(id);
+            table.insert(id, record);
+            id
+        }))
+    }
+
+    fn update<T: Record>(&self, up: &T) -> Result<(), Error> {
+        self.with_table(|table: &mut BTreeMap<Id, T>| {
+            if let Some(id) = up.id() {
+                if let Some(record) = table.get_mut(&id) {
+                    *record = up.clone();
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn delete<T: Record>(&self, id: Id) -> Result<bool, Error> {
+        Ok(self.with_table(|table: &mut BTreeMap<Id, T>| table.remove(&id).is_some()))
+    }
+}
+
+impl CatalogRepository for MemoryRepository {
+    fn get_titles_by_rpg_system(
+        &self,
+        system_id: RpgSystemId,
+    ) -> Result<Vec<(Title, u32, u32)>, Error> {
+        Ok(self
+            .find(|title: &Title| title.system == system_id)
+            .into_iter()
+            .map(|title| {
+                let (stock, available) = self.stock(title.id.expect("stored title has an id"));
+                (title, stock, available)
+            })
+            .collect())
+    }
+
+    fn get_titles_with_details(&self) -> Result<Vec<(Title, RpgSystem, u32, u32)>, Error> {
+        Ok(self
+            .all::<Title>()
+            .into_iter()
+            .filter_map(|title| self.title_with_details(title))
+            .collect())
+    }
+
+    fn get_title_with_details(
+        &self,
+        title_id: TitleId,
+    ) -> Result<Option<(Title, RpgSystem, u32, u32)>, Error> {
+        Ok(self
+            .get::<Title>(title_id)?
+            .and_then(|title| self.title_with_details(title)))
+    }
+
+    fn get_books_with_details(&self) -> Result<Vec<(Book, Option<Rental>, bool)>, Error> {
+        Ok(self.with_details(self.all()))
+    }
+
+    fn get_book_with_details(
+        &self,
+        book_id: BookId,
+    ) -> Result<Option<(Book, Option<Rental>, bool)>, Error> {
+        Ok(self
+            .with_details(self.get::<Book>(book_id)?.into_iter().collect())
+            .pop())
+    }
+
+    fn get_books_with_details_by_owner(
+        &self,
+        owner_type: &EntityType,
+        owner: EntityId,
+    ) -> Result<Vec<(Book, Option<Rental>, bool)>, Error> {
+        Ok(self.with_details(
+            self.find(|book: &Book| &book.owner_type == owner_type && book.owner == owner),
+        ))
+    }
+
+    fn get_books_with_details_by_rentee(
+        &self,
+        rentee_type: &EntityType,
+        rentee: EntityId,
+    ) -> Result<Vec<(Book, Option<Rental>, bool)>, Error> {
+        Ok(self
+            .get_books_with_details()?
+            .into_iter()
+            .filter(|(_, rental, _)| match rental {
+                Some(rental) => &rental.rentee_type == rentee_type && rental.rentee == rentee,
+                None => false,
+            })
+            .collect())
+    }
+
+    fn get_next_external_inventory_id(&self) -> Result<ExternalInventoryId, Error> {
+        Ok(self
+            .all::<Book>()
+            .iter()
+            .map(|book| book.external_inventory_id)
+            .max()
+            .unwrap_or(0)
+            + 1)
+    }
+}
+
+impl LendingRepository for MemoryRepository {
+    /// Transactions run on the repository itself, there is nobody to wait for
+    fn lock_book(&self, _book_id: BookId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn lock_title(&self, _title_id: TitleId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_active_rental_for_book(&self, book_id: BookId) -> Result<Option<Rental>, Error> {
+        Ok(self.active_rental(book_id))
+    }
+
+    fn get_overdue_rentals(&self, day: Date) -> Result<Vec<Rental>, Error> {
+        let mut rentals = self.find(|rental: &Rental| rental.is_active() && rental.to < day);
+        rentals.sort_by_key(|rental| rental.to);
+        Ok(rentals)
+    }
+
+    fn get_reservations_by_title(
+        &self,
+        title_id: TitleId,
+        day: Date,
+    ) -> Result<Vec<Reservation>, Error> {
+        Ok(self.reservations(|reservation| {
+            reservation.title == title_id
+                && (reservation.book.is_none() || reservation.held_until >= Some(day))
+        }))
+    }
+
+    fn get_next_reservation_for_title(
+        &self,
+        title_id: TitleId,
+    ) -> Result<Option<Reservation>, Error> {
+        Ok(self
+            .reservations(|reservation| {
+                reservation.title == title_id && reservation.book.is_none()
+            })
+            .into_iter()
+            .next())
+    }
+
+    fn get_expired_holds_for_title(
+        &self,
+        title_id: TitleId,
+        day: Date,
+    ) -> Result<Vec<Reservation>, Error> {
+        Ok(self.reservations(|reservation| {
+            reservation.title == title_id
+                && reservation.book.is_some()
+                && reservation.held_until.map_or(false, |held_until| held_until < day)
+        }))
+    }
+
+    fn get_reservation_holding_book(
+        &self,
+        book_id: BookId,
+        day: Date,
+    ) -> Result<Option<Reservation>, Error> {
+        Ok(self
+            .find(|reservation: &Reservation| {
+                reservation.book == Some(book_id) && reservation.held_until >= Some(day)
+            })
+            .pop())
+    }
+}
+
+impl MemberRepository for MemoryRepository {
+    fn get_member_by_external_id(
+        &self,
+        external_id: &ExternalId,
+    ) -> Result<Option<Member>, Error> {
+        Ok(self
+            .find(|member: &Member| &member.external_id == external_id)
+            .pop())
+    }
+
+    fn get_api_token_by_hash(&self, hash: &str) -> Result<Option<ApiToken>, Error> {
+        Ok(self.find(|token: &ApiToken| token.hash == hash).pop())
+    }
+
+    fn get_api_tokens_by_member(&self, member_id: MemberId) -> Result<Vec<ApiToken>, Error> {
+        Ok(self.find(|token: &ApiToken| token.member == member_id))
+    }
+
+    fn get_guild_memberships_by_guild(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Vec<GuildMembership>, Error> {
+        Ok(self.find(|membership: &GuildMembership| membership.guild == guild_id))
+    }
+
+    fn get_guild_membership(
+        &self,
+        guild_id: GuildId,
+        member_id: MemberId,
+    ) -> Result<Option<GuildMembership>, Error> {
+        Ok(self
+            .find(|membership: &GuildMembership| {
+                membership.guild == guild_id && membership.member == member_id
+            })
+            .pop())
+    }
+}
+
+impl TransactionRepository for MemoryRepository {
+    /// Runs `f` right away, writes of a failing `f` are not rolled back
+    fn transaction<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&MemoryRepository) -> Result<T, Error>,
+    {
+        f(self)
+    }
+}
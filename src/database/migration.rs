@@ -0,0 +1,221 @@
+use super::*;
+
+/// A change of the database schema, applied once and in order of its version
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    /// Statements for MySQL and MariaDB
+    pub mysql: &'static str,
+    /// The same change for SQLite
+    pub sqlite: &'static str,
+}
+
+impl Migration {
+    /// Statements of this migration in the given dialect
+    pub fn sql(&self, dialect: Dialect) -> &'static str {
+        match dialect {
+            Dialect::MySql => self.mysql,
+            Dialect::Sqlite => self.sqlite,
+        }
+    }
+}
+
+/// All migrations known to this binary, ordered by version without gaps.
+/// Applied migrations must never be changed, add a new one instead.
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial schema",
+        mysql: include_str!("../../res/migrations/mysql/0001-initial-schema.sql"),
+        sqlite: include_str!("../../res/migrations/sqlite/0001-initial-schema.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "rental returns",
+        mysql: include_str!("../../res/migrations/mysql/0002-rental-returns.sql"),
+        sqlite: include_str!("../../res/migrations/sqlite/0002-rental-returns.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "reservations",
+        mysql: include_str!("../../res/migrations/mysql/0003-reservations.sql"),
+        sqlite: include_str!("../../res/migrations/sqlite/0003-reservations.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "api tokens",
+        mysql: include_str!("../../res/migrations/mysql/0004-api-tokens.sql"),
+        sqlite: include_str!("../../res/migrations/sqlite/0004-api-tokens.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "guild memberships",
+        mysql: include_str!("../../res/migrations/mysql/0005-guild-memberships.sql"),
+        sqlite: include_str!("../../res/migrations/sqlite/0005-guild-memberships.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "api token expiry",
+        mysql: include_str!("../../res/migrations/mysql/0006-api-token-expiry.sql"),
+        sqlite: include_str!("../../res/migrations/sqlite/0006-api-token-expiry.sql"),
+    },
+];
+
+/// Version of the newest migration
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+static CREATE_SCHEMA_MIGRATIONS: &str = "create table if not exists schema_migrations (
+  version       int primary key,
+  name          varchar(255) not null,
+  applied_at    datetime not null
+);";
+
+/// Lock keeping concurrently starting instances from migrating at the same time
+static MIGRATION_LOCK: &str = "liberation_schema_migrations";
+
+impl Database {
+    /// Version of the newest migration applied to the database, 0 for an empty database
+    pub fn schema_version(&self) -> Result<u32, Error> {
+        self.backend.execute_batch(CREATE_SCHEMA_MIGRATIONS)?;
+        let versions = self
+            .backend
+            .query("select max(version) from schema_migrations;", params!{})?
+            .into_iter()
+            .map(|row| from_row::<Option<u32>>(row))
+            .collect::<Vec<Option<u32>>>();
+        Ok(versions.into_iter().next().and_then(|version| version).unwrap_or(0))
+    }
+
+    /// Applies all migrations newer than the schema version of the database.
+    /// Returns the versions applied.
+    pub fn migrate(&self) -> Result<Vec<u32>, Error> {
+        let dialect = self.backend.dialect();
+        let mut applied = vec![];
+        self.backend.exclusive(MIGRATION_LOCK, &mut || {
+            let current = self.schema_version()?;
+            check_not_ahead(current)?;
+            for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+                info!("applying migration {}: {} ...", migration.version, migration.name);
+                // Schema changes of MySQL are committed implicitly, so each migration is recorded right after it
+                self.backend.execute_batch(migration.sql(dialect))?;
+                self.backend.execute(
+                    "insert into schema_migrations (version, name, applied_at) values (:version, :name, :applied_at);",
+                    params! {
+                        "version" => migration.version,
+                        "name" => migration.name,
+                        "applied_at" => Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                    },
+                )?;
+                applied.push(migration.version);
+            }
+            Ok(())
+        })?;
+        Ok(applied)
+    }
+
+    /// Fails unless the database has exactly the schema of this binary
+    pub fn check_schema(&self) -> Result<(), Error> {
+        let current = self.schema_version()?;
+        check_not_ahead(current)?;
+        match current < latest_version() {
+            true => Err(Error::SchemaOutdated(current, latest_version())),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Refuses databases migrated by a newer binary
+fn check_not_ahead(current: u32) -> Result<(), Error> {
+    match current > latest_version() {
+        true => Err(Error::SchemaTooNew(current, latest_version())),
+        false => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::migration::*;
+    use crate::database::test_util::*;
+
+    #[test]
+    fn migrations_ordered_without_gaps() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as u32 + 1);
+        }
+    }
+
+    #[test]
+    fn migrate_applies_each_migration_once() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = db
+            .schema_version()
+            .and_then(|version| db.migrate().and_then(|applied| Ok((version, applied))));
+        teardown(settings);
+        assert_eq!(result.unwrap(), (latest_version(), vec![]));
+    }
+
+    /// A rental in the initial schema, ids are given so the statements work in both dialects
+    static INITIAL_RENTAL: &str = "
+        insert into members (member_id, external_id) values (1, 'rincewind');
+        insert into rpg_systems (rpg_system_id, name, shortname) values (1, 'Discworld', null);
+        insert into titles (title_id, name, rpg_system_by_id, language, publisher, year, coverimage)
+            values (1, 'The Colour of Magic', 1, 'en', 'Corgi', 1983, null);
+        insert into books (book_id, title_by_id, owner_member_by_id, quality, external_inventory_id)
+            values (1, 1, 1, 'used', 1);
+        insert into rentals (rental_id, from_date, to_date, book_by_id, rentee_member_by_id)
+            values (1, '2019-03-01', '2019-03-29', 1, 1);";
+
+    #[test]
+    fn migrate_keeps_rentals_of_initial_schema() {
+        let settings = setup();
+        let db = Database::connect(&settings).unwrap();
+        let initial = &MIGRATIONS[0];
+        let result = db
+            .schema_version()
+            .and_then(|_| db.backend.execute_batch(initial.sql(db.backend.dialect())))
+            .and_then(|_| db.backend.execute_batch(INITIAL_RENTAL))
+            .and_then(|_| {
+                db.backend.execute(
+                    "insert into schema_migrations (version, name, applied_at) values (:version, :name, '2019-03-01 00:00:00');",
+                    params! {
+                        "version" => initial.version,
+                        "name" => initial.name,
+                    },
+                )
+            })
+            .and_then(|_| db.migrate())
+            .and_then(|applied| db.get::<Rental>(1).map(|rental| (applied, rental)));
+        teardown(settings);
+
+        let (applied, rental) = result.unwrap();
+        assert_eq!(applied, (2..=latest_version()).collect::<Vec<u32>>());
+        let rental = rental.expect("Rental of the initial schema is gone :(");
+        assert_eq!(
+            (rental.from, rental.to, rental.returned, rental.extensions),
+            (_d(2019, 3, 1), _d(2019, 3, 29), None, 0)
+        );
+    }
+
+    #[test]
+    fn refuse_database_ahead_of_binary() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = db
+            .backend
+            .execute(
+                "insert into schema_migrations (version, name, applied_at) values (:version, 'from the future', '2999-01-01 00:00:00');",
+                params! {
+                    "version" => latest_version() + 1,
+                },
+            )
+            .and_then(|_| Database::from_settings(&settings));
+        teardown(settings);
+        match result {
+            Err(Error::SchemaTooNew(_, _)) => (),
+            _ => panic!("Expected Error::SchemaTooNew"),
+        }
+    }
+}
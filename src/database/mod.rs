@@ -1,9 +1,8 @@
 pub use crate::error::Error;
 use crate::settings;
 use chrono::prelude::*;
-use mysql::params;
 use serde::{Serialize};
-pub static INIT_DB_STRUCTURE: &str = include_str!("../../res/init-db-structure.sql");
+use std::sync::Arc;
 
 /// Checks string and returns error if string is too long
 macro_rules! check_varchar_length {
@@ -23,32 +22,59 @@ macro_rules! check_date {
     }
 }
 
+/// Named parameters of a query, e.g. `params!{"id" => id}`
+macro_rules! params {
+    ($( $name:expr => $value:expr ),* $(,)*) => {{
+        let params: Params = vec![$( ($name, Value::from($value)) ),*];
+        params
+    }}
+}
+
+mod api_token;
+pub mod backend;
 mod book;
 mod entity;
 mod guild;
+mod guild_membership;
 mod member;
+#[cfg(test)]
+pub mod memory;
+pub mod migration;
 mod rental;
+pub mod repository;
+mod reservation;
 mod rpgsystem;
 mod title;
 
+pub use self::api_token::ApiToken;
 pub use self::book::Book;
 pub use self::entity::EntityType;
 pub use self::guild::Guild;
+pub use self::guild_membership::{GuildMembership, GuildRole};
 pub use self::member::Member;
+pub use self::repository::{
+    CatalogRepository, EntityRepository, LendingRepository, MemberRepository, Record, Repository,
+    TransactionRepository,
+};
 pub use self::rental::Rental;
+pub use self::reservation::Reservation;
 pub use self::rpgsystem::RpgSystem;
 pub use self::title::Title;
 
+pub use self::api_token::ApiTokenId;
 pub use self::book::{BookId, ExternalInventoryId};
 pub use self::entity::EntityId;
 pub use self::guild::GuildId;
+pub use self::guild_membership::GuildMembershipId;
 pub use self::member::ExternalId;
 pub use self::member::MemberId;
 pub use self::rental::RentalId;
+pub use self::reservation::ReservationId;
 pub use self::rpgsystem::RpgSystemId;
 pub use self::title::TitleId;
 
-use mysql;
+use self::backend::{from_row, Backend, Dialect, FromValue, Params, Value};
+use liberation_derive::DMO;
 
 /// Type for ids
 pub type Id = u64;
@@ -57,12 +83,15 @@ pub type Year = i16;
 pub type Date = NaiveDate;
 
 pub mod type_aliases {
+    pub use super::ApiTokenId;
     pub use super::BookId;
     pub use super::EntityId;
     pub use super::ExternalId;
     pub use super::GuildId;
+    pub use super::GuildMembershipId;
     pub use super::MemberId;
     pub use super::RentalId;
+    pub use super::ReservationId;
     pub use super::RpgSystemId;
     pub use super::TitleId;
 
@@ -75,35 +104,31 @@ pub mod type_aliases {
 /// Underlaying database
 #[derive(Clone)]
 pub struct Database {
-    /// MYSQL pool
-    pool: mysql::Pool,
+    /// MySQL server or SQLite file, selected in the settings
+    backend: Arc<dyn Backend>,
+    /// Whether all statements run in a transaction, see `transaction`
+    in_transaction: bool,
 }
 
 //static SQL_DATEFORMAT: &str = "%Y-%m-%d";
 
 impl Database {
-    /// Construct a new Database object from given settings
+    /// Construct a new Database object from given settings.
+    /// Migrates the schema if `automigrate` is set, otherwise fails unless it is up to date.
     pub fn from_settings(settings: &settings::Database) -> Result<Database, Error> {
-        let mut opts = mysql::OptsBuilder::default();
-        opts.ip_or_hostname(settings.hostname.clone())
-            .user(settings.username.clone())
-            .pass(settings.password.clone())
-            .db_name(Some(settings.database.clone()))
-            .prefer_socket(false);
-
-        match settings.port {
-            Some(port) => {
-                opts.tcp_port(port);
-            }
-            None => {}
+        let db = Database::connect(settings)?;
+        match settings.automigrate {
+            true => db.migrate().and(Ok(db)),
+            false => db.check_schema().and(Ok(db)),
         }
+    }
 
-        let pool = mysql::Pool::new(opts)?;
-
-        let mut conn = pool.get_conn()?;
-        conn.query(INIT_DB_STRUCTURE)?;
-
-        return Ok(Database { pool: pool });
+    /// Connect to the database without touching its schema
+    pub fn connect(settings: &settings::Database) -> Result<Database, Error> {
+        Ok(Database {
+            backend: backend::connect(settings)?,
+            in_transaction: false,
+        })
     }
 
     /// Gets all objects of self type from the underlaying database
@@ -131,15 +156,42 @@ impl Database {
         T::delete(self, id)
     }
 
+    /// Runs `f` in a transaction, which is committed if `f` succeeds and rolled back if it fails.
+    /// `f` gets a Database bound to the transaction to run DMOs and queries on,
+    /// statements on `self` are not part of it.
+    /// Within a transaction `f` joins the running one, so an error only rolls back
+    /// if it is passed on to the outermost transaction.
+    pub fn transaction<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Database) -> Result<T, Error>,
+    {
+        if self.in_transaction {
+            return f(self);
+        }
+
+        let tx = Database {
+            backend: Arc::from(self.backend.begin()?),
+            in_transaction: true,
+        };
+        match f(&tx) {
+            Ok(result) => tx.backend.execute_batch("commit;").and(Ok(result)),
+            Err(err) => {
+                tx.backend.execute_batch("rollback;")?;
+                Err(err)
+            }
+        }
+    }
+
     pub fn get_titles_by_rpg_system(
         &self,
         system_id: RpgSystemId,
     ) -> Result<Vec<(Title, u32, u32)>, Error> {
-        let results = self.pool
-        .prep_exec(
-            "select title_id, name, rpg_system_by_id, language, publisher, year, coverimage, count(b.book_id) as stock, ifnull(sum(b.available),0)
+        Ok(self
+        .backend
+        .query(
+            "select title_id, name, rpg_system_by_id, language, publisher, year, coverimage, count(b.book_id) as stock, coalesce(sum(b.available),0)
                 from titles left join (
-                    select *, if(exists(select rentals.rental_id from rentals where rentals.book_by_id = books.book_id and rentals.to_date >= now()), 0, 1 ) as available
+                    select *, case when exists(select rentals.rental_id from rentals where rentals.book_by_id = books.book_id and rentals.return_date is null) then 0 else 1 end as available
                     from books
                     ) b on titles.title_id = b.title_by_id
                 where titles.rpg_system_by_id = :system_id
@@ -147,44 +199,81 @@ impl Database {
             params!{
                 "system_id" => system_id,
             },
-        )
-        .map_err(|err| Error::DatabaseError(err))
-        .map(|result| {
-            result.map(|x| x.unwrap()).map(|row| {
-                let (id, name, system, language, publisher, year, coverimage, stock, available) = mysql::from_row(row);
+        )?
+        .into_iter()
+        .map(|row| {
+            let (id, name, system, language, publisher, year, coverimage, stock, available) = from_row(row);
+            (
+                Title {
+                    id: id,
+                    name: name,
+                    system: system,
+                    language: language,
+                    publisher: publisher,
+                    year: year,
+                    coverimage: coverimage,
+                },
+                stock,
+                available
+            )
+        }).collect::<Vec<(Title, u32, u32)>>())
+    }
+
+    /// Gets Titles with additional information about availability and rentals of corresponding books
+    pub fn get_titles_with_details(&self) -> Result<Vec<(Title, RpgSystem, u32, u32)>, Error> {
+        Ok(self
+            .backend
+            .query(
+                "select title_id, titles.name, language, publisher, year, coverimage, rpg_systems.rpg_system_id, rpg_systems.name, rpg_systems.shortname, count(book_id) as stock, coalesce(sum(books.book_id is not null and not exists(select rentals.rental_id from rentals where rentals.book_by_id = books.book_id and rentals.return_date is null)),0) as available \
+                 from titles join rpg_systems on titles.rpg_system_by_id = rpg_systems.rpg_system_id \
+                    left outer join books on titles.title_id = books.title_by_id \
+                    group by title_id;
+                    ", params!{}
+            )?
+            .into_iter()
+            .map(|row| {
+                let (id, name, language, publisher, year, coverimage, system_id, system_name, system_short, stock, available): (Option<TitleId>, String, String, String, i16, Option<String>, RpgSystemId, String, Option<String>, u32, u32)  = from_row(row);
                 (
                     Title {
                         id: id,
                         name: name,
-                        system: system,
+                        system: system_id,
                         language: language,
                         publisher: publisher,
                         year: year,
                         coverimage: coverimage,
                     },
+                    RpgSystem {
+                        id: Some(system_id),
+                        name: system_name,
+                        shortname: system_short,
+                    },
                     stock,
                     available
                 )
-            }).collect::<Vec<(Title, u32, u32)>>()
-        });
-        return results;
+            }).collect::<Vec<(Title, RpgSystem, u32, u32)>>())
     }
 
-    /// Gets Titles with additional information about availability and rentals of corresponding books
-    pub fn get_titles_with_details(&self) -> Result<Vec<(Title, RpgSystem, u32, u32)>, Error> {
-        let result = self.pool
-            .prep_exec(
-                "select title_id, titles.name, language, publisher, year, coverimage, rpg_systems.rpg_system_id, rpg_systems.name, rpg_systems.shortname, count(book_id) as stock, exists(select rentals.rental_id from rentals where rentals.book_by_id = books.book_id and rentals.to_date >= now()) available \
+    /// Gets a specific Title with additional information about availability and rentals of corresponding books
+    pub fn get_title_with_details(
+        &self,
+        title_id: TitleId,
+    ) -> Result<Option<(Title, RpgSystem, u32, u32)>, Error> {
+        let mut result = self.backend
+            .query(
+                "select title_id, titles.name, language, publisher, year, coverimage, rpg_systems.rpg_system_id, rpg_systems.name, rpg_systems.shortname, count(book_id) as stock, coalesce(sum(books.book_id is not null and not exists(select rentals.rental_id from rentals where rentals.book_by_id = books.book_id and rentals.return_date is null)),0) as available \
                  from titles join rpg_systems on titles.rpg_system_by_id = rpg_systems.rpg_system_id \
                     left outer join books on titles.title_id = books.title_by_id \
+                    where title_id=:titleid \
                     group by title_id;
-                    ", ()
-            )
-            .map_err(|err| Error::DatabaseError(err))
-            .map(|result| {
-                result.map(|x| x.unwrap()).map(|row| {
-                    let (id, name, language, publisher, year, coverimage, system_id, system_name, system_short, stock, available): (Option<TitleId>, String, String, String, i16, Option<String>, RpgSystemId, String, Option<String>, u32, u32)  = mysql::from_row(row);
-                    (
+                    ",
+                params!{
+                    "titleid" => title_id,
+                })?
+            .into_iter()
+            .map(|row| {
+                let (id, name, language, publisher, year, coverimage, system_id, system_name, system_short, stock, available) : (Option<TitleId>, String, String, String, i16, Option<String>, RpgSystemId, String, Option<String>, u32, u32) = from_row(row);
+                (
                         Title {
                             id: id,
                             name: name,
@@ -197,56 +286,12 @@ impl Database {
                         RpgSystem {
                             id: Some(system_id),
                             name: system_name,
-                            shortname: system_short,
+                            shortname: system_short
                         },
                         stock,
                         available
-                    )
-                }).collect::<Vec<(Title, RpgSystem, u32, u32)>>()
-            });
-        return result;
-    }
-
-    /// Gets a specific Title with additional information about availability and rentals of corresponding books
-    pub fn get_title_with_details(
-        &self,
-        title_id: TitleId,
-    ) -> Result<Option<(Title, RpgSystem, u32, u32)>, Error> {
-        let mut result = self.pool
-            .prep_exec(
-                "select title_id, titles.name, language, publisher, year, coverimage, rpg_systems.rpg_system_id, rpg_systems.name, rpg_systems.shortname, count(book_id) as stock,     exists(select rentals.rental_id from rentals where rentals.book_by_id = books.book_id and rentals.to_date >= now()) available \
-                 from titles join rpg_systems on titles.rpg_system_by_id = rpg_systems.rpg_system_id \
-                    left outer join books on titles.title_id = books.title_by_id \
-                    where title_id=:titleid \
-                    group by title_id;
-                    ",
-                params!{
-                    "titleid" => title_id,
-                })
-            .map_err(|err| Error::DatabaseError(err))
-            .map(|result| {
-                result.map(|x| x.unwrap()).map(|row| {
-                    let (id, name, language, publisher, year, coverimage, system_id, system_name, system_short, stock, available) : (Option<TitleId>, String, String, String, i16, Option<String>, RpgSystemId, String, Option<String>, u32, u32) = mysql::from_row(row);
-                    (
-                            Title {
-                                id: id,
-                                name: name,
-                                system: system_id,
-                                language: language,
-                                publisher: publisher,
-                                year: year,
-                                coverimage: coverimage,
-                            },
-                            RpgSystem {
-                                id: Some(system_id),
-                                name: system_name,
-                                shortname: system_short
-                            },
-                            stock,
-                            available
-                    )
-                }).collect::<Vec<(Title, RpgSystem, u32, u32)>>()
-            })?;
+                )
+            }).collect::<Vec<(Title, RpgSystem, u32, u32)>>();
         return Ok(result.pop());
     }
 
@@ -256,51 +301,470 @@ impl Database {
         return Ok(vec![]);
     }
 
-    // one function to query them all, retrieve their data and store it in stucts
     /// Gets all Book objects with additional rental information
     pub fn get_books_with_details(&self) -> Result<Vec<(Book, Option<Rental>, bool)>, Error> {
-        return self.pool
-            .prep_exec(
-                "select
+        self.query_books_with_details("", params!{})
+    }
+
+    /// Gets a specific Book object with additional rental information
+    pub fn get_book_with_details(
+        &self,
+        book_id: BookId,
+    ) -> Result<Option<(Book, Option<Rental>, bool)>, Error> {
+        let mut results = self.query_books_with_details(
+            "where books.book_id = :book_id",
+            params!{
+                "book_id" => book_id,
+            },
+        )?;
+        return Ok(results.pop());
+    }
+
+    /// Gets all Book objects owned by the given entity with additional rental information
+    pub fn get_books_with_details_by_owner(
+        &self,
+        owner_type: &EntityType,
+        owner: EntityId,
+    ) -> Result<Vec<(Book, Option<Rental>, bool)>, Error> {
+        self.query_books_with_details(
+            "where books.owner_member_by_id = :owner_member or books.owner_guild_by_id = :owner_guild",
+            params!{
+                "owner_member" => match owner_type {
+                    EntityType::Member => Some(owner),
+                    EntityType::Guild => None,
+                },
+                "owner_guild" => match owner_type {
+                    EntityType::Member => None,
+                    EntityType::Guild => Some(owner),
+                },
+            },
+        )
+    }
+
+    /// Gets all Book objects currently rented by the given entity with additional rental information
+    pub fn get_books_with_details_by_rentee(
+        &self,
+        rentee_type: &EntityType,
+        rentee: EntityId,
+    ) -> Result<Vec<(Book, Option<Rental>, bool)>, Error> {
+        self.query_books_with_details(
+            "where rentals.rentee_member_by_id = :rentee_member or rentals.rentee_guild_by_id = :rentee_guild",
+            params!{
+                "rentee_member" => match rentee_type {
+                    EntityType::Member => Some(rentee),
+                    EntityType::Guild => None,
+                },
+                "rentee_guild" => match rentee_type {
+                    EntityType::Member => None,
+                    EntityType::Guild => Some(rentee),
+                },
+            },
+        )
+    }
+
+    /// Gets the highest external inventory id in use plus one
+    pub fn get_next_external_inventory_id(&self) -> Result<ExternalInventoryId, Error> {
+        let mut results = self
+            .backend
+            .query("select coalesce(max(external_inventory_id), 0) + 1 from books;", params!{})?
+            .into_iter()
+            .map(|row| {
+                let next: ExternalInventoryId = from_row(row);
+                next
+            }).collect::<Vec<ExternalInventoryId>>();
+        results.pop().ok_or(Error::IllegalState)
+    }
+
+    // one function to query them all, retrieve their data and store it in stucts
+    /// Gets Book objects matching the given condition with information about their active rental
+    fn query_books_with_details(
+        &self,
+        condition: &str,
+        params: Params,
+    ) -> Result<Vec<(Book, Option<Rental>, bool)>, Error> {
+        Ok(self
+            .backend
+            .query(
+                &format!("select
                     books.book_id, books.owner_type, books.quality, books.external_inventory_id, books.title_by_id, \
-                    if(books.owner_type = 'member', o_members.member_id, o_guilds.guild_id) as owner_id, \
+                    case when books.owner_type = 'member' then o_members.member_id else o_guilds.guild_id end as owner_id, \
                     rentals.rental_id, rentals.from_date, rentals.to_date, rentals.rentee_type, \
-                    if(rentals.rentee_type = 'member', r_members.member_id, r_guilds.guild_id) as rentee_id, \
-                    (rentals.to_date is null or rentals.to_date < CURRENT_DATE) as available \
+                    case when rentals.rentee_type = 'member' then r_members.member_id else r_guilds.guild_id end as rentee_id, \
+                    rentals.extensions \
                 from books \
                 left outer join members as o_members on books.owner_member_by_id = o_members.member_id and books.owner_type = 'member' \
                 left outer join guilds as o_guilds on books.owner_guild_by_id = o_guilds.guild_id and books.owner_type = 'guild' \
-                left outer join rentals on books.book_id = rentals.book_by_id and rentals.to_date >= ALL (select to_date from rentals where book_by_id = books.book_id) \
+                left outer join rentals on books.book_id = rentals.book_by_id and rentals.return_date is null \
                 left outer join members as r_members on rentals.rentee_member_by_id = r_members.member_id and rentals.rentee_type = 'member' \
                 left outer join guilds as r_guilds on rentals.rentee_guild_by_id = r_guilds.guild_id and rentals.rentee_type = 'guild' \
+                {} \
                 group by book_id;
-                ", ())
-            .map_err(|err| Error::DatabaseError(err))
-            .map(|result| {
-                result.map(|x| x.unwrap()).map(|row| {
-                    let (book_id, owner_type, quality, external_inventory_id, title_id, owner_id, rental_id, rental_from, rental_to, rentee_type, rentee_id, available)
-                    : (BookId, String, String, ExternalInventoryId, TitleId, EntityId, Option<RentalId>, Option<NaiveDate>, Option<NaiveDate>, Option<String>, Option<EntityId>, bool) = mysql::from_row(row);
-                    (
-                        Book {
-                            id: Some(book_id),
-                            title: title_id,
-                            owner_type: EntityType::from_str(owner_type.as_str()).expect("Bad owner type"),
-                            owner: owner_id,
-                            quality: quality,
-                            external_inventory_id,
-                        },
-                        rental_id.map_or_else(|| None, |id| Some(Rental {
-                            id: Some(id),
-                            from: rental_from.expect("rental start date is not set"),
-                            to: rental_to.expect("rental end date is not set"),
-                            book: book_id,
-                            rentee: rentee_id.expect("rentee_id is not set"),
-                            rentee_type: EntityType::from_str(rentee_type.expect("rentee type is not set").as_str()).expect("Bad rentee Type"),
-                        })),
-                        available,
+                ", condition), params)?
+            .into_iter()
+            .map(|row| {
+                let (book_id, owner_type, quality, external_inventory_id, title_id, owner_id, rental_id, rental_from, rental_to, rentee_type, rentee_id, rental_extensions)
+                : (BookId, String, String, ExternalInventoryId, TitleId, EntityId, Option<RentalId>, Option<NaiveDate>, Option<NaiveDate>, Option<String>, Option<EntityId>, Option<u32>) = from_row(row);
+                // Only active rentals are joined, so a book without one is available
+                let available = rental_id.is_none();
+                (
+                    Book {
+                        id: Some(book_id),
+                        title: title_id,
+                        owner_type: EntityType::from_str(owner_type.as_str()).expect("Bad owner type"),
+                        owner: owner_id,
+                        quality: quality,
+                        external_inventory_id,
+                    },
+                    rental_id.map_or_else(|| None, |id| Some(Rental {
+                        id: Some(id),
+                        from: rental_from.expect("rental start date is not set"),
+                        to: rental_to.expect("rental end date is not set"),
+                        book: book_id,
+                        rentee: rentee_id.expect("rentee_id is not set"),
+                        rentee_type: EntityType::from_str(rentee_type.expect("rentee type is not set").as_str()).expect("Bad rentee Type"),
+                        returned: None,
+                        extensions: rental_extensions.expect("rental extensions are not set"),
+                    })),
+                    available,
+                )
+            }).collect::<Vec<(Book, Option<Rental>, bool)>>())
+    }
+
+    /// Locks the row of a Book until the transaction ends, so that concurrent checkouts of it wait for each other.
+    /// Has to come first in the transaction, MySQL reads everything after it from a snapshot taken then.
+    pub fn lock_book(&self, book_id: BookId) -> Result<(), Error> {
+        match self.backend.dialect() {
+            // Plain reads under repeatable read do not see rentals committed in between, locking reads wait for them
+            Dialect::MySql => self
+                .backend
+                .query(
+                    "select book_id from books where book_id=:book_id for update;",
+                    params! {
+                        "book_id" => book_id,
+                    },
+                )
+                .map(|_| ()),
+            // Transactions take the write lock of the whole database as they begin
+            Dialect::Sqlite => Ok(()),
+        }
+    }
+
+    /// Locks the row of a Title and those of its Books until the transaction ends,
+    /// so that reservations of the title wait for checkouts and returns of its copies.
+    /// Has to come first in the transaction, like `lock_book`.
+    pub fn lock_title(&self, title_id: TitleId) -> Result<(), Error> {
+        match self.backend.dialect() {
+            Dialect::MySql => {
+                let params = params! {
+                    "title_id" => title_id,
+                };
+                self.backend.query(
+                    "select title_id from titles where title_id=:title_id for update;",
+                    params.clone(),
+                )?;
+                self.backend
+                    .query(
+                        "select book_id from books where title_by_id=:title_id for update;",
+                        params,
                     )
-                }).collect::<Vec<(Book, Option<Rental>, bool)>>()
-            });
+                    .map(|_| ())
+            }
+            Dialect::Sqlite => Ok(()),
+        }
+    }
+
+    /// Gets the rental of a Book which has not been returned yet, if there is any
+    pub fn get_active_rental_for_book(&self, book_id: BookId) -> Result<Option<Rental>, Error> {
+        let mut results = self
+            .backend
+            .query(
+                "select rental_id, from_date, to_date, book_by_id, rentee_member_by_id, rentee_guild_by_id, rentee_type, return_date, extensions from rentals \
+                where book_by_id=:book_id and return_date is null;",
+                params!{
+                    "book_id" => book_id,
+                },
+            )?
+            .into_iter()
+            .map(|row| {
+                let (id, from, to, book, rentee_member, rentee_guild, rentee_type, returned, extensions) = from_row(row);
+                Rental::from_db(id, from, to, book, rentee_member, rentee_guild, rentee_type, returned, extensions).unwrap()
+            }).collect::<Vec<Rental>>();
+        return Ok(results.pop());
+    }
+
+    /// Gets all rentals which are not returned although they ended before the given day
+    pub fn get_overdue_rentals(&self, day: Date) -> Result<Vec<Rental>, Error> {
+        Ok(self
+            .backend
+            .query(
+                "select rental_id, from_date, to_date, book_by_id, rentee_member_by_id, rentee_guild_by_id, rentee_type, return_date, extensions from rentals \
+                where return_date is null and to_date < :day \
+                order by to_date;",
+                params!{
+                    "day" => day,
+                },
+            )?
+            .into_iter()
+            .map(|row| {
+                let (id, from, to, book, rentee_member, rentee_guild, rentee_type, returned, extensions) = from_row(row);
+                Rental::from_db(id, from, to, book, rentee_member, rentee_guild, rentee_type, returned, extensions).unwrap()
+            }).collect::<Vec<Rental>>())
+    }
+
+    /// Gets the open reservations of a Title in queue order.
+    /// Reservations whose held copy expired before the given day are left out.
+    pub fn get_reservations_by_title(
+        &self,
+        title_id: TitleId,
+        day: Date,
+    ) -> Result<Vec<Reservation>, Error> {
+        Ok(self
+            .backend
+            .query(
+                "select reservation_id, title_by_id, member_by_id, reservation_date, held_book_by_id, held_until from reservations \
+                where title_by_id=:title_id and (held_book_by_id is null or held_until >= :day) \
+                order by reservation_date, reservation_id;",
+                params!{
+                    "title_id" => title_id,
+                    "day" => day,
+                },
+            )?
+            .into_iter()
+            .map(|row| {
+                let (id, title, member, date, book, held_until) = from_row(row);
+                Reservation {
+                    id: id,
+                    title: title,
+                    member: member,
+                    date: date,
+                    book: book,
+                    held_until: held_until,
+                }
+            }).collect::<Vec<Reservation>>())
+    }
+
+    /// Gets the first reservation of a Title which is still waiting for a copy
+    pub fn get_next_reservation_for_title(
+        &self,
+        title_id: TitleId,
+    ) -> Result<Option<Reservation>, Error> {
+        let mut results = self
+            .backend
+            .query(
+                "select reservation_id, title_by_id, member_by_id, reservation_date, held_book_by_id, held_until from reservations \
+                where title_by_id=:title_id and held_book_by_id is null \
+                order by reservation_date, reservation_id limit 1;",
+                params!{
+                    "title_id" => title_id,
+                },
+            )?
+            .into_iter()
+            .map(|row| {
+                let (id, title, member, date, book, held_until) = from_row(row);
+                Reservation {
+                    id: id,
+                    title: title,
+                    member: member,
+                    date: date,
+                    book: book,
+                    held_until: held_until,
+                }
+            }).collect::<Vec<Reservation>>();
+        return Ok(results.pop());
+    }
+
+    /// Gets the reservations of a Title whose held copy expired before the given day
+    pub fn get_expired_holds_for_title(
+        &self,
+        title_id: TitleId,
+        day: Date,
+    ) -> Result<Vec<Reservation>, Error> {
+        Ok(self
+            .backend
+            .query(
+                "select reservation_id, title_by_id, member_by_id, reservation_date, held_book_by_id, held_until from reservations \
+                where title_by_id=:title_id and held_book_by_id is not null and held_until < :day \
+                order by reservation_date, reservation_id;",
+                params!{
+                    "title_id" => title_id,
+                    "day" => day,
+                },
+            )?
+            .into_iter()
+            .map(|row| {
+                let (id, title, member, date, book, held_until) = from_row(row);
+                Reservation {
+                    id,
+                    title,
+                    member,
+                    date,
+                    book,
+                    held_until,
+                }
+            }).collect::<Vec<Reservation>>())
+    }
+
+    /// Gets the reservation a Book is held for on the given day, if there is any
+    pub fn get_reservation_holding_book(
+        &self,
+        book_id: BookId,
+        day: Date,
+    ) -> Result<Option<Reservation>, Error> {
+        let mut results = self
+            .backend
+            .query(
+                "select reservation_id, title_by_id, member_by_id, reservation_date, held_book_by_id, held_until from reservations \
+                where held_book_by_id=:book_id and held_until >= :day;",
+                params!{
+                    "book_id" => book_id,
+                    "day" => day,
+                },
+            )?
+            .into_iter()
+            .map(|row| {
+                let (id, title, member, date, book, held_until) = from_row(row);
+                Reservation {
+                    id: id,
+                    title: title,
+                    member: member,
+                    date: date,
+                    book: book,
+                    held_until: held_until,
+                }
+            }).collect::<Vec<Reservation>>();
+        return Ok(results.pop());
+    }
+
+    /// Gets the Member with the given external id, e.g. the subject of a token
+    pub fn get_member_by_external_id(
+        &self,
+        external_id: &ExternalId,
+    ) -> Result<Option<Member>, Error> {
+        let mut results = self
+            .backend
+            .query(
+                "select member_id, external_id from members where external_id=:external_id;",
+                params!{
+                    "external_id" => external_id.clone(),
+                },
+            )?
+            .into_iter()
+            .map(|row| {
+                let (id, external_id) = from_row(row);
+                Member {
+                    id: id,
+                    external_id: external_id,
+                }
+            }).collect::<Vec<Member>>();
+        return Ok(results.pop());
+    }
+
+    /// Get the api token with the given secret hash
+    pub fn get_api_token_by_hash(&self, hash: &str) -> Result<Option<ApiToken>, Error> {
+        let mut results = self
+            .backend
+            .query(
+                "select token_id, member_by_id, name, token_hash, roles, created_date, expires_date from api_tokens where token_hash=:hash;",
+                params!{
+                    "hash" => hash,
+                },
+            )?
+            .into_iter()
+            .map(|row| {
+                let (id, member, name, hash, roles, created, expires) = from_row(row);
+                ApiToken::from_db(id, member, name, hash, roles, created, expires)
+            }).collect::<Vec<ApiToken>>();
+        return Ok(results.pop());
+    }
+
+    /// Get all api tokens of a member
+    pub fn get_api_tokens_by_member(&self, member_id: MemberId) -> Result<Vec<ApiToken>, Error> {
+        Ok(self
+            .backend
+            .query(
+                "select token_id, member_by_id, name, token_hash, roles, created_date, expires_date from api_tokens where member_by_id=:member_id;",
+                params!{
+                    "member_id" => member_id,
+                },
+            )?
+            .into_iter()
+            .map(|row| {
+                let (id, member, name, hash, roles, created, expires) = from_row(row);
+                ApiToken::from_db(id, member, name, hash, roles, created, expires)
+            }).collect())
+    }
+
+    /// Get all memberships of a guild
+    pub fn get_guild_memberships_by_guild(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Vec<GuildMembership>, Error> {
+        Ok(self
+            .backend
+            .query(
+                "select membership_id, guild_by_id, member_by_id, role from guild_memberships where guild_by_id=:guild_id;",
+                params!{
+                    "guild_id" => guild_id,
+                },
+            )?
+            .into_iter()
+            .map(|row| {
+                let (id, guild, member, role) = from_row(row);
+                GuildMembership::from_db(id, guild, member, role)
+            }).collect())
+    }
+
+    /// Get the membership of a member in a guild
+    pub fn get_guild_membership(
+        &self,
+        guild_id: GuildId,
+        member_id: MemberId,
+    ) -> Result<Option<GuildMembership>, Error> {
+        let mut results = self
+            .backend
+            .query(
+                "select membership_id, guild_by_id, member_by_id, role from guild_memberships where guild_by_id=:guild_id and member_by_id=:member_id;",
+                params!{
+                    "guild_id" => guild_id,
+                    "member_id" => member_id,
+                },
+            )?
+            .into_iter()
+            .map(|row| {
+                let (id, guild, member, role) = from_row(row);
+                GuildMembership::from_db(id, guild, member, role)
+            }).collect::<Vec<GuildMembership>>();
+        return Ok(results.pop());
+    }
+
+    /// Get the id of the member with the given external id, creating the member if it does not exist yet
+    pub fn provision_member(&self, external_id: &ExternalId) -> Result<MemberId, Error> {
+        if let Some(member) = self.get_member_by_external_id(external_id)? {
+            return Ok(member.id.expect("member id shall not be empty"));
+        }
+
+        check_varchar_length!(external_id);
+        match self.backend.dialect() {
+            // last_insert_id(member_id) makes concurrent first logins return the existing id
+            Dialect::MySql => Ok(self.backend.execute(
+                "insert into members (external_id) values (:external_id) on duplicate key update member_id=last_insert_id(member_id);",
+                params!{
+                    "external_id" => external_id.clone(),
+                },
+            ).map(|changes| changes.last_insert_id)?),
+            // A concurrent first login may have inserted the member in between
+            Dialect::Sqlite => {
+                self.backend.execute(
+                    "insert or ignore into members (external_id) values (:external_id);",
+                    params!{
+                        "external_id" => external_id.clone(),
+                    },
+                )?;
+                self.get_member_by_external_id(external_id)?
+                    .and_then(|member| member.id)
+                    .ok_or(Error::IllegalState)
+            }
+        }
     }
 }
 
@@ -320,14 +784,8 @@ pub trait DMO<T = Self> {
     fn delete(this: &Database, id: Self::Id) -> Result<bool, Error>;
 }
 
-#[deprecated(since = "0.0.0", note = "this is a stub for later oauth roles")]
-#[derive(Debug, PartialEq, Eq, Serialize)]
-pub struct Role {
-    pub identifier: String,
-}
-
 #[cfg(test)]
-mod test_util {
+pub(crate) mod test_util {
     /*
     ████████ ███████ ███████ ████████ ███████
        ██    ██      ██         ██    ██
@@ -335,11 +793,12 @@ mod test_util {
        ██    ██           ██    ██         ██
        ██    ███████ ███████    ██    ███████
     */
+    use super::super::settings::Backend;
     use super::super::settings::Database as Db;
     use super::super::settings::TestSettings;
     use super::*;
-    use mysql;
     use rand::{thread_rng, Rng};
+    use std::{env, fs};
 
     pub fn _s(s: &str) -> String {
         String::from(s)
@@ -351,33 +810,54 @@ mod test_util {
     pub const TOO_LONG_STRING: &str = "Das beste 👿System der Welt welches lä😀nger als 255 zeich👿en lang ist, damit wir 😀einen Varchar sprechen!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! Du willst noch mehr=!=! Hier hast du mehr doofe Zeichen !!!!!!!!!! Bist du jetzt glücklich==";
 
     pub fn setup() -> Db {
+        create(TestSettings::new().unwrap().database)
+    }
+
+    /// Like `setup`, but on the MySQL server of the test settings whichever backend is set
+    pub fn setup_mysql() -> Db {
         let mut settings = TestSettings::new().unwrap().database;
+        settings.backend = Backend::Mysql;
+        create(settings)
+    }
 
-        let mut opts = mysql::OptsBuilder::default();
-        opts.ip_or_hostname(settings.hostname.clone())
-            .user(settings.username.clone())
-            .pass(settings.password.clone())
-            .prefer_socket(false);
+    fn create(mut settings: Db) -> Db {
+        let mut rng = thread_rng();
+        let name = format!("test_{}", rng.gen::<u32>());
+
+        match settings.backend {
+            Backend::Sqlite => {
+                settings.database = env::temp_dir()
+                    .join(format!("liberation_{}.sqlite", name))
+                    .to_string_lossy()
+                    .into_owned();
+            }
+            Backend::Mysql => {
+                let setup_pool = mysql::Pool::new_manual(1, 2, server_opts(&settings)).unwrap();
+                let mut conn = setup_pool.get_conn().unwrap();
 
-        match settings.port {
-            Some(port) => {
-                opts.tcp_port(port);
+                settings.database = name;
+                conn.query(format!("create database {}", settings.database))
+                    .unwrap();
             }
-            None => {}
         }
 
-        let setup_pool = mysql::Pool::new_manual(1, 2, opts).unwrap();
-        let mut conn = setup_pool.get_conn().unwrap();
-
-        let mut rng = thread_rng();
-        settings.database = String::from(format!("test_{}", rng.gen::<u32>()));
-        conn.query(format!("create database {}", settings.database))
-            .unwrap();
-
         return settings;
     }
 
     pub fn teardown(settings: Db) {
+        match settings.backend {
+            Backend::Sqlite => fs::remove_file(&settings.database).unwrap(),
+            Backend::Mysql => {
+                let pool = mysql::Pool::new_manual(1, 2, server_opts(&settings)).unwrap();
+                let mut conn = pool.get_conn().unwrap();
+
+                conn.query(format!("drop database {}", settings.database))
+                    .unwrap();
+            }
+        }
+    }
+
+    fn server_opts(settings: &Db) -> mysql::OptsBuilder {
         let mut opts = mysql::OptsBuilder::default();
         opts.ip_or_hostname(settings.hostname.clone())
             .user(settings.username.clone())
@@ -390,12 +870,7 @@ mod test_util {
             }
             None => {}
         }
-
-        let pool = mysql::Pool::new_manual(1, 2, opts).unwrap();
-        let mut conn = pool.get_conn().unwrap();
-
-        conn.query(format!("drop database {}", settings.database))
-            .unwrap();
+        opts
     }
 
     pub fn insert_book_default(db: &Database) -> Result<(BookId, Book), Error> {
@@ -435,8 +910,8 @@ mod test_util {
 
 #[cfg(test)]
 mod tests {
-    use database::test_util::*;
-    use database::Database;
+    use crate::database::test_util::*;
+    use crate::database::*;
     /*
     ████████ ███████ ███████ ████████ ███████
        ██    ██      ██         ██    ██
@@ -459,4 +934,55 @@ mod tests {
 
         teardown(settings);
     }
+
+    #[test]
+    fn transaction_commits_all_writes() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = db
+            .transaction(|tx| {
+                tx.insert(&Member::new(None, _s("first-uuid")))?;
+                tx.insert(&Member::new(None, _s("second-uuid")))
+            })
+            .and_then(|_| db.get_all::<Member>());
+        teardown(settings);
+        match result {
+            Ok(members) => assert_eq!(members.len(), 2),
+            Err(err) => panic!("Expected committed members, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn transaction_rolls_back_on_error() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = db.transaction(|tx| {
+            tx.insert(&Member::new(None, _s("first-uuid")))?;
+            tx.insert(&Member::new(None, _s(TOO_LONG_STRING)))
+        });
+        let members = db.get_all::<Member>();
+        teardown(settings);
+        match result {
+            Err(Error::DataTooLong(_)) => (),
+            _ => panic!("Expected DatabaseError::FieldError(FieldError::DataTooLong)"),
+        }
+        assert_eq!(members.unwrap().len(), 0);
+    }
+
+    #[test]
+    fn nested_transaction_joins_outer_one() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result: Result<(), Error> = db.transaction(|tx| {
+            tx.transaction(|inner| inner.insert(&Member::new(None, _s("first-uuid"))))?;
+            Err(Error::IllegalState)
+        });
+        let members = db.get_all::<Member>();
+        teardown(settings);
+        match result {
+            Err(Error::IllegalState) => (),
+            _ => panic!("Expected the error of the outer transaction"),
+        }
+        assert_eq!(members.unwrap().len(), 0);
+    }
 }
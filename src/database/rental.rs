@@ -1,28 +1,38 @@
 use super::*;
 use crate::serde_formats;
 use serde::Serialize;
-use mysql::params;
 
 /// Id type for Rental
 pub type RentalId = Id;
 
 /// Stores all information of a rental process
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, DMO)]
+#[dmo(table = "rentals", id = "rental_id")]
 pub struct Rental {
     /// Id
     pub id: Option<RentalId>,
     /// Date of rental
     #[serde(with = "serde_formats::naive_date")]
+    #[dmo(column = "from_date", date)]
     pub from: Date,
     /// Date of end of rental
     #[serde(with = "serde_formats::naive_date")]
+    #[dmo(column = "to_date", date)]
     pub to: Date,
     /// Id of rented Book
+    #[dmo(column = "book_by_id")]
     pub book: BookId,
     /// Type of rentee
     pub rentee_type: EntityType,
     /// Id of rentee
+    #[dmo(entity_type = "rentee_type", member = "rentee_member_by_id", guild = "rentee_guild_by_id")]
     pub rentee: EntityId,
+    /// Date the book was actually returned, None while the rental is active
+    #[serde(with = "serde_formats::optional_naive_date")]
+    #[dmo(column = "return_date", date)]
+    pub returned: Option<Date>,
+    /// How often the rental has been extended
+    pub extensions: u32,
 }
 
 impl Rental {
@@ -42,9 +52,16 @@ impl Rental {
             book: book,
             rentee: rentee,
             rentee_type: rentee_type,
+            returned: None,
+            extensions: 0,
         };
     }
 
+    /// Whether the book of this rental has not been returned yet
+    pub fn is_active(&self) -> bool {
+        self.returned.is_none()
+    }
+
     /// Construct a new Rental object with given parameters with manual input of rentee type
     pub fn from_db(
         id: RentalId,
@@ -54,6 +71,8 @@ impl Rental {
         rentee_member: Option<MemberId>,
         rentee_guild: Option<GuildId>,
         rentee_type: String,
+        returned: Option<Date>,
+        extensions: u32,
     ) -> Result<Rental, String> {
         let rentee_type = match EntityType::from_str(rentee_type.as_str()) {
             Ok(x) => x,
@@ -70,106 +89,18 @@ impl Rental {
             )),
         };
 
-        Ok(Rental::new(Some(id), from, to, book, rentee, rentee_type))
+        Ok(Rental {
+            returned,
+            extensions,
+            ..Rental::new(Some(id), from, to, book, rentee, rentee_type)
+        })
     }
 }
 
-impl DMO for Rental {
-    type Id = RentalId;
-
-    fn get(db: &Database, rental_id: RentalId) -> Result<Option<Rental>, Error> {
-        let mut results = db.pool
-        .prep_exec(
-            "select rental_id, from_date, to_date, book_by_id, rentee_member_by_id, rentee_guild_by_id, rentee_type from rentals where rental_id=:rental_id;",
-            params!{
-                "rental_id" => rental_id,
-            },
-        )
-    .map(|result| {
-        result.map(|x| x.unwrap()).map(|row| {
-            let (id, from, to, book, rentee_member, rentee_guild, rentee_type) = mysql::from_row(row);
-            let from: NaiveDate = from;
-            let to: NaiveDate = to;
-            Rental::from_db(id, from, to, book, rentee_member, rentee_guild, rentee_type).unwrap()
-        }).collect::<Vec<Rental>>()
-    })?;
-        return Ok(results.pop());
-    }
-
-    fn get_all(db: &Database) -> Result<Vec<Rental>, Error> {
-        Ok(db.pool.prep_exec("select rental_id, from_date, to_date, book_by_id, rentee_member_by_id, rentee_guild_by_id, rentee_type from rentals;",())
-    .map(|result| {
-        result.map(|x| x.unwrap()).map(|row| {
-            let (id, from, to, book, rentee_member, rentee_guild, rentee_type) = mysql::from_row(row);
-            //FIXME: @FutureMe: You should have handled the error directly!!!! You stupid prick.
-            let from: NaiveDate = from;
-            let to: NaiveDate = to;
-            Rental::from_db(id, from, to, book, rentee_member, rentee_guild, rentee_type).unwrap()
-        }).collect()
-    })?)
-    }
-
-    fn insert(db: &Database, inp: &Rental) -> Result<RentalId, Error> {
-        check_date!(inp.from, inp.to);
-        Ok(db.pool.prep_exec("insert into rentals (from_date, to_date, book_by_id, rentee_member_by_id, rentee_guild_by_id) values (:from, :to, :book, :rentee_member, :rentee_guild)",
-        params!{
-            "from" => inp.from,
-            "to" => inp.to,
-            "book" => inp.book,
-            "rentee_member" => match inp.rentee_type {
-                EntityType::Member => Some(inp.rentee),
-                EntityType::Guild => None,
-            },
-            "rentee_guild" => match inp.rentee_type {
-                EntityType::Member => None,
-                EntityType::Guild => Some(inp.rentee),
-            },
-        }).map(|result| result.last_insert_id()
-        )?)
-    }
-
-    fn update(db: &Database, rental: &Rental) -> Result<(), Error> {
-        check_date!(rental.from, rental.to);
-        Ok(db.pool.prep_exec("update rentals set from_date=:from, to_date=:to, book_by_id=:book, rentee_member_by_id=:rentee_member, rentee_guild_by_id=:rentee_guild where rental_id=:id;",
-        params!{
-            //"from" => rental.from.format(SQL_DATEFORMAT).to_string(),
-            //"to" => rental.to.format(SQL_DATEFORMAT).to_string(),
-            "from" => rental.from,
-            "to" => rental.to,
-            "book" => rental.book,
-            "rentee_member" => match rental.rentee_type {
-                EntityType::Member => Some(rental.rentee),
-                EntityType::Guild => None,
-            },
-            "rentee_guild" => match rental.rentee_type {
-                EntityType::Member => None,
-                EntityType::Guild => Some(rental.rentee),
-            },
-            "id" => rental.id,
-        }).and(Ok(()))?)
-    }
-
-    fn delete(db: &Database, id: Id) -> Result<bool, Error> {
-        Ok(db
-            .pool
-            .prep_exec(
-                "delete from rentals where rental_id=:id",
-                params! {
-                    "id" => id,
-                },
-            )
-            .map_err(|err| Error::DatabaseError(err))
-            .and_then(|result| match result.affected_rows() {
-                1 => Ok(true),
-                0 => Ok(false),
-                _ => Err(Error::IllegalState),
-            })?)
-    }
-}
 #[cfg(test)]
 mod tests {
-    use database::test_util::*;
-    use database::*;
+    use crate::database::test_util::*;
+    use crate::database::*;
 
     #[test]
     fn insert_rental_correct() {
@@ -221,6 +152,166 @@ mod tests {
         }
     }
 
+    #[test]
+    fn get_active_rental_ignores_returned() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = insert_book_default(&db)
+            .and_then(|(book_id, book)| {
+                let rental = Rental::new(
+                    None,
+                    _d(2018, 2, 4),
+                    _d(2018, 4, 16),
+                    book_id,
+                    book.owner,
+                    book.owner_type,
+                );
+                db.insert(&rental).and_then(|id| {
+                    Ok((
+                        book_id,
+                        Rental {
+                            id: Some(id),
+                            ..rental
+                        },
+                    ))
+                })
+            })
+            .and_then(|(book_id, rental)| {
+                db.get_active_rental_for_book(book_id)
+                    .and_then(|active| Ok((book_id, rental, active)))
+            })
+            .and_then(|(book_id, mut rental, active)| {
+                let was_active = active.map_or(false, |active| active == rental);
+                rental.returned = Some(_d(2018, 4, 10));
+                db.update(&rental)
+                    .and_then(|_| db.get_active_rental_for_book(book_id))
+                    .and_then(|active| Ok(was_active && active.is_none()))
+            });
+        teardown(settings);
+        match result {
+            Ok(true) => (),
+            Ok(false) => panic!("Expected only the unreturned rental to be active"),
+            _ => {
+                result.unwrap();
+                ()
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_checkouts_of_a_book_fail_but_one() {
+        checkout_concurrently(setup());
+    }
+
+    /// SQLite serialises all transactions, the row lock of `lock_book` only matters for MySQL.
+    /// Run with `cargo test -- --ignored` and a MySQL server in the test settings.
+    #[test]
+    #[ignore]
+    fn concurrent_checkouts_of_a_book_fail_but_one_on_mysql() {
+        checkout_concurrently(setup_mysql());
+    }
+
+    /// Checks out a book in two threads at once, only one of them may succeed
+    fn checkout_concurrently(settings: crate::settings::Database) {
+        use std::sync::{Arc, Barrier};
+        use std::thread;
+        use std::time::Duration;
+
+        let db = Database::from_settings(&settings).unwrap();
+        let result = insert_book_default(&db).and_then(|(book_id, book)| {
+            let barrier = Arc::new(Barrier::new(2));
+            let checkouts = (0..2)
+                .map(|_| {
+                    let db = db.clone();
+                    let barrier = barrier.clone();
+                    let book = book.clone();
+                    thread::spawn(move || {
+                        barrier.wait();
+                        db.transaction(|tx| {
+                            tx.lock_book(book_id)?;
+                            if tx.get_active_rental_for_book(book_id)?.is_some() {
+                                return Err(Error::BookNotAvailable);
+                            }
+                            // Gives the other checkout time to look at the book, too
+                            thread::sleep(Duration::from_millis(100));
+                            tx.insert(&Rental::new(
+                                None,
+                                _d(2018, 2, 4),
+                                _d(2018, 4, 16),
+                                book_id,
+                                book.owner,
+                                book.owner_type,
+                            ))
+                        })
+                        // Errors are not Send, their messages are enough to tell them apart
+                        .map_err(|err| format!("{}", err))
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|checkout| checkout.join().expect("checkout thread panicked"))
+                .collect::<Vec<Result<RentalId, String>>>();
+            db.get_all::<Rental>()
+                .map(|rentals| (checkouts, rentals.len()))
+        });
+        teardown(settings);
+        match result {
+            Ok((checkouts, 1)) => {
+                assert_eq!(
+                    checkouts.iter().filter(|checkout| checkout.is_ok()).count(),
+                    1
+                );
+                let not_available = Err(format!("{}", Error::BookNotAvailable));
+                assert!(checkouts.contains(&not_available));
+            }
+            Ok((_, rentals)) => panic!("Expected one rental, got {}", rentals),
+            _ => {
+                result.unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn get_overdue_rentals_correct() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = insert_book_default(&db)
+            .and_then(|(book_id, book)| {
+                let rental = Rental::new(
+                    None,
+                    _d(2018, 2, 4),
+                    _d(2018, 4, 16),
+                    book_id,
+                    book.owner,
+                    book.owner_type,
+                );
+                db.insert(&rental).and_then(|id| {
+                    Ok(Rental {
+                        id: Some(id),
+                        ..rental
+                    })
+                })
+            })
+            .and_then(|rental| {
+                db.get_overdue_rentals(_d(2018, 4, 16))
+                    .and_then(|on_time| Ok((rental, on_time)))
+            })
+            .and_then(|(rental, on_time)| {
+                db.get_overdue_rentals(_d(2018, 4, 17)).and_then(|overdue| {
+                    Ok(on_time.is_empty() && overdue.len() == 1 && overdue[0] == rental)
+                })
+            });
+        teardown(settings);
+        match result {
+            Ok(true) => (),
+            Ok(false) => panic!("Expected the rental to be overdue only after its end date"),
+            _ => {
+                result.unwrap();
+                ()
+            }
+        }
+    }
+
     #[test]
     fn insert_rental_invalid_book() {
         let settings = setup();
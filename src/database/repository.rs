@@ -0,0 +1,301 @@
+use super::*;
+
+/// An entity a repository stores by its id
+pub trait Record: DMO<Id = Id> + Clone + 'static {
+    /// Id, None before the entity is inserted
+    fn id(&self) -> Option<Id>;
+    /// Sets the id assigned on insert
+    fn set_id(&mut self, id: Id);
+}
+
+macro_rules! record {
+    ($( $t:ty ),+) => {
+        $(impl Record for $t {
+            fn id(&self) -> Option<Id> {
+                self.id
+            }
+
+            fn set_id(&mut self, id: Id) {
+                self.id = Some(id);
+            }
+        })*
+    }
+}
+
+record!(ApiToken, Book, Guild, GuildMembership, Member, Rental, Reservation, RpgSystem, Title);
+
+/// Basic operations on all entities, see `DMO`
+pub trait EntityRepository {
+    /// Gets all entities of the given type
+    fn get_all<T: Record>(&self) -> Result<Vec<T>, Error>;
+    /// Gets the entity of the given type with the given id
+    fn get<T: Record>(&self, id: Id) -> Result<Option<T>, Error>;
+    /// Inserts an entity and returns its new id
+    fn insert<T: Record>(&self, inp: &T) -> Result<Id, Error>;
+    /// Updates an entity
+    fn update<T: Record>(&self, up: &T) -> Result<(), Error>;
+    /// Deletes the entity of the given type with the given id, false if there is none
+    fn delete<T: Record>(&self, id: Id) -> Result<bool, Error>;
+}
+
+/// Titles and books with their stock and availability
+pub trait CatalogRepository {
+    fn get_titles_by_rpg_system(&self, system_id: RpgSystemId)
+        -> Result<Vec<(Title, u32, u32)>, Error>;
+    fn get_titles_with_details(&self) -> Result<Vec<(Title, RpgSystem, u32, u32)>, Error>;
+    fn get_title_with_details(
+        &self,
+        title_id: TitleId,
+    ) -> Result<Option<(Title, RpgSystem, u32, u32)>, Error>;
+    fn get_books_with_details(&self) -> Result<Vec<(Book, Option<Rental>, bool)>, Error>;
+    fn get_book_with_details(
+        &self,
+        book_id: BookId,
+    ) -> Result<Option<(Book, Option<Rental>, bool)>, Error>;
+    fn get_books_with_details_by_owner(
+        &self,
+        owner_type: &EntityType,
+        owner: EntityId,
+    ) -> Result<Vec<(Book, Option<Rental>, bool)>, Error>;
+    fn get_books_with_details_by_rentee(
+        &self,
+        rentee_type: &EntityType,
+        rentee: EntityId,
+    ) -> Result<Vec<(Book, Option<Rental>, bool)>, Error>;
+    fn get_next_external_inventory_id(&self) -> Result<ExternalInventoryId, Error>;
+}
+
+/// Rentals and reservations
+pub trait LendingRepository {
+    fn lock_book(&self, book_id: BookId) -> Result<(), Error>;
+    fn lock_title(&self, title_id: TitleId) -> Result<(), Error>;
+    fn get_active_rental_for_book(&self, book_id: BookId) -> Result<Option<Rental>, Error>;
+    fn get_overdue_rentals(&self, day: Date) -> Result<Vec<Rental>, Error>;
+    fn get_reservations_by_title(
+        &self,
+        title_id: TitleId,
+        day: Date,
+    ) -> Result<Vec<Reservation>, Error>;
+    fn get_next_reservation_for_title(
+        &self,
+        title_id: TitleId,
+    ) -> Result<Option<Reservation>, Error>;
+    fn get_expired_holds_for_title(
+        &self,
+        title_id: TitleId,
+        day: Date,
+    ) -> Result<Vec<Reservation>, Error>;
+    fn get_reservation_holding_book(
+        &self,
+        book_id: BookId,
+        day: Date,
+    ) -> Result<Option<Reservation>, Error>;
+}
+
+/// Members with their api tokens and guild memberships
+pub trait MemberRepository {
+    fn get_member_by_external_id(&self, external_id: &ExternalId)
+        -> Result<Option<Member>, Error>;
+    fn get_api_token_by_hash(&self, hash: &str) -> Result<Option<ApiToken>, Error>;
+    fn get_api_tokens_by_member(&self, member_id: MemberId) -> Result<Vec<ApiToken>, Error>;
+    fn get_guild_memberships_by_guild(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Vec<GuildMembership>, Error>;
+    fn get_guild_membership(
+        &self,
+        guild_id: GuildId,
+        member_id: MemberId,
+    ) -> Result<Option<GuildMembership>, Error>;
+}
+
+/// Several writes succeeding or failing together
+pub trait TransactionRepository {
+    /// Runs `f` in a transaction, see `Database::transaction`
+    fn transaction<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Self) -> Result<T, Error>;
+}
+
+/// Everything the business logic needs from the storage.
+/// Implemented by the `Database` and, for tests, by the `MemoryRepository`.
+pub trait Repository:
+    EntityRepository
+    + CatalogRepository
+    + LendingRepository
+    + MemberRepository
+    + TransactionRepository
+{
+}
+
+impl<R> Repository for R where
+    R: EntityRepository
+        + CatalogRepository
+        + LendingRepository
+        + MemberRepository
+        + TransactionRepository
+{
+}
+
+impl EntityRepository for Database {
+    fn get_all<T: Record>(&self) -> Result<Vec<T>, Error> {
+        T::get_all(self)
+    }
+
+    fn get<T: Record>(&self, id: Id) -> Result<Option<T>, Error> {
+        T::get(self, id)
+    }
+
+    fn insert<T: Record>(&self, inp: &T) -> Result<Id, Error> {
+        T::insert(self, inp)
+    }
+
+    fn update<T: Record>(&self, up: &T) -> Result<(), Error> {
+        T::update(self, up)
+    }
+
+    fn delete<T: Record>(&self, id: Id) -> Result<bool, Error> {
+        T::delete(self, id)
+    }
+}
+
+impl CatalogRepository for Database {
+    fn get_titles_by_rpg_system(
+        &self,
+        system_id: RpgSystemId,
+    ) -> Result<Vec<(Title, u32, u32)>, Error> {
+        Database::get_titles_by_rpg_system(self, system_id)
+    }
+
+    fn get_titles_with_details(&self) -> Result<Vec<(Title, RpgSystem, u32, u32)>, Error> {
+        Database::get_titles_with_details(self)
+    }
+
+    fn get_title_with_details(
+        &self,
+        title_id: TitleId,
+    ) -> Result<Option<(Title, RpgSystem, u32, u32)>, Error> {
+        Database::get_title_with_details(self, title_id)
+    }
+
+    fn get_books_with_details(&self) -> Result<Vec<(Book, Option<Rental>, bool)>, Error> {
+        Database::get_books_with_details(self)
+    }
+
+    fn get_book_with_details(
+        &self,
+        book_id: BookId,
+    ) -> Result<Option<(Book, Option<Rental>, bool)>, Error> {
+        Database::get_book_with_details(self, book_id)
+    }
+
+    fn get_books_with_details_by_owner(
+        &self,
+        owner_type: &EntityType,
+        owner: EntityId,
+    ) -> Result<Vec<(Book, Option<Rental>, bool)>, Error> {
+        Database::get_books_with_details_by_owner(self, owner_type, owner)
+    }
+
+    fn get_books_with_details_by_rentee(
+        &self,
+        rentee_type: &EntityType,
+        rentee: EntityId,
+    ) -> Result<Vec<(Book, Option<Rental>, bool)>, Error> {
+        Database::get_books_with_details_by_rentee(self, rentee_type, rentee)
+    }
+
+    fn get_next_external_inventory_id(&self) -> Result<ExternalInventoryId, Error> {
+        Database::get_next_external_inventory_id(self)
+    }
+}
+
+impl LendingRepository for Database {
+    fn lock_book(&self, book_id: BookId) -> Result<(), Error> {
+        Database::lock_book(self, book_id)
+    }
+
+    fn lock_title(&self, title_id: TitleId) -> Result<(), Error> {
+        Database::lock_title(self, title_id)
+    }
+
+    fn get_active_rental_for_book(&self, book_id: BookId) -> Result<Option<Rental>, Error> {
+        Database::get_active_rental_for_book(self, book_id)
+    }
+
+    fn get_overdue_rentals(&self, day: Date) -> Result<Vec<Rental>, Error> {
+        Database::get_overdue_rentals(self, day)
+    }
+
+    fn get_reservations_by_title(
+        &self,
+        title_id: TitleId,
+        day: Date,
+    ) -> Result<Vec<Reservation>, Error> {
+        Database::get_reservations_by_title(self, title_id, day)
+    }
+
+    fn get_next_reservation_for_title(
+        &self,
+        title_id: TitleId,
+    ) -> Result<Option<Reservation>, Error> {
+        Database::get_next_reservation_for_title(self, title_id)
+    }
+
+    fn get_expired_holds_for_title(
+        &self,
+        title_id: TitleId,
+        day: Date,
+    ) -> Result<Vec<Reservation>, Error> {
+        Database::get_expired_holds_for_title(self, title_id, day)
+    }
+
+    fn get_reservation_holding_book(
+        &self,
+        book_id: BookId,
+        day: Date,
+    ) -> Result<Option<Reservation>, Error> {
+        Database::get_reservation_holding_book(self, book_id, day)
+    }
+}
+
+impl MemberRepository for Database {
+    fn get_member_by_external_id(
+        &self,
+        external_id: &ExternalId,
+    ) -> Result<Option<Member>, Error> {
+        Database::get_member_by_external_id(self, external_id)
+    }
+
+    fn get_api_token_by_hash(&self, hash: &str) -> Result<Option<ApiToken>, Error> {
+        Database::get_api_token_by_hash(self, hash)
+    }
+
+    fn get_api_tokens_by_member(&self, member_id: MemberId) -> Result<Vec<ApiToken>, Error> {
+        Database::get_api_tokens_by_member(self, member_id)
+    }
+
+    fn get_guild_memberships_by_guild(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Vec<GuildMembership>, Error> {
+        Database::get_guild_memberships_by_guild(self, guild_id)
+    }
+
+    fn get_guild_membership(
+        &self,
+        guild_id: GuildId,
+        member_id: MemberId,
+    ) -> Result<Option<GuildMembership>, Error> {
+        Database::get_guild_membership(self, guild_id, member_id)
+    }
+}
+
+impl TransactionRepository for Database {
+    fn transaction<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Database) -> Result<T, Error>,
+    {
+        Database::transaction(self, f)
+    }
+}
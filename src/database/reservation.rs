@@ -0,0 +1,201 @@
+use super::*;
+use crate::serde_formats;
+use serde::Serialize;
+
+/// Id type for Reservation
+pub type ReservationId = Id;
+
+/// A member waiting for a copy of a title.
+/// Once a copy is returned it is held for the first reservation in the queue.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, DMO)]
+#[dmo(table = "reservations", id = "reservation_id")]
+pub struct Reservation {
+    /// Id
+    pub id: Option<ReservationId>,
+    /// Reserved title
+    #[dmo(column = "title_by_id")]
+    pub title: TitleId,
+    /// Id of the waiting member
+    #[dmo(column = "member_by_id")]
+    pub member: MemberId,
+    /// Date the reservation was placed
+    #[serde(with = "serde_formats::naive_date")]
+    #[dmo(column = "reservation_date", date)]
+    pub date: Date,
+    /// Copy held for the member, None while still waiting in the queue
+    #[dmo(column = "held_book_by_id")]
+    pub book: Option<BookId>,
+    /// Last day the held copy is kept for the member
+    #[serde(with = "serde_formats::optional_naive_date")]
+    #[dmo(date)]
+    pub held_until: Option<Date>,
+}
+
+impl Reservation {
+    /// Construct a new Reservation object with given parameters
+    pub fn new(
+        id: Option<ReservationId>,
+        title: TitleId,
+        member: MemberId,
+        date: Date,
+    ) -> Reservation {
+        Reservation {
+            id: id,
+            title: title,
+            member: member,
+            date: date,
+            book: None,
+            held_until: None,
+        }
+    }
+
+    /// Whether a copy is held for this reservation on the given day
+    pub fn is_holding(&self, day: Date) -> bool {
+        match (self.book, self.held_until) {
+            (Some(_), Some(held_until)) => held_until >= day,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::test_util::*;
+    use crate::database::*;
+
+    #[test]
+    fn insert_reservation_correct() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = insert_book_default(&db)
+            .and_then(|(_book_id, book)| {
+                let reservation = Reservation::new(None, book.title, book.owner, _d(2019, 3, 12));
+                db.insert(&reservation).and_then(|id| Ok((id, reservation)))
+            })
+            .and_then(|(id, reservation)| {
+                db.get::<Reservation>(id).and_then(|rec_reservation| {
+                    Ok(rec_reservation.map_or(false, |fetched_reservation| {
+                        Reservation {
+                            id: Some(id),
+                            ..reservation
+                        } == fetched_reservation
+                    }))
+                })
+            });
+        teardown(settings);
+        match result {
+            Ok(true) => (),
+            Ok(false) => panic!("Inserted reservation is not in DB :("),
+            _ => {
+                result.unwrap();
+                ()
+            }
+        }
+    }
+
+    #[test]
+    fn update_reservation_hold() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = insert_book_default(&db)
+            .and_then(|(book_id, book)| {
+                let reservation = Reservation::new(None, book.title, book.owner, _d(2019, 3, 12));
+                db.insert(&reservation).and_then(|id| {
+                    Ok(Reservation {
+                        id: Some(id),
+                        book: Some(book_id),
+                        held_until: Some(_d(2019, 3, 19)),
+                        ..reservation
+                    })
+                })
+            })
+            .and_then(|reservation| {
+                db.update(&reservation)
+                    .and_then(|_| db.get_next_reservation_for_title(reservation.title))
+                    .and_then(|next| Ok((reservation, next)))
+            })
+            .and_then(|(reservation, next)| {
+                db.get_reservation_holding_book(reservation.book.unwrap(), _d(2019, 3, 15))
+                    .and_then(|holding| {
+                        Ok(next.is_none() && holding.map_or(false, |held| held == reservation))
+                    })
+            });
+        teardown(settings);
+        match result {
+            Ok(true) => (),
+            Ok(false) => panic!("Expected held reservation to leave the queue"),
+            _ => {
+                result.unwrap();
+                ()
+            }
+        }
+    }
+
+    #[test]
+    fn get_expired_holds_for_title() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = insert_book_default(&db)
+            .and_then(|(book_id, book)| {
+                let reservation = Reservation {
+                    book: Some(book_id),
+                    held_until: Some(_d(2019, 3, 19)),
+                    ..Reservation::new(None, book.title, book.owner, _d(2019, 3, 12))
+                };
+                db.insert(&reservation).and_then(|id| {
+                    Ok(Reservation {
+                        id: Some(id),
+                        ..reservation
+                    })
+                })
+            })
+            .and_then(|reservation| {
+                let title = reservation.title;
+                db.get_expired_holds_for_title(title, _d(2019, 3, 19))
+                    .and_then(|held| {
+                        db.get_expired_holds_for_title(title, _d(2019, 3, 20))
+                            .and_then(|expired| Ok(held.is_empty() && expired == vec![reservation]))
+                    })
+            });
+        teardown(settings);
+        match result {
+            Ok(true) => (),
+            Ok(false) => panic!("Expected the hold to expire after its last day"),
+            _ => {
+                result.unwrap();
+                ()
+            }
+        }
+    }
+
+    #[test]
+    fn insert_second_reservation_of_member_fails() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = insert_book_default(&db).and_then(|(_book_id, book)| {
+            db.insert(&Reservation::new(None, book.title, book.owner, _d(2019, 3, 12)))
+                .and_then(|_| {
+                    db.insert(&Reservation::new(None, book.title, book.owner, _d(2019, 3, 13)))
+                })
+        });
+        teardown(settings);
+        match result {
+            Err(Error::DuplicateEntry) => (),
+            _ => panic!("Expected DuplicateEntry error"),
+        }
+    }
+
+    #[test]
+    fn insert_reservation_invalid_title() {
+        let settings = setup();
+        let db = Database::from_settings(&settings).unwrap();
+        let result = insert_book_default(&db).and_then(|(_book_id, book)| {
+            db.insert(&Reservation::new(None, 012481632, book.owner, _d(2019, 3, 12)))
+        });
+        teardown(settings);
+        match result {
+            Err(Error::ConstraintError(_)) => (),
+            _ => panic!("Expected DatabaseError::FieldError(FieldError::ConstraintError)"),
+        }
+    }
+}
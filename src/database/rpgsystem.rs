@@ -1,17 +1,18 @@
 use super::*;
 use std::string::String;
 use serde::{Serialize, Deserialize};
-use mysql::params;
 
 /// Id type for RpgSystem
 pub type RpgSystemId = Id;
 
 /// An RPG System
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, DMO)]
+#[dmo(table = "rpg_systems", id = "rpg_system_id")]
 pub struct RpgSystem {
     /// Id
     pub id: Option<RpgSystemId>,
     /// Name of RPG System
+    #[dmo(varchar)]
     pub name: String,
     /// Common abbreviation of the system name, e.g. D&D
     pub shortname: Option<String>,
@@ -28,109 +29,11 @@ impl RpgSystem {
     }
 }
 
-impl DMO for RpgSystem {
-    type Id = RpgSystemId;
-    fn insert(db: &Database, inp: &RpgSystem) -> Result<RpgSystemId, Error> {
-        check_varchar_length!(inp.name);
-        Ok(db
-            .pool
-            .prep_exec(
-                "insert into rpg_systems (name, shortname) values (:name, :shortname)",
-                params! {
-                    "name" => inp.name.clone(),
-                    "shortname" => inp.shortname.clone()
-                },
-            )
-            .map(|result| result.last_insert_id())?)
-    }
-
-    fn get_all(db: &Database) -> Result<Vec<RpgSystem>, Error> {
-        Ok(db
-            .pool
-            .prep_exec(
-                "select rpg_system_id, name, shortname from rpg_systems;",
-                (),
-            )
-            .map(|result| {
-                result
-                    .map(|x| x.unwrap())
-                    .map(|row| {
-                        let (id, name, short): (Option<RpgSystemId>, String, Option<String>) =
-                            mysql::from_row(row);
-                        RpgSystem {
-                            id: id,
-                            name: name,
-                            shortname: short,
-                        }
-                    })
-                    .collect()
-            })?)
-    }
-
-    //TODO: Test
-    fn get(db: &Database, rpg_system_id: Id) -> Result<Option<RpgSystem>, Error> {
-        let mut results = db.pool
-            .prep_exec(
-                "select rpg_system_id, name, shortname from rpg_systems where rpg_system_id=:rpg_system_id;",
-                params!{
-                    "rpg_system_id" => rpg_system_id,
-                },
-            )
-            .map(|result| {
-                result
-                    .map(|x| x.unwrap())
-                    .map(|row| {
-                        let (id, name, short) : (Option<RpgSystemId>, String, Option<String>) = mysql::from_row(row);
-                        RpgSystem { id: id, name: name, shortname: short }
-                    })
-                    .collect::<Vec<RpgSystem>>()
-            })?;
-        return Ok(results.pop());
-    }
-
-    fn update(db: &Database, rpgsystem: &RpgSystem) -> Result<(), Error> {
-        check_varchar_length!(rpgsystem.name);
-        /*match rpgsystem.shortname {
-            None => (),
-            Some(short) => check_varchar_length!(short)
-        }*/
-
-        Ok(db
-            .pool
-            .prep_exec(
-                "update rpg_systems set name=:name, shortname=:short where rpg_system_id=:id;",
-                params! {
-                    "name" => rpgsystem.name.clone(),
-                    "short" => rpgsystem.shortname.clone(),
-                    "id" => rpgsystem.id,
-                },
-            )
-            .map(|_| ())?)
-    }
-
-    fn delete(db: &Database, id: Id) -> Result<bool, Error> {
-        Ok(db
-            .pool
-            .prep_exec(
-                "delete from rpg_systems where rpg_system_id=:id",
-                params! {
-                    "id" => id,
-                },
-            )
-            .map_err(|err| Error::DatabaseError(err))
-            .and_then(|result| match result.affected_rows() {
-                1 => Ok(true),
-                0 => Ok(false),
-                _ => Err(Error::IllegalState),
-            })?)
-    }
-}
-
 #[cfg(test)]
 mod tests {
-    use database::test_util::*;
-    use database::RpgSystem;
-    use database::{Database, Error};
+    use crate::database::test_util::*;
+    use crate::database::RpgSystem;
+    use crate::database::{Database, Error};
 
     #[test]
     fn insert_rpg_system_correct() {
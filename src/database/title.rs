@@ -1,23 +1,26 @@
 use super::*;
 use serde::{Serialize, Deserialize};
-use mysql;
-use mysql::params;
 
 /// Id type for Title
 pub type TitleId = Id;
 
 /// Describes an abstract book. Copys of the book are stored in the Book type.
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, DMO)]
+#[dmo(table = "titles", id = "title_id")]
 pub struct Title {
     /// Id
     pub id: Option<TitleId>,
     /// Name/title of the book
+    #[dmo(varchar)]
     pub name: String,
     /// RPG System to which the book belongs
+    #[dmo(column = "rpg_system_by_id")]
     pub system: RpgSystemId,
     /// Language
+    #[dmo(varchar)]
     pub language: String,
     /// Publisher
+    #[dmo(varchar)]
     pub publisher: String,
     /// Year of publishing
     pub year: Year,
@@ -48,103 +51,10 @@ impl Title {
     }
 }
 
-impl DMO for Title {
-    type Id = TitleId;
-    fn get_all(db: &Database) -> Result<Vec<Title>, Error> {
-        Ok(db.pool.prep_exec("select title_id, name, rpg_system_by_id, language, publisher, year, coverimage from titles;",())
-    .map(|result| {
-        result.map(|x| x.unwrap()).map(|row| {
-            let (id, name, system, language, publisher, year, coverimage) = mysql::from_row(row);
-            Title {
-                id: id,
-                name: name,
-                system: system,
-                language: language,
-                publisher: publisher,
-                year: year,
-                coverimage: coverimage,
-            }
-        }).collect()
-    })?)
-    }
-
-    //TODO: Test
-    fn get(db: &Database, title_id: TitleId) -> Result<Option<Title>, Error> {
-        let mut results = db.pool
-        .prep_exec(
-            "select title_id, name, rpg_system_by_id, language, publisher, year, coverimage from titles where title_id=:title_id;",
-            params!{
-                "title_id" => title_id,
-            },
-        )
-        .map(|result| {
-            result.map(|x| x.unwrap()).map(|row| {
-                let (id, name, system, language, publisher, year, coverimage) = mysql::from_row(row);
-                Title {
-                    id: id,
-                    name: name,
-                    system: system,
-                    language: language,
-                    publisher: publisher,
-                    year: year,
-                    coverimage: coverimage,
-                }
-            }).collect::<Vec<Title>>()
-        })?;
-        return Ok(results.pop());
-    }
-
-    //TODO Test
-
-    fn insert(db: &Database, inp: &Title) -> Result<TitleId, Error> {
-        check_varchar_length!(inp.name, inp.language, inp.publisher);
-        Ok(db.pool.prep_exec("insert into titles (name, rpg_system_by_id, language, publisher, year, coverimage) values (:name, :system, :language, :publisher, :year, :coverimage)",
-        params!{
-            "name" => inp.name.clone(),
-            "system" => inp.system,
-            "language" => inp.language.clone(),
-            "publisher" => inp.publisher.clone(),
-            "year" => inp.year,
-            "coverimage" => inp.coverimage.clone(),
-        }).map(|result| result.last_insert_id())?)
-    }
-
-    fn update(db: &Database, title: &Title) -> Result<(), Error> {
-        check_varchar_length!(title.name, title.language, title.publisher);
-        Ok(db.pool.prep_exec("update titles set name=:name, rpg_system_by_id=:system, language=:language, publisher=:publisher, year=:year, coverimage=:coverimage where title_id=:id;",
-        params!{
-            "name" => title.name.clone(),
-            "system" => title.system,
-            "language" => title.language.clone(),
-            "publisher" => title.publisher.clone(),
-            "year" => title.year,
-            "coverimage" => title.coverimage.clone(),
-            "id" => title.id,
-        }).and(Ok(()))?)
-    }
-
-    fn delete(db: &Database, id: Id) -> Result<bool, Error> {
-        Ok(db
-            .pool
-            .prep_exec(
-                "delete from titles where title_id=:id",
-                params! {
-                    "id" => id,
-                },
-            )
-            .map_err(|err| Error::DatabaseError(err))
-            .and_then(|result| match result.affected_rows() {
-                1 => Ok(true),
-                0 => Ok(false),
-                _ => Err(Error::IllegalState),
-            })?)
-    }
-}
-
 #[cfg(test)]
 mod tests {
-    use database::test_util::*;
-    use database::*;
+    use crate::database::test_util::*;
+    use crate::database::*;
 
     #[test]
     fn insert_title_name_too_long() {
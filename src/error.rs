@@ -4,6 +4,7 @@ use awc;
 use core::num::ParseIntError;
 
 use mysql::Error as MySqlError;
+use rusqlite::Error as SqliteError;
 
 
 use std::fmt;
@@ -16,8 +17,12 @@ type Field = String;
 pub enum Error {
     /// Internal Database Errors -> 500
     DatabaseError(MySqlError),
+    /// Internal Errors of a SQLite database -> 500
+    SqliteError(SqliteError),
     /// Database Constraints, usually from invalid User input -> 400 or 500
     ConstraintError(Option<Field>),
+    /// Row with the same unique key exists already -> 409
+    DuplicateEntry,
     /// User input is too long -> 400
     DataTooLong(Field),
     /// User input has wrong type -> 400
@@ -27,11 +32,29 @@ pub enum Error {
     /// Invalid Json from user -> 400
     JsonPayloadError(actix_web::error::JsonPayloadError),
     /// Backend can not authenticate with the Keycloak server-> 500
-    //KeycloakAuthenticationError(Box<RequestTokenError<dyn Fail, BasicErrorResponseType>>),
+    KeycloakAuthenticationError(String),
+    /// Keycloak answered with an error status -> 500
+    KeycloakStatusError(u16),
     /// No connection to Keycloak server -> 500
     KeycloakConnectionError(SendRequestError),
     /// Keycload answer wrong -> 500
     KeycloakJsonError(awc::error::JsonPayloadError),
+    /// No connection to the OpenID provider -> 500
+    OidcConnectionError(SendRequestError),
+    /// OpenID provider answer wrong -> 500
+    OidcJsonError(awc::error::JsonPayloadError),
+    /// Discovered issuer differs from the configured one -> 500
+    OidcIssuerMismatch(String),
+    /// Database schema is older than the binary and may not be migrated automatically -> 500
+    SchemaOutdated(u32, u32),
+    /// Database schema was migrated by a newer binary -> 500
+    SchemaTooNew(u32, u32),
+    /// Database settings the backend can not work with -> 500
+    UnsupportedDatabase(String),
+    /// Named database lock held by another connection for too long -> 500
+    LockTimeout(String),
+    /// Introspection endpoint not reachable or answer wrong -> 500
+    IntrospectionError(String),
     /// Authentication Token is invalid -> 401
     InvalidAuthenticationError,
     /// Missing a required claim -> 403
@@ -44,12 +67,23 @@ pub enum Error {
     ActixError(error::Error),
     /// No item with given id found -> 404
     ItemNotFound,
+    /// Book is currently rented by someone else -> 409
+    BookNotAvailable,
+    /// Rental has already been returned -> 409
+    RentalAlreadyReturned,
+    /// Title still has available copies, no need to reserve it -> 409
+    TitleAvailable,
+    /// Member already has an open reservation for the title -> 409
+    AlreadyReserved,
+    /// Request breaks the configured lending policy -> 409
+    LendingPolicyViolation(String),
 }
 
 impl From<MySqlError> for Error {
     fn from(error: MySqlError) -> Self {
         match error {
             MySqlError::MySqlError(ref e) if e.code == 1452 => Error::ConstraintError(None),
+            MySqlError::MySqlError(ref e) if e.code == 1062 => Error::DuplicateEntry,
             /*MySqlError::MySqlError(e) => match e.code {
                 1452 => DatabaseError::FieldError(FieldError::ConstraintError(None)),
                 _ => DatabaseError::GenericError(MySqlError::MySqlError(e)),
@@ -59,6 +93,24 @@ impl From<MySqlError> for Error {
     }
 }
 
+impl From<SqliteError> for Error {
+    fn from(error: SqliteError) -> Self {
+        match error {
+            SqliteError::SqliteFailure(ref e, _)
+                if e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY =>
+            {
+                Error::ConstraintError(None)
+            }
+            SqliteError::SqliteFailure(ref e, _)
+                if e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE =>
+            {
+                Error::DuplicateEntry
+            }
+            _ => Error::SqliteError(error),
+        }
+    }
+}
+
 //impl From<NoneError> for Error {
 //    fn from(error: NoneError) -> Self {
 //        Error::BadRequestFormat
@@ -104,12 +156,44 @@ impl fmt::Display for Error {
         match *self {
             //TODO: Use Field when available
             Error::ConstraintError(_) => write!(f, "ERROR: unknown constaint error"),
+            Error::DuplicateEntry => write!(f, "ERROR: entry exists already"),
             Error::DataTooLong(ref field) => write!(f, "ERROR: data too long for field: {}", field),
             Error::IllegalValueForType(ref field) => {
                 write!(f, "ERROR: illegal value in field: {}", field)
             }
             Error::DatabaseError(ref err) => write!(f, "{{ {} }}", err),
+            Error::SqliteError(ref err) => write!(f, "{{ {} }}", err),
             Error::JsonPayloadError(ref err) => write!(f, "{{ {} }}", err),
+            Error::ItemNotFound => write!(f, "ERROR: item not found"),
+            Error::BookNotAvailable => write!(f, "ERROR: book is not available"),
+            Error::RentalAlreadyReturned => write!(f, "ERROR: rental has already been returned"),
+            Error::TitleAvailable => write!(f, "ERROR: title has available copies"),
+            Error::AlreadyReserved => write!(f, "ERROR: title is already reserved"),
+            Error::LendingPolicyViolation(ref reason) => {
+                write!(f, "ERROR: lending policy violated: {}", reason)
+            }
+            Error::OidcIssuerMismatch(ref issuer) => {
+                write!(f, "ERROR: openid provider announced unexpected issuer: {}", issuer)
+            }
+            Error::SchemaOutdated(database, binary) => write!(
+                f,
+                "ERROR: database schema version {} is older than {}, run `liberation migrate`",
+                database, binary
+            ),
+            Error::SchemaTooNew(database, binary) => write!(
+                f,
+                "ERROR: database schema version {} is newer than {} supported by this binary",
+                database, binary
+            ),
+            Error::UnsupportedDatabase(ref reason) => {
+                write!(f, "ERROR: unsupported database: {}", reason)
+            }
+            Error::LockTimeout(ref name) => {
+                write!(f, "ERROR: timed out waiting for database lock {}", name)
+            }
+            Error::IntrospectionError(ref reason) => {
+                write!(f, "ERROR: token introspection failed: {}", reason)
+            }
             //Error::KeycloakAuthenticationError(ref err) => write!(f, "{{ {} }}", err),
             // Error::ActixError(ref err) => write!(f, "{{ {} }}", err),
             _ => write!(f, "ERROR: unknown error"),
@@ -125,6 +209,9 @@ impl ResponseError for Error {
             Error::DataTooLong(ref e) => HttpResponse::BadRequest()
                 .header("x-field", e.clone())
                 .body(format!("{}", self)),
+            Error::IllegalValueForType(ref e) => HttpResponse::BadRequest()
+                .header("x-field", e.clone())
+                .body(format!("{}", self)),
             Error::InvalidAuthenticationError => HttpResponse::Unauthorized()
                 .header(
                     "WWW-Authenticate",
@@ -133,6 +220,15 @@ impl ResponseError for Error {
                 .finish(),
             Error::YouShallNotPassError => HttpResponse::Forbidden().finish(),
             Error::SpeakFriendAndEnterError => HttpResponse::Unauthorized().finish(),
+            Error::ItemNotFound => HttpResponse::NotFound().finish(),
+            Error::DuplicateEntry
+            | Error::BookNotAvailable
+            | Error::RentalAlreadyReturned
+            | Error::TitleAvailable
+            | Error::AlreadyReserved
+            | Error::LendingPolicyViolation(_) => {
+                HttpResponse::Conflict().body(format!("{}", self))
+            }
             //_ => HttpResponse::InternalServerError().finish(), TODO: Debugging option
             // Error::ActixError(err) => err.as_response_error().error_response(),
             // Error::ActixInternalError(err) => err.error_response(),
@@ -0,0 +1,103 @@
+use crate::auth::roles::ROLE_DEVELOPER;
+use crate::auth::Claims;
+use crate::error::Error;
+use crate::settings::Feature;
+use std::collections::HashMap;
+
+/// Reserving titles which have no available copies
+pub const FEATURE_RESERVATIONS: &str = "reservations";
+
+/// Feature flags from the config. Features missing in the config are disabled.
+#[derive(Clone, Debug)]
+pub struct Features {
+    flags: HashMap<String, Feature>,
+}
+
+impl Features {
+    pub fn new(flags: HashMap<String, Feature>) -> Features {
+        Features { flags }
+    }
+
+    /// Whether the feature is available to the logged in user.
+    /// Features restricted to developers need the developer role.
+    pub fn is_enabled(&self, name: &str, claims: &Option<Claims>) -> bool {
+        match self.flags.get(name) {
+            Some(feature) if feature.enabled && feature.developer => claims
+                .as_ref()
+                .map_or(false, |claims| claims.roles.iter().any(|role| role == ROLE_DEVELOPER)),
+            Some(feature) => feature.enabled,
+            None => false,
+        }
+    }
+
+    /// Fails with not found, so disabled features look like they do not exist
+    pub fn assert_enabled(&self, name: &str, claims: &Option<Claims>) -> Result<(), Error> {
+        match self.is_enabled(name, claims) {
+            true => Ok(()),
+            false => Err(Error::ItemNotFound),
+        }
+    }
+
+    /// Names of all features available to the logged in user
+    pub fn enabled_for(&self, claims: &Option<Claims>) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .flags
+            .keys()
+            .filter(|name| self.is_enabled(name, claims))
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::mock_idp::claims;
+
+    fn features() -> Features {
+        let feature = |enabled, developer| Feature { enabled, developer };
+        let mut flags = HashMap::new();
+        flags.insert(String::from("released"), feature(true, false));
+        flags.insert(String::from("disabled"), feature(false, false));
+        flags.insert(String::from("beta"), feature(true, true));
+        flags.insert(String::from("alpha"), feature(true, false));
+        Features::new(flags)
+    }
+
+    #[test]
+    fn disabled_and_unknown_features_are_not_enabled() {
+        let features = features();
+        let developer = Some(claims("developer", vec![ROLE_DEVELOPER]));
+        for name in &["disabled", "unknown"] {
+            assert!(!features.is_enabled(name, &developer));
+            match features.assert_enabled(name, &developer) {
+                Err(Error::ItemNotFound) => (),
+                _ => panic!("Expected Error::ItemNotFound"),
+            }
+        }
+    }
+
+    #[test]
+    fn developer_features_need_the_developer_role() {
+        let features = features();
+        let developer = Some(claims("developer", vec![ROLE_DEVELOPER]));
+        let member = Some(claims("member", vec!["member"]));
+        assert!(features.is_enabled("beta", &developer));
+        assert!(!features.is_enabled("beta", &member));
+        assert!(!features.is_enabled("beta", &None));
+        assert!(features.is_enabled("released", &None));
+    }
+
+    #[test]
+    fn enabled_for_lists_the_enabled_features_sorted() {
+        let features = features();
+        let developer = Some(claims("developer", vec![ROLE_DEVELOPER]));
+        assert_eq!(features.enabled_for(&None), vec!["alpha", "released"]);
+        assert_eq!(
+            features.enabled_for(&developer),
+            vec!["alpha", "beta", "released"]
+        );
+    }
+}
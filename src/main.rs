@@ -5,14 +5,16 @@ mod auth;
 mod business;
 mod database;
 mod error;
+mod features;
 mod serde_formats;
 mod settings;
 
 use actix::{Actor, System};
 use actix_web::{web, App, HttpServer};
 use api::{get_static, get_v1};
-use auth::KeycloakCache;
+use auth::{Introspector, JwtVerifier, KeycloakCache};
 use settings::Settings;
+use std::env;
 use actix_web::middleware::Logger;
 
 fn main() {
@@ -20,25 +22,63 @@ fn main() {
 
     info!("retrieving settings ...");
     let settings = Settings::new().unwrap();
+
+    if env::args().nth(1).as_ref().map(String::as_str) == Some("migrate") {
+        info!("migrating DB ...");
+        let applied = database::Database::connect(&settings.database)
+            .and_then(|db| db.migrate())
+            .unwrap_or_else(|err| panic!("{}", err));
+        info!("applied {} migrations", applied.len());
+        return;
+    }
+
     info!("initializing DB ...");
-    let db = database::Database::from_settings(&settings.database).unwrap();
+    let db = database::Database::from_settings(&settings.database)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    info!("initializing openid provider ...");
+    let verifier = match (settings.oidc.introspection, &settings.keycloak) {
+        (true, Some(keycloak)) => JwtVerifier::from_settings(&settings.oidc).with_introspection(
+            Introspector::from_settings(keycloak)
+                .with_optional_audience(settings.oidc.optionalaudience),
+        ),
+        (true, None) => {
+            warn!("token introspection needs the client credentials of the keycloak section");
+            JwtVerifier::from_settings(&settings.oidc)
+        }
+        (false, _) => JwtVerifier::from_settings(&settings.oidc),
+    };
+    let oidc_actor = auth::OidcProvider::new(verifier.clone());
 
     info!("initializing keycloak ...");
     let kc: KeycloakCache = KeycloakCache::new();
-    let kc_actor = auth::Keycloak::from_settings(&settings.keycloak, kc.clone());
+    let kc_actor = settings
+        .keycloak
+        .as_ref()
+        .map(|keycloak| auth::Keycloak::from_settings(keycloak, kc.clone()));
 
     let state = api::AppState {
         db,
         kc: kc.clone(),
+        verifier,
+        lending: settings.lending.clone(),
+        features: features::Features::new(settings.features.clone()),
     };
 
     let sys = System::new("server");
-    kc_actor.start();
+    oidc_actor.start();
+    match kc_actor {
+        Some(kc_actor) => {
+            kc_actor.start();
+        }
+        None => warn!("no keycloak configured, member names and roles are not available"),
+    }
 
     let serve_static_files = settings.serve_static_files;
     HttpServer::new(move || {
         let mut app = App::new()
             .wrap(Logger::default())
+            .wrap(auth::IntrospectTokens::new(state.verifier.clone()))
             .register_data(web::Data::new(state.clone()))
             .service(get_v1());
         if serve_static_files {
@@ -1,7 +1,7 @@
 pub mod naive_date {
     use chrono::NaiveDate;
-    use serde::Serializer;
-    const DATE_FORMAT: &'static str = "%Y-%m-%d";
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    pub const DATE_FORMAT: &'static str = "%Y-%m-%d";
 
     pub fn serialize<S>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -10,4 +10,40 @@ pub mod naive_date {
         let s = format!("{}", date.format(DATE_FORMAT));
         serializer.serialize_str(&s)
     }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        NaiveDate::parse_from_str(s.as_str(), DATE_FORMAT).map_err(de::Error::custom)
+    }
+}
+
+pub mod optional_naive_date {
+    use super::naive_date::DATE_FORMAT;
+    use chrono::NaiveDate;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(date: &Option<NaiveDate>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match date {
+            Some(date) => serializer.serialize_str(&format!("{}", date.format(DATE_FORMAT))),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<NaiveDate>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(s) => NaiveDate::parse_from_str(s.as_str(), DATE_FORMAT)
+                .map(Some)
+                .map_err(de::Error::custom),
+            None => Ok(None),
+        }
+    }
 }
@@ -1,15 +1,43 @@
+use crate::auth::DEFAULT_ROLES_CLAIM;
+use crate::database::EntityType;
 use config::{Config, ConfigError, Environment, File};
+use std::collections::HashMap;
 use url::Url;
 use url_serde;
 use serde::{Deserialize};
 
+/// Storage of the database
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// MySQL or MariaDB server
+    Mysql,
+    /// SQLite file, `database` is its path
+    Sqlite,
+}
+
+impl Default for Backend {
+    fn default() -> Backend {
+        Backend::Mysql
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Database {
+    #[serde(default)]
+    pub backend: Backend,
     pub hostname: Option<String>, //default 127.0.0.1 by mysql
     pub port: Option<u16>,
     pub username: Option<String>, //default None by mysql
     pub password: Option<String>, //default None by mysql
     pub database: String,
+    /// Apply pending migrations on start, otherwise they are applied with `liberation migrate`
+    #[serde(default = "default_auto_migrate")]
+    pub automigrate: bool,
+}
+
+fn default_auto_migrate() -> bool {
+    true
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -19,6 +47,97 @@ pub struct Keycloak {
     pub realm: String,
     pub clientid: String,
     pub clientsecret: String,
+    /// Seconds between two synchronisations of users and roles
+    #[serde(default = "default_sync_interval")]
+    pub syncinterval: u64,
+    /// Longest wait in seconds between retries after failed synchronisations
+    #[serde(default = "default_max_backoff")]
+    pub maxbackoff: u64,
+    /// Users requested per page from the admin api, at least 1
+    #[serde(default = "default_page_size")]
+    pub pagesize: u32,
+    /// Only fetch users changed according to the admin events between full synchronisations.
+    /// Saving admin events has to be enabled in the realm.
+    #[serde(default)]
+    pub adminevents: bool,
+    /// Seconds between two full synchronisations if admin events are used
+    #[serde(default = "default_full_sync_interval")]
+    pub fullsyncinterval: u64,
+}
+
+fn default_sync_interval() -> u64 {
+    5
+}
+
+fn default_max_backoff() -> u64 {
+    300
+}
+
+fn default_page_size() -> u32 {
+    100
+}
+
+fn default_full_sync_interval() -> u64 {
+    3600
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Oidc {
+    /// Expected `iss` of access tokens, the provider is discovered below it
+    pub issuer: String,
+    /// Expected `aud` of access tokens
+    pub audience: String,
+    /// Validate opaque access tokens at the introspection endpoint of the provider,
+    /// authenticated with the client credentials of the `[keycloak]` section
+    #[serde(default)]
+    pub introspection: bool,
+    /// Accept introspected tokens without `aud`, for providers not reporting the audience
+    #[serde(default)]
+    pub optionalaudience: bool,
+    /// Claim holding the realm roles of the user, nested claims are separated by dots
+    #[serde(default = "default_roles_claim")]
+    pub rolesclaim: String,
+}
+
+fn default_roles_claim() -> String {
+    String::from(DEFAULT_ROLES_CLAIM)
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LendingPolicy {
+    /// Longest a rental may run in total, counted from the day of checkout
+    pub max_loan_days: u32,
+    /// How often a rental may be extended
+    pub max_extensions: u32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Lending {
+    /// Days a returned copy is held for the first reservation of its title
+    pub hold_days: u32,
+    /// Rules for books owned by members
+    pub member: LendingPolicy,
+    /// Rules for books owned by guilds
+    pub guild: LendingPolicy,
+}
+
+impl Lending {
+    /// Gets the lending policy for books of the given owner type
+    pub fn policy_for(&self, owner_type: &EntityType) -> &LendingPolicy {
+        match owner_type {
+            EntityType::Member => &self.member,
+            EntityType::Guild => &self.guild,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Feature {
+    /// Whether the feature is switched on
+    pub enabled: bool,
+    /// Only available to users with the developer role
+    #[serde(default)]
+    pub developer: bool,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -27,7 +146,13 @@ pub struct Settings {
     pub serve_static_files: bool,
     pub port: u16,
     pub database: Database,
-    pub keycloak: Keycloak,
+    pub oidc: Oidc,
+    /// Admin api for member names and roles, optional
+    pub keycloak: Option<Keycloak>,
+    pub lending: Lending,
+    /// Feature flags by name
+    #[serde(default)]
+    pub features: HashMap<String, Feature>,
 }
 
 impl Settings {
@@ -45,7 +170,19 @@ impl Settings {
         s.merge(Environment::with_prefix("LIBERATION").separator("_"))
             .unwrap();
 
-        s.try_into()
+        let settings: Settings = s.try_into()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Rejects values the types allow but which can not work
+    fn validate(&self) -> Result<(), ConfigError> {
+        match &self.keycloak {
+            Some(keycloak) if keycloak.pagesize == 0 => Err(ConfigError::Message(String::from(
+                "keycloak.pagesize must be at least 1",
+            ))),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -73,6 +210,8 @@ impl TestSettings {
             .unwrap();
 
         s.set("database.database", "")?;
+        // Tests run on temporary SQLite files unless a server is configured
+        s.set_default("database.backend", "sqlite")?;
 
         s.merge(Environment::with_prefix("LIBERATION").separator("_"))
             .unwrap();